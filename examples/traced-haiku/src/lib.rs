@@ -0,0 +1,42 @@
+use pharia_skill::{skill, ChatRequest, Csi, Message, RunTrace};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+#[derive(Debug, JsonSchema, Serialize)]
+struct Output {
+    haiku: String,
+}
+
+/// Writes a haiku about a topic. Every run is traced, and the trace is logged to the Kernel.
+#[skill(trace)]
+fn haiku(csi: &impl Csi, topic: &str) -> Output {
+    // Own sub-steps show up in the trace next to the CSI calls.
+    let prompt = RunTrace::current().in_step("prompt", |span| {
+        let prompt = format!("Write a haiku about {topic}");
+        span.set_output(&prompt);
+        prompt
+    });
+
+    let request = ChatRequest::new("llama-3.1-8b-instruct", Message::user(prompt));
+    let result = csi.chat(request);
+
+    Output {
+        haiku: result.message.content,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pharia_skill_test::MockCsi;
+
+    use super::*;
+
+    #[test]
+    fn haiku_is_answer_of_model() {
+        let csi = MockCsi::new("Leaves drift on the stream");
+
+        let output = haiku(&csi, "autumn");
+
+        assert_eq!(output.haiku, "Leaves drift on the stream");
+    }
+}
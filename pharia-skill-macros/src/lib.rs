@@ -1,8 +1,8 @@
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::{
-    spanned::Spanned, AttrStyle, Expr, FnArg, GenericArgument, ItemFn, Lit, PathArguments,
-    ReturnType, Type,
+    spanned::Spanned, AttrStyle, Expr, FnArg, GenericArgument, ItemFn, Lit, Pat, PathArguments,
+    ReturnType, Type, TypeParamBound,
 };
 
 fn report_error(msg: &str, span: proc_macro2::Span) -> TokenStream {
@@ -11,6 +11,8 @@ fn report_error(msg: &str, span: proc_macro2::Span) -> TokenStream {
 
 const ARG_MSG: &str = "The skill function should take two arguments: first is `csi: &impl Csi`, second is `input` with a type that implements `serde::Deserialize` and `schemars::JsonSchema`.";
 const RETURN_MSG: &str = "The skill function should return a value that implements `serde::Serialize` and `schemars::JsonSchema`.";
+const ATTR_MSG: &str = "The skill attribute only accepts `trace`, e.g. `#[skill(trace)]`.";
+const TOOL_ARG_MSG: &str = "The tool function arguments should be plain identifiers with a type that implements `serde::de::DeserializeOwned` and `schemars::JsonSchema`.";
 
 /// Macro to define a Skill. It wraps a function that takes a single argument and returns a single value.
 ///
@@ -19,8 +21,25 @@ const RETURN_MSG: &str = "The skill function should return a value that implemen
 /// You can use the `#[derive(schemars::JsonSchema)]` attribute to automatically implement `JsonSchema` for your types.
 ///
 /// Also, the doc comment can be used to provide a description of the skill.
+///
+/// A skill can also stream its output by returning an `impl Iterator<Item = T>` (optionally wrapped
+/// in an `anyhow::Result`). Each item is written to the output stream as soon as it is produced,
+/// so `T` needs to implement `serde::Serialize` and `schemars::JsonSchema`.
+///
+/// With `#[skill(trace)]`, every run is recorded by a `pharia_skill::RunTrace`: the run itself, all
+/// CSI calls with their requests and responses, and any sub-steps the skill opens on
+/// `RunTrace::current()`. The resulting tree of steps is logged to the Kernel at the end of the run.
 #[proc_macro_attribute]
-pub fn skill(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn skill(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let trace = if attr.is_empty() {
+        false
+    } else {
+        let attr = syn::parse_macro_input!(attr as syn::Ident);
+        if attr != "trace" {
+            return report_error(ATTR_MSG, attr.span());
+        }
+        true
+    };
     let func = syn::parse_macro_input!(item as syn::ItemFn);
     let func_name = &func.sig.ident;
     let description = extract_doc_comment(&func);
@@ -36,6 +55,29 @@ pub fn skill(_attr: TokenStream, item: TokenStream) -> TokenStream {
         ReturnType::Type(_, ty) => ty,
         ReturnType::Default => return report_error(RETURN_MSG, func.span()),
     });
+    let item_type = extract_iterator_item(output_type);
+
+    let (output_schema, output_item_schema, handler_output) = match item_type {
+        Some(item_type) => (
+            quote!(json::schema_for!(Vec<#item_type>)),
+            quote!(Some(
+                json::to_vec(&json::schema_for!(#item_type))
+                    .expect("Failed to serialize output item schema")
+            )),
+            quote!(HandlerStream::from(output)),
+        ),
+        None => (
+            quote!(json::schema_for!(#output_type)),
+            quote!(None),
+            quote!(HandlerResult::from(output)),
+        ),
+    };
+    let collected_output = match item_type {
+        Some(_) => quote!(#handler_output.collect()),
+        None => handler_output.clone(),
+    };
+
+    let (run, run_streaming) = run_bodies(trace, func_name, &collected_output, &handler_output);
 
     quote!(
         #func
@@ -43,7 +85,7 @@ pub fn skill(_attr: TokenStream, item: TokenStream) -> TokenStream {
         static __SKILL_METADATA: std::sync::LazyLock<::pharia_skill::bindings::exports::pharia::skill::skill_handler::SkillMetadata> = std::sync::LazyLock::new(|| {
             use ::pharia_skill::bindings::{exports::pharia::skill::skill_handler::SkillMetadata, json};
             let input_schema = json::schema_for!(#input_type);
-            let output_schema = json::schema_for!(#output_type);
+            let output_schema = #output_schema;
             SkillMetadata {
                 description: (!#description.is_empty()).then_some(#description.to_string()),
                 input_schema: json::to_vec(&input_schema).expect("Failed to serialize input schema"),
@@ -54,17 +96,21 @@ pub fn skill(_attr: TokenStream, item: TokenStream) -> TokenStream {
         mod __pharia_skill {
             use ::pharia_skill::bindings::{
                 export,
-                exports::pharia::skill::skill_handler::{Error, Guest, SkillMetadata},
-                json, HandlerResult, WitCsi,
+                exports::pharia::skill::{
+                    skill_handler::{Error, Guest, SkillMetadata},
+                    streaming_skill_handler,
+                },
+                json,
+                pharia::skill::streaming_output::OutputStream,
+                HandlerResult, HandlerStream,
             };
 
             pub struct Skill;
 
             impl Guest for Skill {
-                fn run(input: Vec<u8>) -> Result<Vec<u8>, Error> {
-                    let input = json::from_slice(&input)?;
-                    let output = super::#func_name(&WitCsi, input);
-                    HandlerResult::from(output).into()
+                fn run(raw_input: Vec<u8>) -> Result<Vec<u8>, Error> {
+                    let input = json::from_slice(&raw_input)?;
+                    #run
                 }
 
                 fn metadata() -> SkillMetadata {
@@ -72,6 +118,17 @@ pub fn skill(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 }
             }
 
+            impl streaming_skill_handler::Guest for Skill {
+                fn run(raw_input: Vec<u8>, output_stream: &OutputStream) -> Result<(), streaming_skill_handler::Error> {
+                    let input = json::from_slice(&raw_input)?;
+                    #run_streaming
+                }
+
+                fn output_item_schema() -> Option<Vec<u8>> {
+                    #output_item_schema
+                }
+            }
+
             export!(Skill);
         }
 
@@ -79,6 +136,127 @@ pub fn skill(_attr: TokenStream, item: TokenStream) -> TokenStream {
     .into()
 }
 
+/// The bodies of the blocking and the streaming handler, which run the skill function on the
+/// `input`. Traced skills run within a `RunTrace`.
+fn run_bodies(
+    trace: bool,
+    func_name: &syn::Ident,
+    collected_output: &proc_macro2::TokenStream,
+    handler_output: &proc_macro2::TokenStream,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    if trace {
+        (
+            quote!(::pharia_skill::bindings::run_traced(stringify!(#func_name), &raw_input, input, |csi, input| {
+                let output = super::#func_name(csi, input);
+                #collected_output.into()
+            })),
+            quote!(::pharia_skill::bindings::run_traced(stringify!(#func_name), &raw_input, input, |csi, input| {
+                let output = super::#func_name(csi, input);
+                #handler_output.write_recorded(output_stream)
+            }).into()),
+        )
+    } else {
+        (
+            quote!({
+                let csi = ::pharia_skill::bindings::skill_csi();
+                let output = super::#func_name(&csi, input);
+                #collected_output.into()
+            }),
+            quote!({
+                let csi = ::pharia_skill::bindings::skill_csi();
+                let output = super::#func_name(&csi, input);
+                #handler_output.write_to(output_stream)
+            }),
+        )
+    }
+}
+
+/// Macro to define a Tool from a plain function, so that it can be called by an `Agent`.
+///
+/// It generates a unit struct named after the function in `PascalCase`, which implements `pharia_skill::Tool`.
+/// Each argument of the function becomes a property of the JSON object the model calls the tool with,
+/// so the argument types need to implement `serde::de::DeserializeOwned` and `schemars::JsonSchema`.
+/// Arguments of type `Option` are not required.
+/// The return value should implement `serde::Serialize`, and can also be wrapped in an `anyhow::Result`.
+///
+/// The doc comment is used to describe the tool to the model.
+#[proc_macro_attribute]
+pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let func = syn::parse_macro_input!(item as syn::ItemFn);
+    let func_name = &func.sig.ident;
+    let visibility = &func.vis;
+    let tool_name = func_name.to_string();
+    let struct_name = format_ident!("{}", to_pascal_case(&tool_name));
+    let struct_doc = format!("Tool which calls [`{tool_name}`].");
+    let description = extract_doc_comment(&func);
+
+    let mut names = Vec::new();
+    let mut types = Vec::new();
+    let mut required = Vec::new();
+    for arg in &func.sig.inputs {
+        let FnArg::Typed(pat_type) = arg else {
+            return report_error(TOOL_ARG_MSG, arg.span());
+        };
+        let Pat::Ident(pat_ident) = pat_type.pat.as_ref() else {
+            return report_error(TOOL_ARG_MSG, arg.span());
+        };
+        names.push(pat_ident.ident.to_string());
+        types.push(&pat_type.ty);
+        required.push(!is_option(&pat_type.ty));
+    }
+
+    quote!(
+        #func
+
+        #[doc = #struct_doc]
+        #visibility struct #struct_name;
+
+        impl ::pharia_skill::Tool for #struct_name {
+            fn definition(&self) -> ::pharia_skill::ToolDefinition {
+                let mut parameters = ::pharia_skill::bindings::tool::Parameters::new();
+                #(parameters.add::<#types>(#names, #required);)*
+                let definition = ::pharia_skill::ToolDefinition::new(#tool_name, parameters.into_schema());
+                if #description.is_empty() {
+                    definition
+                } else {
+                    definition.with_description(#description)
+                }
+            }
+
+            fn invoke(&self, arguments: &str) -> ::core::result::Result<String, ::pharia_skill::bindings::tool::Error> {
+                #[allow(unused_mut)]
+                let mut arguments = ::pharia_skill::bindings::tool::Arguments::parse(arguments)?;
+                let output = #func_name(#(arguments.take::<#types>(#names)?),*);
+                ::pharia_skill::bindings::tool::ToolOutput::from(output).into()
+            }
+        }
+    )
+    .into()
+}
+
+fn to_pascal_case(name: &str) -> String {
+    name.split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Option"),
+        _ => false,
+    }
+}
+
 // Pull out the type from a Result type if the user used one.
 fn extract_output_result(output_type: &Type) -> &Type {
     match output_type {
@@ -105,6 +283,31 @@ fn extract_output_result(output_type: &Type) -> &Type {
     .unwrap_or(output_type)
 }
 
+// Pull out the item type if the skill returns an `impl Iterator<Item = T>`.
+fn extract_iterator_item(output_type: &Type) -> Option<&Type> {
+    let Type::ImplTrait(impl_trait) = output_type else {
+        return None;
+    };
+    impl_trait.bounds.iter().find_map(|bound| match bound {
+        TypeParamBound::Trait(bound) => bound
+            .path
+            .segments
+            .last()
+            .filter(|segment| segment.ident == "Iterator")
+            .and_then(|segment| match &segment.arguments {
+                PathArguments::AngleBracketed(args) => Some(args),
+                PathArguments::Parenthesized(_) | PathArguments::None => None,
+            })
+            .and_then(|args| {
+                args.args.iter().find_map(|arg| match arg {
+                    GenericArgument::AssocType(assoc) if assoc.ident == "Item" => Some(&assoc.ty),
+                    _ => None,
+                })
+            }),
+        _ => None,
+    })
+}
+
 fn extract_doc_comment(func: &ItemFn) -> String {
     func.attrs
         .iter()
@@ -0,0 +1,385 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use pharia_skill::{
+    ChatEvent, ChatRequest, ChatResponse, Chunk, ChunkRequest, Completion, CompletionEvent,
+    CompletionRequest, Csi, CsiError, DetokenizeRequest, Document, DocumentPath, Embedding,
+    EmbeddingRequest, LanguageCode, LogRecord, SearchRequest, SearchResult, SelectLanguageRequest,
+    TokenizeRequest,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{deserialize_metadata, Function};
+
+/// One request to a CSI function and its outcome.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Interaction {
+    function: Function,
+    request: Value,
+    /// The response, or for streams the events received before the stream ended or failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<CsiError>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+/// Records every request to the wrapped [`Csi`] with its response to a JSON cassette, so tests
+/// can later run offline against a [`ReplayCsi`].
+///
+/// Each request of a concurrent call is recorded on its own, streams are recorded once they
+/// ended. The cassette is written after every interaction.
+///
+/// ```no_run
+/// use pharia_skill_test::{DevCsi, RecordingCsi};
+///
+/// let csi = RecordingCsi::new(DevCsi::aleph_alpha("token"), "tests/cassettes/haiku.json").unwrap();
+/// // run the skill with `csi` ...
+/// ```
+pub struct RecordingCsi<C> {
+    inner: C,
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+}
+
+impl<C: Csi> RecordingCsi<C> {
+    /// Record into a cassette at `path`, replacing an existing one.
+    ///
+    /// # Errors
+    /// Will return an error if the cassette cannot be written.
+    pub fn new(inner: C, path: impl Into<PathBuf>) -> io::Result<Self> {
+        let csi = Self {
+            inner,
+            path: path.into(),
+            cassette: Mutex::new(Cassette::default()),
+        };
+        csi.save(&Cassette::default())?;
+        Ok(csi)
+    }
+
+    fn save(&self, cassette: &Cassette) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(cassette)?)
+    }
+
+    /// # Panics
+    /// Panics if the cassette cannot be written, as a test recording only part of its
+    /// interactions would fail confusingly when replayed.
+    fn record(&self, interactions: impl IntoIterator<Item = Interaction>) {
+        let mut cassette = self.cassette.lock().unwrap();
+        cassette.interactions.extend(interactions);
+        if let Err(error) = self.save(&cassette) {
+            panic!("Failed to write cassette {}: {error}", self.path.display());
+        }
+    }
+
+    /// Send the requests and record each of them, with its response or the error of the call.
+    fn recorded<R: Serialize, T: Serialize>(
+        &self,
+        function: Function,
+        requests: Vec<R>,
+        send: impl FnOnce(Vec<R>) -> Result<Vec<T>, CsiError>,
+    ) -> Result<Vec<T>, CsiError> {
+        let recorded_requests = requests.iter().map(to_value).collect::<Vec<_>>();
+        let result = send(requests);
+        let outcomes = match &result {
+            Ok(responses) => responses
+                .iter()
+                .map(|response| (Some(to_value(response)), None))
+                .collect(),
+            Err(error) => vec![(None, Some(error.clone())); recorded_requests.len()],
+        };
+        self.record(recorded_requests.into_iter().zip(outcomes).map(
+            |(request, (response, error))| Interaction {
+                function,
+                request,
+                response,
+                error,
+            },
+        ));
+        result
+    }
+
+    /// Pass the stream on, and record it with its events once it ended or failed.
+    fn recorded_stream<'a, E: Serialize + 'a>(
+        &'a self,
+        function: Function,
+        request: Value,
+        mut stream: impl Iterator<Item = Result<E, CsiError>> + 'a,
+    ) -> impl Iterator<Item = Result<E, CsiError>> + 'a {
+        let mut events = Vec::new();
+        let mut recorded = false;
+        std::iter::from_fn(move || {
+            let event = stream.next();
+            if let Some(Ok(event)) = &event {
+                events.push(to_value(event));
+            } else if !recorded {
+                recorded = true;
+                let error = event
+                    .as_ref()
+                    .and_then(|event| event.as_ref().err().cloned());
+                self.record([Interaction {
+                    function,
+                    request: request.clone(),
+                    response: Some(Value::Array(std::mem::take(&mut events))),
+                    error,
+                }]);
+            }
+            event
+        })
+    }
+}
+
+/// Serialize a request or response for the cassette.
+///
+/// # Panics
+/// Panics for values which cannot be represented as JSON, which none of the CSI types are.
+fn to_value(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).expect("CSI types can be represented as JSON")
+}
+
+macro_rules! record {
+    ($($method:ident: $function:ident, $request:ty => $response:ty;)*) => {
+        $(fn $method(&self, requests: Vec<$request>) -> Result<Vec<$response>, CsiError> {
+            self.recorded(Function::$function, requests, |requests| self.inner.$method(requests))
+        })*
+    };
+}
+
+impl<C: Csi> Csi for RecordingCsi<C> {
+    record! {
+        try_chunk_concurrently: Chunk, ChunkRequest => Vec<String>;
+        try_chunk_with_offsets_concurrently: ChunkWithOffsets, ChunkRequest => Vec<Chunk>;
+        try_search_concurrently: Search, SearchRequest => Vec<SearchResult>;
+        try_chat_concurrently: Chat, ChatRequest => ChatResponse;
+        try_complete_concurrently: Complete, CompletionRequest => Completion;
+        try_embed_concurrently: Embed, EmbeddingRequest => Vec<Embedding>;
+        try_tokenize_concurrently: Tokenize, TokenizeRequest => Vec<u32>;
+        try_detokenize_concurrently: Detokenize, DetokenizeRequest => String;
+        try_count_tokens_concurrently: CountTokens, TokenizeRequest => u32;
+        try_select_language_concurrently: SelectLanguage, SelectLanguageRequest => Option<LanguageCode>;
+    }
+
+    /// Documents are recorded with their raw metadata, so the cassette can be replayed with any
+    /// metadata type.
+    fn try_documents<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        self.recorded(Function::Documents, paths, |paths| {
+            self.inner.try_documents::<Value>(paths)
+        })?
+        .into_iter()
+        .map(deserialize_metadata)
+        .collect()
+    }
+
+    fn try_documents_metadata<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        self.recorded(Function::DocumentMetadata, paths, |paths| {
+            self.inner.try_documents_metadata::<Value>(paths)
+        })?
+        .into_iter()
+        .map(|metadata| Ok(metadata.map(serde_json::from_value).transpose()?))
+        .collect()
+    }
+
+    fn try_chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+        let recorded = to_value(&request);
+        self.recorded_stream(
+            Function::ChatStream,
+            recorded,
+            self.inner.try_chat_stream(request),
+        )
+    }
+
+    fn try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+        let recorded = to_value(&request);
+        self.recorded_stream(
+            Function::CompletionStream,
+            recorded,
+            self.inner.try_completion_stream(request),
+        )
+    }
+
+    fn log(&self, record: LogRecord) {
+        self.inner.log(record);
+    }
+}
+
+/// Serves the responses of a cassette recorded by a [`RecordingCsi`], so tests which were
+/// recorded against the Kernel or another [`Csi`] run offline.
+///
+/// A request is answered by the first interaction of the same function with an equal request
+/// which has not been replayed yet. Once all have been, the last of them is replayed again.
+///
+/// # Panics
+/// Every function panics on requests which are not in the cassette, so tests fail loudly instead
+/// of the skill handling a made up error.
+pub struct ReplayCsi {
+    interactions: Vec<Interaction>,
+    replayed: Mutex<Vec<bool>>,
+}
+
+impl ReplayCsi {
+    /// Load the cassette at `path`.
+    ///
+    /// # Errors
+    /// Will return an error if the file cannot be read or is not a cassette.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = BufReader::new(File::open(path)?);
+        let Cassette { interactions } = serde_json::from_reader(file)?;
+        Ok(Self {
+            replayed: Mutex::new(vec![false; interactions.len()]),
+            interactions,
+        })
+    }
+
+    fn interaction(&self, function: Function, request: &impl Serialize) -> &Interaction {
+        let request = to_value(request);
+        let mut replayed = self.replayed.lock().unwrap();
+        let matching = self
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, interaction)| {
+                interaction.function == function && interaction.request == request
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let index = matching
+            .iter()
+            .find(|index| !replayed[**index])
+            .or(matching.last())
+            .copied()
+            .unwrap_or_else(|| {
+                panic!("No interaction recorded for {function:?} with request {request}")
+            });
+        replayed[index] = true;
+        &self.interactions[index]
+    }
+
+    fn replayed<R: Serialize, T: DeserializeOwned>(
+        &self,
+        function: Function,
+        requests: &[R],
+    ) -> Result<Vec<T>, CsiError> {
+        requests
+            .iter()
+            .map(|request| {
+                let interaction = self.interaction(function, request);
+                if let Some(error) = &interaction.error {
+                    return Err(error.clone());
+                }
+                let response = interaction.response.clone().unwrap_or_default();
+                Ok(serde_json::from_value(response)?)
+            })
+            .collect()
+    }
+
+    /// The events of the stream, ending with its error if it failed.
+    fn replayed_stream<E: DeserializeOwned>(
+        &self,
+        function: Function,
+        request: &impl Serialize,
+    ) -> Vec<Result<E, CsiError>> {
+        let interaction = self.interaction(function, request);
+        let events = interaction
+            .response
+            .clone()
+            .map(serde_json::from_value::<Vec<E>>)
+            .transpose();
+        let (events, error) = match events {
+            Ok(events) => (events.unwrap_or_default(), interaction.error.clone()),
+            Err(error) => (vec![], Some(error.into())),
+        };
+        events.into_iter().map(Ok).chain(error.map(Err)).collect()
+    }
+}
+
+macro_rules! replay {
+    ($($method:ident: $function:ident, $request:ty => $response:ty;)*) => {
+        $(fn $method(&self, requests: Vec<$request>) -> Result<Vec<$response>, CsiError> {
+            self.replayed(Function::$function, &requests)
+        })*
+    };
+}
+
+impl Csi for ReplayCsi {
+    replay! {
+        try_chunk_concurrently: Chunk, ChunkRequest => Vec<String>;
+        try_chunk_with_offsets_concurrently: ChunkWithOffsets, ChunkRequest => Vec<Chunk>;
+        try_search_concurrently: Search, SearchRequest => Vec<SearchResult>;
+        try_chat_concurrently: Chat, ChatRequest => ChatResponse;
+        try_complete_concurrently: Complete, CompletionRequest => Completion;
+        try_embed_concurrently: Embed, EmbeddingRequest => Vec<Embedding>;
+        try_tokenize_concurrently: Tokenize, TokenizeRequest => Vec<u32>;
+        try_detokenize_concurrently: Detokenize, DetokenizeRequest => String;
+        try_count_tokens_concurrently: CountTokens, TokenizeRequest => u32;
+        try_select_language_concurrently: SelectLanguage, SelectLanguageRequest => Option<LanguageCode>;
+    }
+
+    fn try_documents<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        self.replayed::<_, Document>(Function::Documents, &paths)?
+            .into_iter()
+            .map(deserialize_metadata)
+            .collect()
+    }
+
+    fn try_documents_metadata<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        self.replayed(Function::DocumentMetadata, &paths)
+    }
+
+    fn try_chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+        self.replayed_stream(Function::ChatStream, &request)
+            .into_iter()
+    }
+
+    fn try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+        self.replayed_stream(Function::CompletionStream, &request)
+            .into_iter()
+    }
+}
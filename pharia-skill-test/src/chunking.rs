@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    ops::Range,
+    path::Path,
+    sync::{Arc, LazyLock},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pharia_skill::{Chunk, ChunkRequest, Csi, CsiError, CsiLayer};
+use regex::Regex;
+
+/// The pre-tokenization pattern of Llama 3, without its look-ahead which `regex` lacks.
+static WORDS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+",
+    )
+    .expect("pattern is valid")
+});
+
+/// Splits texts into tokens, for [`LocalChunker`] to count them.
+pub trait Tokenizer: Send + Sync {
+    /// The byte ranges of the tokens of the text, in order. Ranges must start and end at char
+    /// boundaries, but may leave out text between tokens, e.g. whitespace.
+    fn tokenize(&self, text: &str) -> Vec<Range<usize>>;
+}
+
+/// Counts every run of non-whitespace characters as one token.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Range<usize>> {
+        let mut tokens = Vec::new();
+        let mut word_start = None;
+        for (index, c) in text.char_indices() {
+            match (word_start, c.is_whitespace()) {
+                (None, false) => word_start = Some(index),
+                (Some(start), true) => {
+                    tokens.push(start..index);
+                    word_start = None;
+                }
+                _ => {}
+            }
+        }
+        tokens.extend(word_start.map(|start| start..text.len()));
+        tokens
+    }
+}
+
+/// Splits words the way the models of the Kernel do, into the byte pair encoded tokens of a
+/// vocabulary.
+///
+/// Words are found with the pre-tokenization pattern of the Llama 3 tokenizer, minus the
+/// look-ahead which keeps the last space of a run of whitespace for the following word. Counts may
+/// hence be off by a token now and then. Tokens which end within a multi-byte character are
+/// counted together with the next one.
+pub struct BpeTokenizer {
+    ranks: HashMap<Vec<u8>, u32>,
+}
+
+impl BpeTokenizer {
+    /// Load a vocabulary in the format of `tiktoken`, as shipped with Llama 3 as
+    /// `tokenizer.model`: one base64 encoded token and its rank per line.
+    ///
+    /// # Errors
+    /// Will return an error if the file can not be read, or a line is not a token and rank.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid vocabulary entry: {line}"),
+            )
+        };
+        let ranks = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let (token, rank) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+                let token = STANDARD.decode(token).map_err(|_| invalid(line))?;
+                let rank = rank.trim().parse().map_err(|_| invalid(line))?;
+                Ok((token, rank))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { ranks })
+    }
+
+    /// Boundaries of the tokens of a word, found by merging the adjacent parts with the lowest
+    /// rank until no merged part is in the vocabulary.
+    fn merge(&self, word: &[u8]) -> Vec<usize> {
+        let mut boundaries = (0..=word.len()).collect::<Vec<_>>();
+        loop {
+            let lowest = boundaries
+                .windows(3)
+                .enumerate()
+                .filter_map(|(index, parts)| {
+                    let rank = self.ranks.get(&word[parts[0]..parts[2]])?;
+                    Some((rank, index))
+                })
+                .min();
+            match lowest {
+                Some((_, index)) => {
+                    boundaries.remove(index + 1);
+                }
+                None => return boundaries,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for BpeTokenizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BpeTokenizer")
+            .field("vocabulary_size", &self.ranks.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Range<usize>> {
+        let mut tokens = Vec::new();
+        for word in WORDS.find_iter(text) {
+            let mut token_start = word.start();
+            for end in self.merge(word.as_str().as_bytes()).into_iter().skip(1) {
+                let end = word.start() + end;
+                if text.is_char_boundary(end) {
+                    tokens.push(token_start..end);
+                    token_start = end;
+                }
+            }
+        }
+        tokens
+    }
+}
+
+/// Chunks texts locally, so skills relying on multiple chunks can be tested without a Kernel.
+///
+/// Chunks hold up to `max_tokens` tokens of the [`Tokenizer`] and the last `overlap` tokens of a
+/// chunk are repeated at the start of the next one, like the Kernel does. The whitespace between
+/// chunks is not part of either. As a [`CsiLayer`], it answers chunk requests and passes all other
+/// requests on to the wrapped [`Csi`]:
+///
+/// ```no_run
+/// use pharia_skill::{ChunkParams, ChunkRequest, Csi, CsiExt};
+/// use pharia_skill_test::{BpeTokenizer, LocalChunker, StubCsi};
+///
+/// let tokenizer = BpeTokenizer::load("tokenizer.model").unwrap();
+/// let csi = StubCsi.layer(LocalChunker::new(tokenizer));
+///
+/// let chunks = csi.chunk(ChunkRequest::new(
+///     "A long text ...",
+///     ChunkParams::new("llama-3.1-8b-instruct", 128).with_overlap(16),
+/// ));
+/// ```
+///
+/// [`StubCsi`](crate::StubCsi) and [`MockCsi`](crate::MockCsi) chunk with a
+/// [`WhitespaceTokenizer`] by default.
+#[derive(Clone)]
+pub struct LocalChunker {
+    tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl LocalChunker {
+    #[must_use]
+    pub fn new(tokenizer: impl Tokenizer + 'static) -> Self {
+        Self {
+            tokenizer: Arc::new(tokenizer),
+        }
+    }
+
+    /// The chunks of the text of the request, with their positions and number of tokens.
+    ///
+    /// # Errors
+    /// Will return [`CsiError::InvalidParameters`] if the overlap is not less than `max_tokens`.
+    pub fn chunk(&self, request: &ChunkRequest) -> Result<Vec<Chunk>, CsiError> {
+        let ChunkRequest { text, params } = request;
+        if params.overlap >= params.max_tokens {
+            return Err(CsiError::InvalidParameters(format!(
+                "overlap ({}) must be less than max_tokens ({})",
+                params.overlap, params.max_tokens
+            )));
+        }
+        let tokens = self.tokenizer.tokenize(text);
+        let max_tokens = params.max_tokens as usize;
+        let step = max_tokens - params.overlap as usize;
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < tokens.len() {
+            let end = (start + max_tokens).min(tokens.len());
+            let byte_offset = tokens[start].start;
+            chunks.push(Chunk {
+                text: text[byte_offset..tokens[end - 1].end].to_owned(),
+                byte_offset: byte_offset as u64,
+                char_offset: text[..byte_offset].chars().count() as u64,
+                token_count: u32::try_from(end - start).unwrap_or(params.max_tokens),
+            });
+            if end == tokens.len() {
+                break;
+            }
+            start += step;
+        }
+        Ok(chunks)
+    }
+
+    /// Find the position of each of the chunks in the text, and count their tokens. Chunks are
+    /// expected in order, but may overlap.
+    pub(crate) fn locate(&self, text: &str, chunks: Vec<String>) -> Result<Vec<Chunk>, CsiError> {
+        let mut search_start = 0;
+        chunks
+            .into_iter()
+            .map(|chunk| {
+                let byte_offset = text[search_start..]
+                    .find(&chunk)
+                    .map(|position| search_start + position)
+                    .ok_or_else(|| CsiError::Decode(format!("Chunk not found in text: {chunk}")))?;
+                search_start = text[byte_offset..]
+                    .chars()
+                    .next()
+                    .map_or(text.len(), |c| byte_offset + c.len_utf8());
+                Ok(Chunk {
+                    byte_offset: byte_offset as u64,
+                    char_offset: text[..byte_offset].chars().count() as u64,
+                    token_count: u32::try_from(self.tokenizer.tokenize(&chunk).len())
+                        .unwrap_or(u32::MAX),
+                    text: chunk,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Default for LocalChunker {
+    fn default() -> Self {
+        Self::new(WhitespaceTokenizer)
+    }
+}
+
+impl fmt::Debug for LocalChunker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalChunker").finish_non_exhaustive()
+    }
+}
+
+impl CsiLayer for LocalChunker {
+    fn chunk<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        Ok(self
+            .chunk_with_offsets(inner, requests)?
+            .into_iter()
+            .map(|chunks| chunks.into_iter().map(|chunk| chunk.text).collect())
+            .collect())
+    }
+
+    fn chunk_with_offsets<C: Csi + ?Sized>(
+        &self,
+        _inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        requests.iter().map(|request| self.chunk(request)).collect()
+    }
+}
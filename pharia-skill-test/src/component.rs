@@ -0,0 +1,255 @@
+//! Run compiled skill components, with the CSI provided by any [`Csi`] implementation.
+
+mod batch;
+mod chunking;
+mod document_index;
+mod inference;
+mod language;
+mod logging;
+mod tokenization;
+
+use std::{fmt, path::Path};
+
+use anyhow::Context;
+use pharia_skill::{Csi, CsiError};
+use serde_json::Value;
+use wasmtime::{
+    component::{Component, Linker, Resource, ResourceTable},
+    Engine, Store,
+};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiView};
+
+use bindings::{exports::pharia::skill::skill_handler, pharia::skill::streaming_output};
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        world: "skill",
+        path: "./src/wit",
+        trappable_imports: true,
+        with: {
+            "pharia:skill/inference/completion-stream": super::inference::CompletionStream,
+            "pharia:skill/inference/chat-stream": super::inference::ChatStream,
+            "pharia:skill/streaming-output/output-stream": super::OutputStream,
+        },
+    });
+}
+
+/// Runs a skill compiled to a `wasm32-wasip2` component, the way the Kernel does, so the code
+/// generated by the [`skill`](pharia_skill::skill) macro is tested end to end.
+///
+/// Requests of the skill to the CSI are answered by the wrapped [`Csi`], e.g. a
+/// [`MockCsi`](crate::MockCsi). Errors of the CSI trap the skill, and are returned by
+/// [`ComponentRunner::run`].
+///
+/// ```no_run
+/// use pharia_skill_test::{ComponentRunner, MockCsi};
+/// use serde_json::json;
+///
+/// let mut runner = ComponentRunner::load(
+///     "target/wasm32-wasip2/release/hello_world.wasm",
+///     MockCsi::new("Hello, Homer"),
+/// )
+/// .unwrap();
+///
+/// let output = runner.run(&json!("Homer")).unwrap();
+/// assert_eq!(output, json!({ "message": "Hello, Homer" }));
+/// ```
+pub struct ComponentRunner<C> {
+    store: Store<HostState<C>>,
+    skill: bindings::Skill,
+}
+
+impl<C> ComponentRunner<C>
+where
+    C: Csi + Send + 'static,
+{
+    /// Compile the component at the path and instantiate it.
+    ///
+    /// # Errors
+    /// Will return an error if the file can not be read, is not a component or does not export
+    /// a skill.
+    pub fn load(path: impl AsRef<Path>, csi: C) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let engine = Engine::default();
+        let component = Component::from_file(&engine, path)
+            .with_context(|| format!("Failed to load component {}", path.display()))?;
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+        bindings::Skill::add_to_linker(&mut linker, |state: &mut HostState<C>| state)?;
+        let mut store = Store::new(&engine, HostState::new(csi));
+        let skill = bindings::Skill::instantiate(&mut store, &component, &linker)?;
+        Ok(Self { store, skill })
+    }
+
+    /// Run the skill with the input, and return its output.
+    ///
+    /// # Errors
+    /// Will return a [`SkillError`] if the skill reported an error, and any other error if it
+    /// trapped, e.g. because a request to the CSI failed.
+    pub fn run(&mut self, input: &Value) -> anyhow::Result<Value> {
+        let output = self
+            .skill
+            .pharia_skill_skill_handler()
+            .call_run(&mut self.store, &serde_json::to_vec(input)?)?
+            .map_err(SkillError::from)?;
+        Ok(serde_json::from_slice(&output)?)
+    }
+
+    /// Run the skill with the input as a streaming skill, and return the items it wrote to its
+    /// output stream.
+    ///
+    /// # Errors
+    /// Will return a [`SkillError`] if the skill reported an error, and any other error if it
+    /// trapped, e.g. because a request to the CSI failed.
+    pub fn run_stream(&mut self, input: &Value) -> anyhow::Result<Vec<Value>> {
+        let output = self.store.data_mut().table.push(OutputStream::default())?;
+        let borrowed = Resource::new_borrow(output.rep());
+        let result = self.skill.pharia_skill_streaming_skill_handler().call_run(
+            &mut self.store,
+            &serde_json::to_vec(input)?,
+            borrowed,
+        );
+        let OutputStream { items } = self.store.data_mut().table.delete(output)?;
+        result?.map_err(SkillError::from)?;
+        items
+            .iter()
+            .map(|item| Ok(serde_json::from_slice(item)?))
+            .collect()
+    }
+
+    /// The description and the JSON schemas of the input and output of the skill.
+    ///
+    /// # Errors
+    /// Will return an error if the skill trapped or its schemas are not valid JSON.
+    pub fn metadata(&mut self) -> anyhow::Result<SkillMetadata> {
+        let skill_handler::SkillMetadata {
+            description,
+            input_schema,
+            output_schema,
+        } = self
+            .skill
+            .pharia_skill_skill_handler()
+            .call_metadata(&mut self.store)?;
+        let output_item_schema = self
+            .skill
+            .pharia_skill_streaming_skill_handler()
+            .call_output_item_schema(&mut self.store)?;
+        Ok(SkillMetadata {
+            description,
+            input_schema: serde_json::from_slice(&input_schema)?,
+            output_schema: serde_json::from_slice(&output_schema)?,
+            output_item_schema: output_item_schema
+                .map(|schema| serde_json::from_slice(&schema))
+                .transpose()?,
+        })
+    }
+
+    /// The CSI answering the requests of the skill, e.g. to verify a [`MockCsi`](crate::MockCsi)
+    /// after a run.
+    #[must_use]
+    pub fn csi(&self) -> &C {
+        &self.store.data().csi
+    }
+}
+
+/// The metadata a skill component exports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkillMetadata {
+    pub description: Option<String>,
+    pub input_schema: Value,
+    /// For streaming skills, the schema of the list of all output items
+    pub output_schema: Value,
+    /// Only set for streaming skills, the schema of each output item
+    pub output_item_schema: Option<Value>,
+}
+
+/// An error a skill component reported for a run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkillError {
+    /// The skill failed while processing the input.
+    Internal(String),
+    /// The input could not be deserialized into the input of the skill.
+    InvalidInput(String),
+}
+
+impl fmt::Display for SkillError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Internal(message) => write!(f, "Internal error: {message}"),
+            Self::InvalidInput(message) => write!(f, "Invalid input: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SkillError {}
+
+impl From<skill_handler::Error> for SkillError {
+    fn from(error: skill_handler::Error) -> Self {
+        match error {
+            skill_handler::Error::Internal(message) => Self::Internal(message),
+            skill_handler::Error::InvalidInput(message) => Self::InvalidInput(message),
+        }
+    }
+}
+
+impl From<bindings::exports::pharia::skill::streaming_skill_handler::Error> for SkillError {
+    fn from(error: bindings::exports::pharia::skill::streaming_skill_handler::Error) -> Self {
+        use bindings::exports::pharia::skill::streaming_skill_handler::Error;
+        match error {
+            Error::Internal(message) => Self::Internal(message),
+            Error::InvalidInput(message) => Self::InvalidInput(message),
+        }
+    }
+}
+
+/// The data of the store a component runs in.
+struct HostState<C> {
+    csi: C,
+    wasi: WasiCtx,
+    table: ResourceTable,
+}
+
+impl<C> HostState<C> {
+    fn new(csi: C) -> Self {
+        Self {
+            csi,
+            wasi: WasiCtxBuilder::new().inherit_stdio().build(),
+            table: ResourceTable::new(),
+        }
+    }
+}
+
+impl<C: Send> WasiView for HostState<C> {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+/// Turn a failed request to the CSI into a trap of the skill.
+fn trap(error: CsiError) -> anyhow::Error {
+    anyhow::Error::new(error)
+}
+
+/// The items a streaming skill wrote to its output.
+#[derive(Default)]
+pub struct OutputStream {
+    items: Vec<Vec<u8>>,
+}
+
+impl<C> streaming_output::Host for HostState<C> {}
+
+impl<C> streaming_output::HostOutputStream for HostState<C> {
+    fn write(&mut self, output: Resource<OutputStream>, item: Vec<u8>) -> anyhow::Result<()> {
+        self.table.get_mut(&output)?.items.push(item);
+        Ok(())
+    }
+
+    fn drop(&mut self, _output: Resource<OutputStream>) -> anyhow::Result<()> {
+        // Owned by the runner, which removes it from the table after the run.
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use pharia_skill::{Csi, CsiError, CsiRequest, CsiResponse};
+
+use super::{bindings::pharia::skill::batch, document_index::encode_metadata, trap, HostState};
+
+impl TryFrom<batch::CsiRequest> for CsiRequest {
+    type Error = CsiError;
+
+    fn try_from(value: batch::CsiRequest) -> Result<Self, Self::Error> {
+        Ok(match value {
+            batch::CsiRequest::Chunk(request) => Self::Chunk(request.into()),
+            batch::CsiRequest::ChunkWithOffsets(request) => Self::ChunkWithOffsets(request.into()),
+            batch::CsiRequest::Search(request) => Self::Search(request.try_into()?),
+            batch::CsiRequest::Documents(path) => Self::Documents(path.into()),
+            batch::CsiRequest::DocumentMetadata(path) => Self::DocumentsMetadata(path.into()),
+            batch::CsiRequest::Chat(request) => Self::Chat(request.try_into()?),
+            batch::CsiRequest::ChatWithTools(request) => Self::Chat(request.try_into()?),
+            batch::CsiRequest::Complete(request) => Self::Complete(request.into()),
+            batch::CsiRequest::Embed(request) => Self::Embed(request.into()),
+            batch::CsiRequest::Tokenize(request) => Self::Tokenize(request.into()),
+            batch::CsiRequest::Detokenize(request) => Self::Detokenize(request.into()),
+            batch::CsiRequest::CountTokens(request) => Self::CountTokens(request.into()),
+            batch::CsiRequest::SelectLanguage(request) => Self::SelectLanguage(request.try_into()?),
+        })
+    }
+}
+
+impl TryFrom<CsiResponse> for batch::CsiResponse {
+    type Error = CsiError;
+
+    fn try_from(value: CsiResponse) -> Result<Self, Self::Error> {
+        Ok(match value {
+            CsiResponse::Chunk(chunks) => Self::Chunk(chunks),
+            CsiResponse::ChunkWithOffsets(chunks) => {
+                Self::ChunkWithOffsets(chunks.into_iter().map(Into::into).collect())
+            }
+            CsiResponse::Search(results) => {
+                Self::Search(results.into_iter().map(Into::into).collect())
+            }
+            CsiResponse::Documents(document) => Self::Documents(document.try_into()?),
+            CsiResponse::DocumentsMetadata(metadata) => {
+                Self::DocumentMetadata(metadata.as_ref().map(encode_metadata).transpose()?)
+            }
+            CsiResponse::Chat(response) => Self::Chat(response.into()),
+            CsiResponse::Complete(completion) => Self::Complete(completion.into()),
+            CsiResponse::Embed(embeddings) => Self::Embed(embeddings),
+            CsiResponse::Tokenize(tokens) => Self::Tokenize(tokens),
+            CsiResponse::Detokenize(text) => Self::Detokenize(text),
+            CsiResponse::CountTokens(count) => Self::CountTokens(count),
+            CsiResponse::SelectLanguage(language) => {
+                Self::SelectLanguage(language.map(|language| language.to_string()))
+            }
+        })
+    }
+}
+
+impl<C: Csi> batch::Host for HostState<C> {
+    fn batch(
+        &mut self,
+        requests: Vec<batch::CsiRequest>,
+    ) -> anyhow::Result<Vec<batch::CsiResponse>> {
+        // Both kinds of chat requests are answered by `Csi::try_chat`, but each with its own kind
+        // of response.
+        let with_tools = requests
+            .iter()
+            .map(|request| matches!(request, batch::CsiRequest::ChatWithTools(_)))
+            .collect::<Vec<_>>();
+        let requests = requests
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()
+            .map_err(trap)?;
+        let responses = self.csi.try_batch(requests).map_err(trap)?;
+        responses
+            .into_iter()
+            .zip(with_tools)
+            .map(|(response, with_tools)| match response {
+                CsiResponse::Chat(response) if with_tools => {
+                    Ok(batch::CsiResponse::ChatWithTools(response.into()))
+                }
+                response => response.try_into(),
+            })
+            .collect::<Result<_, _>>()
+            .map_err(trap)
+    }
+}
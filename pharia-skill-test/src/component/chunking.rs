@@ -0,0 +1,67 @@
+use pharia_skill::{Chunk, ChunkParams, ChunkRequest, Csi};
+
+use super::{bindings::pharia::skill::chunking, trap, HostState};
+
+impl From<chunking::ChunkParams> for ChunkParams {
+    fn from(value: chunking::ChunkParams) -> Self {
+        let chunking::ChunkParams {
+            model,
+            max_tokens,
+            overlap,
+        } = value;
+        Self {
+            model,
+            max_tokens,
+            overlap,
+        }
+    }
+}
+
+impl From<chunking::ChunkRequest> for ChunkRequest {
+    fn from(value: chunking::ChunkRequest) -> Self {
+        let chunking::ChunkRequest { text, params } = value;
+        Self {
+            text,
+            params: params.into(),
+        }
+    }
+}
+
+impl From<Chunk> for chunking::TextChunk {
+    fn from(value: Chunk) -> Self {
+        let Chunk {
+            text,
+            byte_offset,
+            char_offset,
+            token_count,
+        } = value;
+        Self {
+            text,
+            byte_offset,
+            char_offset,
+            token_count,
+        }
+    }
+}
+
+impl<C: Csi> chunking::Host for HostState<C> {
+    fn chunk(&mut self, request: Vec<chunking::ChunkRequest>) -> anyhow::Result<Vec<Vec<String>>> {
+        self.csi
+            .try_chunk_concurrently(request.into_iter().map(Into::into).collect())
+            .map_err(trap)
+    }
+
+    fn chunk_with_offsets(
+        &mut self,
+        request: Vec<chunking::ChunkRequest>,
+    ) -> anyhow::Result<Vec<Vec<chunking::TextChunk>>> {
+        let chunks = self
+            .csi
+            .try_chunk_with_offsets_concurrently(request.into_iter().map(Into::into).collect())
+            .map_err(trap)?;
+        Ok(chunks
+            .into_iter()
+            .map(|chunks| chunks.into_iter().map(Into::into).collect())
+            .collect())
+    }
+}
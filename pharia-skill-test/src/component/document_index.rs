@@ -0,0 +1,293 @@
+use pharia_skill::{
+    Csi, CsiError, Document, DocumentPath, FilterCondition, IndexPath, MetadataFieldValue,
+    MetadataFilter, MetadataFilterCondition, Modality, SearchFilter, SearchRequest, SearchResult,
+    TextCursor,
+};
+use serde_json::Value;
+
+use super::{bindings::pharia::skill::document_index, trap, HostState};
+
+impl From<document_index::IndexPath> for IndexPath {
+    fn from(value: document_index::IndexPath) -> Self {
+        let document_index::IndexPath {
+            namespace,
+            collection,
+            index,
+        } = value;
+        Self {
+            namespace,
+            collection,
+            index,
+        }
+    }
+}
+
+impl From<document_index::DocumentPath> for DocumentPath {
+    fn from(value: document_index::DocumentPath) -> Self {
+        let document_index::DocumentPath {
+            namespace,
+            collection,
+            name,
+        } = value;
+        Self {
+            namespace,
+            collection,
+            name,
+        }
+    }
+}
+
+impl From<DocumentPath> for document_index::DocumentPath {
+    fn from(value: DocumentPath) -> Self {
+        let DocumentPath {
+            namespace,
+            collection,
+            name,
+        } = value;
+        Self {
+            namespace,
+            collection,
+            name,
+        }
+    }
+}
+
+impl TryFrom<document_index::SearchRequest> for SearchRequest {
+    type Error = CsiError;
+
+    fn try_from(value: document_index::SearchRequest) -> Result<Self, Self::Error> {
+        let document_index::SearchRequest {
+            index_path,
+            query,
+            max_results,
+            min_score,
+            filters,
+        } = value;
+        Ok(Self {
+            query,
+            index_path: index_path.into(),
+            max_results,
+            min_score,
+            filters: filters
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl From<SearchResult> for document_index::SearchResult {
+    fn from(value: SearchResult) -> Self {
+        let SearchResult {
+            document_path,
+            content,
+            score,
+            start,
+            end,
+        } = value;
+        Self {
+            document_path: document_path.into(),
+            content,
+            score,
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+}
+
+impl From<TextCursor> for document_index::TextCursor {
+    fn from(value: TextCursor) -> Self {
+        let TextCursor { item, position } = value;
+        Self { item, position }
+    }
+}
+
+impl TryFrom<document_index::SearchFilter> for SearchFilter {
+    type Error = CsiError;
+
+    fn try_from(value: document_index::SearchFilter) -> Result<Self, Self::Error> {
+        let conditions = |filters: Vec<document_index::MetadataFilter>| {
+            filters
+                .into_iter()
+                .map(|filter| Ok(FilterCondition::Metadata(filter.try_into()?)))
+                .collect::<Result<Vec<_>, CsiError>>()
+        };
+        Ok(match value {
+            document_index::SearchFilter::Without(filters) => Self::Without(conditions(filters)?),
+            document_index::SearchFilter::WithOneOf(filters) => {
+                Self::WithOneOf(conditions(filters)?)
+            }
+            document_index::SearchFilter::WithAll(filters) => Self::With(conditions(filters)?),
+        })
+    }
+}
+
+impl TryFrom<document_index::MetadataFilter> for MetadataFilter {
+    type Error = CsiError;
+
+    fn try_from(value: document_index::MetadataFilter) -> Result<Self, Self::Error> {
+        let document_index::MetadataFilter { field, condition } = value;
+        Ok(Self {
+            field,
+            condition: condition.try_into()?,
+        })
+    }
+}
+
+impl TryFrom<document_index::MetadataFilterCondition> for MetadataFilterCondition {
+    type Error = CsiError;
+
+    fn try_from(value: document_index::MetadataFilterCondition) -> Result<Self, Self::Error> {
+        use document_index::MetadataFilterCondition as Condition;
+
+        let timestamp = |s: String| {
+            s.parse()
+                .map_err(|_| CsiError::InvalidParameters(format!("Invalid timestamp: {s}")))
+        };
+        Ok(match value {
+            Condition::GreaterThan(n) => Self::GreaterThan(n),
+            Condition::GreaterThanOrEqualTo(n) => Self::GreaterThanOrEqualTo(n),
+            Condition::LessThan(n) => Self::LessThan(n),
+            Condition::LessThanOrEqualTo(n) => Self::LessThanOrEqualTo(n),
+            Condition::After(s) => Self::After(timestamp(s)?),
+            Condition::AtOrAfter(s) => Self::AtOrAfter(timestamp(s)?),
+            Condition::Before(s) => Self::Before(timestamp(s)?),
+            Condition::AtOrBefore(s) => Self::AtOrBefore(timestamp(s)?),
+            Condition::EqualTo(metadata_field_value) => Self::EqualTo(metadata_field_value.into()),
+            Condition::IsNull => Self::IsNull(serde_bool::True),
+        })
+    }
+}
+
+impl From<document_index::MetadataFieldValue> for MetadataFieldValue {
+    fn from(value: document_index::MetadataFieldValue) -> Self {
+        match value {
+            document_index::MetadataFieldValue::StringType(s) => Self::String(s),
+            document_index::MetadataFieldValue::IntegerType(n) => Self::Integer(n),
+            document_index::MetadataFieldValue::BooleanType(b) => Self::Boolean(b),
+        }
+    }
+}
+
+impl From<Modality> for document_index::Modality {
+    fn from(value: Modality) -> Self {
+        match value {
+            Modality::Text { text } => Self::Text(text),
+            Modality::Image => Self::Image,
+        }
+    }
+}
+
+impl TryFrom<Document<Value>> for document_index::Document {
+    type Error = CsiError;
+
+    fn try_from(value: Document<Value>) -> Result<Self, Self::Error> {
+        let Document {
+            path,
+            contents,
+            metadata,
+        } = value;
+        Ok(Self {
+            path: path.into(),
+            contents: contents.into_iter().map(Into::into).collect(),
+            metadata: metadata.as_ref().map(encode_metadata).transpose()?,
+        })
+    }
+}
+
+pub(super) fn encode_metadata(metadata: &Value) -> Result<Vec<u8>, CsiError> {
+    Ok(serde_json::to_vec(metadata)?)
+}
+
+impl<C: Csi> document_index::Host for HostState<C> {
+    fn search(
+        &mut self,
+        requests: Vec<document_index::SearchRequest>,
+    ) -> anyhow::Result<Vec<Vec<document_index::SearchResult>>> {
+        let requests = requests
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()
+            .map_err(trap)?;
+        let results = self.csi.try_search_concurrently(requests).map_err(trap)?;
+        Ok(results
+            .into_iter()
+            .map(|results| results.into_iter().map(Into::into).collect())
+            .collect())
+    }
+
+    fn document_metadata(
+        &mut self,
+        requests: Vec<document_index::DocumentPath>,
+    ) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+        let metadata = self
+            .csi
+            .try_documents_metadata::<Value>(requests.into_iter().map(Into::into).collect())
+            .map_err(trap)?;
+        metadata
+            .iter()
+            .map(|metadata| metadata.as_ref().map(encode_metadata).transpose())
+            .collect::<Result<_, _>>()
+            .map_err(trap)
+    }
+
+    fn documents(
+        &mut self,
+        requests: Vec<document_index::DocumentPath>,
+    ) -> anyhow::Result<Vec<document_index::Document>> {
+        let documents = self
+            .csi
+            .try_documents::<Value>(requests.into_iter().map(Into::into).collect())
+            .map_err(trap)?;
+        documents
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()
+            .map_err(trap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_are_converted() {
+        let filter = document_index::SearchFilter::WithAll(vec![
+            document_index::MetadataFilter {
+                field: "created".to_owned(),
+                condition: document_index::MetadataFilterCondition::After(
+                    "2024-01-01T00:00:00Z".to_owned(),
+                ),
+            },
+            document_index::MetadataFilter {
+                field: "deleted".to_owned(),
+                condition: document_index::MetadataFilterCondition::IsNull,
+            },
+        ]);
+
+        let converted = SearchFilter::try_from(filter).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(converted).unwrap(),
+            serde_json::json!({
+                "with": [
+                    { "metadata": { "field": "created", "after": "2024-01-01T00:00:00Z" } },
+                    { "metadata": { "field": "deleted", "is_null": true } },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_timestamps_are_rejected() {
+        let condition = document_index::MetadataFilterCondition::Before("yesterday".to_owned());
+
+        let converted = MetadataFilterCondition::try_from(condition);
+
+        assert_eq!(
+            converted.unwrap_err(),
+            CsiError::InvalidParameters("Invalid timestamp: yesterday".to_owned())
+        );
+    }
+}
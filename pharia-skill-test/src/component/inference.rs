@@ -0,0 +1,548 @@
+use std::collections::VecDeque;
+
+use pharia_skill::{
+    ChatEvent, ChatParams, ChatRequest, ChatResponse, Completion, CompletionEvent,
+    CompletionParams, CompletionRequest, Csi, CsiError, Distribution, EmbeddingRequest,
+    FinishReason, Logprob, Logprobs, Message, Representation, ResponseFormat, TokenUsage, ToolCall,
+    ToolDefinition,
+};
+use wasmtime::component::Resource;
+
+use super::{bindings::pharia::skill::inference, trap, HostState};
+
+impl From<Logprob> for inference::Logprob {
+    fn from(value: Logprob) -> Self {
+        let Logprob { token, logprob } = value;
+        Self { token, logprob }
+    }
+}
+
+impl From<Distribution> for inference::Distribution {
+    fn from(value: Distribution) -> Self {
+        let Distribution { sampled, top } = value;
+        Self {
+            sampled: sampled.into(),
+            top: top.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<TokenUsage> for inference::TokenUsage {
+    fn from(value: TokenUsage) -> Self {
+        let TokenUsage { prompt, completion } = value;
+        Self { prompt, completion }
+    }
+}
+
+/// Tool calls can only be reported to chats with tools, for everything else they end the answer
+/// like a stop sequence. So do reasons added to the SDK later.
+impl From<FinishReason> for inference::FinishReason {
+    fn from(value: FinishReason) -> Self {
+        match value {
+            FinishReason::Length => Self::Length,
+            FinishReason::ContentFilter => Self::ContentFilter,
+            _ => Self::Stop,
+        }
+    }
+}
+
+impl From<FinishReason> for inference::FinishReasonWithTools {
+    fn from(value: FinishReason) -> Self {
+        match value {
+            FinishReason::Length => Self::Length,
+            FinishReason::ContentFilter => Self::ContentFilter,
+            FinishReason::ToolCalls => Self::ToolCalls,
+            // Reasons added to the SDK later end the answer like a stop sequence.
+            _ => Self::Stop,
+        }
+    }
+}
+
+impl From<inference::Logprobs> for Logprobs {
+    fn from(value: inference::Logprobs) -> Self {
+        match value {
+            inference::Logprobs::No => Self::No,
+            inference::Logprobs::Sampled => Self::Sampled,
+            inference::Logprobs::Top(n) => Self::Top(n),
+        }
+    }
+}
+
+impl From<inference::CompletionParams> for CompletionParams {
+    fn from(value: inference::CompletionParams) -> Self {
+        let inference::CompletionParams {
+            max_tokens,
+            temperature,
+            top_k,
+            top_p,
+            stop,
+            return_special_tokens,
+            frequency_penalty,
+            presence_penalty,
+            logprobs,
+        } = value;
+        Self {
+            max_tokens,
+            temperature,
+            top_k,
+            top_p,
+            stop,
+            return_special_tokens,
+            frequency_penalty,
+            presence_penalty,
+            logprobs: logprobs.into(),
+        }
+    }
+}
+
+impl From<inference::CompletionRequest> for CompletionRequest {
+    fn from(value: inference::CompletionRequest) -> Self {
+        let inference::CompletionRequest {
+            model,
+            prompt,
+            params,
+        } = value;
+        Self {
+            model,
+            prompt,
+            params: params.into(),
+        }
+    }
+}
+
+impl From<Completion> for inference::Completion {
+    fn from(value: Completion) -> Self {
+        let Completion {
+            text,
+            finish_reason,
+            logprobs,
+            usage,
+        } = value;
+        Self {
+            text,
+            finish_reason: finish_reason.into(),
+            logprobs: logprobs.into_iter().map(Into::into).collect(),
+            usage: usage.into(),
+        }
+    }
+}
+
+impl From<CompletionEvent> for inference::CompletionEvent {
+    fn from(value: CompletionEvent) -> Self {
+        match value {
+            CompletionEvent::Append { text, logprobs } => {
+                Self::Append(inference::CompletionAppend {
+                    text,
+                    logprobs: logprobs.into_iter().map(Into::into).collect(),
+                })
+            }
+            CompletionEvent::End { finish_reason } => Self::End(finish_reason.into()),
+            CompletionEvent::Usage { usage } => Self::Usage(usage.into()),
+        }
+    }
+}
+
+impl From<ToolCall> for inference::ToolCall {
+    fn from(value: ToolCall) -> Self {
+        let ToolCall {
+            id,
+            name,
+            arguments,
+        } = value;
+        Self {
+            id,
+            name,
+            arguments,
+        }
+    }
+}
+
+impl From<inference::ToolCall> for ToolCall {
+    fn from(value: inference::ToolCall) -> Self {
+        let inference::ToolCall {
+            id,
+            name,
+            arguments,
+        } = value;
+        Self {
+            id,
+            name,
+            arguments,
+        }
+    }
+}
+
+impl TryFrom<inference::ToolDefinition> for ToolDefinition {
+    type Error = CsiError;
+
+    fn try_from(value: inference::ToolDefinition) -> Result<Self, Self::Error> {
+        let inference::ToolDefinition {
+            name,
+            description,
+            parameters,
+        } = value;
+        Ok(Self {
+            name,
+            description,
+            parameters: serde_json::from_slice(&parameters)?,
+        })
+    }
+}
+
+impl From<Message> for inference::Message {
+    fn from(value: Message) -> Self {
+        let Message { role, content, .. } = value;
+        Self { role, content }
+    }
+}
+
+impl From<inference::Message> for Message {
+    fn from(value: inference::Message) -> Self {
+        let inference::Message { role, content } = value;
+        Self::new(role, content)
+    }
+}
+
+impl From<Message> for inference::MessageWithTools {
+    fn from(value: Message) -> Self {
+        let Message {
+            role,
+            content,
+            tool_calls,
+            tool_call_id,
+        } = value;
+        Self {
+            role,
+            content,
+            tool_calls: tool_calls.into_iter().map(Into::into).collect(),
+            tool_call_id,
+        }
+    }
+}
+
+impl From<inference::MessageWithTools> for Message {
+    fn from(value: inference::MessageWithTools) -> Self {
+        let inference::MessageWithTools {
+            role,
+            content,
+            tool_calls,
+            tool_call_id,
+        } = value;
+        Self {
+            role,
+            content,
+            tool_calls: tool_calls.into_iter().map(Into::into).collect(),
+            tool_call_id,
+        }
+    }
+}
+
+impl From<inference::ChatParams> for ChatParams {
+    fn from(value: inference::ChatParams) -> Self {
+        let inference::ChatParams {
+            max_tokens,
+            temperature,
+            top_p,
+            frequency_penalty,
+            presence_penalty,
+            logprobs,
+        } = value;
+        Self {
+            max_tokens,
+            temperature,
+            top_p,
+            frequency_penalty,
+            presence_penalty,
+            logprobs: logprobs.into(),
+            response_format: None,
+        }
+    }
+}
+
+impl TryFrom<inference::ResponseFormat> for ResponseFormat {
+    type Error = CsiError;
+
+    fn try_from(value: inference::ResponseFormat) -> Result<Self, Self::Error> {
+        Ok(match value {
+            inference::ResponseFormat::Text => Self::Text,
+            inference::ResponseFormat::JsonSchema(schema) => {
+                Self::JsonSchema(serde_json::from_slice(&schema)?)
+            }
+        })
+    }
+}
+
+impl TryFrom<inference::ChatRequest> for ChatRequest {
+    type Error = CsiError;
+
+    fn try_from(value: inference::ChatRequest) -> Result<Self, Self::Error> {
+        let inference::ChatRequest {
+            model,
+            messages,
+            params,
+        } = value;
+        Ok(Self {
+            model,
+            messages: messages.into_iter().map(Into::into).collect(),
+            params: params.into(),
+            tools: Vec::new(),
+        })
+    }
+}
+
+impl TryFrom<inference::ChatWithToolsRequest> for ChatRequest {
+    type Error = CsiError;
+
+    fn try_from(value: inference::ChatWithToolsRequest) -> Result<Self, Self::Error> {
+        let inference::ChatWithToolsRequest {
+            model,
+            messages,
+            params,
+            tools,
+            response_format,
+        } = value;
+        Ok(Self {
+            model,
+            messages: messages.into_iter().map(Into::into).collect(),
+            params: ChatParams {
+                response_format: response_format.map(TryInto::try_into).transpose()?,
+                ..params.into()
+            },
+            tools: tools
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl From<ChatResponse> for inference::ChatResponse {
+    fn from(value: ChatResponse) -> Self {
+        let ChatResponse {
+            message,
+            finish_reason,
+            logprobs,
+            usage,
+        } = value;
+        Self {
+            message: message.into(),
+            finish_reason: finish_reason.into(),
+            logprobs: logprobs.into_iter().map(Into::into).collect(),
+            usage: usage.into(),
+        }
+    }
+}
+
+impl From<ChatResponse> for inference::ChatWithToolsResponse {
+    fn from(value: ChatResponse) -> Self {
+        let ChatResponse {
+            message,
+            finish_reason,
+            logprobs,
+            usage,
+        } = value;
+        Self {
+            message: message.into(),
+            finish_reason: finish_reason.into(),
+            logprobs: logprobs.into_iter().map(Into::into).collect(),
+            usage: usage.into(),
+        }
+    }
+}
+
+impl From<ChatEvent> for inference::ChatEvent {
+    fn from(value: ChatEvent) -> Self {
+        match value {
+            ChatEvent::MessageBegin { role } => Self::MessageBegin(role),
+            ChatEvent::MessageAppend { content, logprobs } => {
+                Self::MessageAppend(inference::MessageAppend {
+                    content,
+                    logprobs: logprobs.into_iter().map(Into::into).collect(),
+                })
+            }
+            ChatEvent::ToolCall { tool_call } => Self::ToolCall(tool_call.into()),
+            ChatEvent::MessageEnd { finish_reason } => Self::MessageEnd(finish_reason.into()),
+            ChatEvent::Usage { usage } => Self::Usage(usage.into()),
+        }
+    }
+}
+
+impl From<inference::Representation> for Representation {
+    fn from(value: inference::Representation) -> Self {
+        match value {
+            inference::Representation::Symmetric => Self::Symmetric,
+            inference::Representation::Document => Self::Document,
+            inference::Representation::Query => Self::Query,
+        }
+    }
+}
+
+impl From<inference::EmbeddingRequest> for EmbeddingRequest {
+    fn from(value: inference::EmbeddingRequest) -> Self {
+        let inference::EmbeddingRequest {
+            model,
+            texts,
+            representation,
+            dimensions,
+        } = value;
+        Self {
+            model,
+            texts,
+            representation: representation.into(),
+            dimensions,
+        }
+    }
+}
+
+/// The events of a completion stream. The stream of the [`Csi`] borrows it, so all events are
+/// collected when the skill opens the stream, and handed out one by one.
+pub struct CompletionStream {
+    events: VecDeque<Result<inference::CompletionEvent, CsiError>>,
+}
+
+/// The events of a chat stream, collected when the skill opens the stream.
+pub struct ChatStream {
+    events: VecDeque<Result<inference::ChatEvent, CsiError>>,
+}
+
+impl<C: Csi> inference::Host for HostState<C> {
+    fn complete(
+        &mut self,
+        requests: Vec<inference::CompletionRequest>,
+    ) -> anyhow::Result<Vec<inference::Completion>> {
+        let completions = self
+            .csi
+            .try_complete_concurrently(requests.into_iter().map(Into::into).collect())
+            .map_err(trap)?;
+        Ok(completions.into_iter().map(Into::into).collect())
+    }
+
+    fn chat(
+        &mut self,
+        requests: Vec<inference::ChatRequest>,
+    ) -> anyhow::Result<Vec<inference::ChatResponse>> {
+        let requests = requests
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()
+            .map_err(trap)?;
+        let responses = self.csi.try_chat_concurrently(requests).map_err(trap)?;
+        Ok(responses.into_iter().map(Into::into).collect())
+    }
+
+    fn chat_with_tools(
+        &mut self,
+        requests: Vec<inference::ChatWithToolsRequest>,
+    ) -> anyhow::Result<Vec<inference::ChatWithToolsResponse>> {
+        let requests = requests
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()
+            .map_err(trap)?;
+        let responses = self.csi.try_chat_concurrently(requests).map_err(trap)?;
+        Ok(responses.into_iter().map(Into::into).collect())
+    }
+
+    fn embed(
+        &mut self,
+        requests: Vec<inference::EmbeddingRequest>,
+    ) -> anyhow::Result<Vec<Vec<inference::Embedding>>> {
+        self.csi
+            .try_embed_concurrently(requests.into_iter().map(Into::into).collect())
+            .map_err(trap)
+    }
+}
+
+impl<C: Csi> inference::HostCompletionStream for HostState<C> {
+    fn new(
+        &mut self,
+        init: inference::CompletionRequest,
+    ) -> anyhow::Result<Resource<CompletionStream>> {
+        let events = self
+            .csi
+            .try_completion_stream(init.into())
+            .map(|event| event.map(Into::into))
+            .collect();
+        Ok(self.table.push(CompletionStream { events })?)
+    }
+
+    fn next(
+        &mut self,
+        stream: Resource<CompletionStream>,
+    ) -> anyhow::Result<Option<inference::CompletionEvent>> {
+        let stream = self.table.get_mut(&stream)?;
+        stream.events.pop_front().transpose().map_err(trap)
+    }
+
+    fn drop(&mut self, stream: Resource<CompletionStream>) -> anyhow::Result<()> {
+        self.table.delete(stream)?;
+        Ok(())
+    }
+}
+
+impl<C: Csi> inference::HostChatStream for HostState<C> {
+    fn new(&mut self, init: inference::ChatRequest) -> anyhow::Result<Resource<ChatStream>> {
+        let request = ChatRequest::try_from(init).map_err(trap)?;
+        let events = self
+            .csi
+            .try_chat_stream(request)
+            .map(|event| event.map(Into::into))
+            .collect();
+        Ok(self.table.push(ChatStream { events })?)
+    }
+
+    fn next(
+        &mut self,
+        stream: Resource<ChatStream>,
+    ) -> anyhow::Result<Option<inference::ChatEvent>> {
+        let stream = self.table.get_mut(&stream)?;
+        stream.events.pop_front().transpose().map_err(trap)
+    }
+
+    fn drop(&mut self, stream: Resource<ChatStream>) -> anyhow::Result<()> {
+        self.table.delete(stream)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn json_of_chat_requests_is_parsed() {
+        let request = inference::ChatWithToolsRequest {
+            model: "llama-3.1-8b-instruct".to_owned(),
+            messages: vec![],
+            params: inference::ChatParams {
+                max_tokens: None,
+                temperature: None,
+                top_p: None,
+                frequency_penalty: None,
+                presence_penalty: None,
+                logprobs: inference::Logprobs::No,
+            },
+            tools: vec![inference::ToolDefinition {
+                name: "add".to_owned(),
+                description: None,
+                parameters: br#"{"type":"object","properties":{}}"#.to_vec(),
+            }],
+            response_format: Some(inference::ResponseFormat::JsonSchema(
+                br#"{"type":"object"}"#.to_vec(),
+            )),
+        };
+
+        let converted = ChatRequest::try_from(request).unwrap();
+
+        assert_eq!(
+            converted.params.response_format,
+            Some(ResponseFormat::JsonSchema(json!({ "type": "object" })))
+        );
+        assert_eq!(
+            converted.tools[0].parameters,
+            json!({ "type": "object", "properties": {} })
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use pharia_skill::{Csi, CsiError, LanguageCode, SelectLanguageRequest};
+
+use super::{bindings::pharia::skill::language, trap, HostState};
+
+impl TryFrom<language::SelectLanguageRequest> for SelectLanguageRequest {
+    type Error = CsiError;
+
+    fn try_from(value: language::SelectLanguageRequest) -> Result<Self, Self::Error> {
+        let language::SelectLanguageRequest { text, languages } = value;
+        Ok(Self {
+            text,
+            languages: languages
+                .iter()
+                .map(|code| parse_language(code))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// The Kernel rejects unknown language codes, so the skill traps on them as well.
+fn parse_language(code: &str) -> Result<LanguageCode, CsiError> {
+    code.parse()
+        .map_err(|_| CsiError::InvalidParameters(format!("Unknown language code: {code}")))
+}
+
+impl<C: Csi> language::Host for HostState<C> {
+    fn select_language(
+        &mut self,
+        request: Vec<language::SelectLanguageRequest>,
+    ) -> anyhow::Result<Vec<Option<String>>> {
+        let requests = request
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<Result<_, _>>()
+            .map_err(trap)?;
+        let languages = self
+            .csi
+            .try_select_language_concurrently(requests)
+            .map_err(trap)?;
+        Ok(languages
+            .into_iter()
+            .map(|language| language.map(|language| language.to_string()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_language_codes_are_rejected() {
+        let request = language::SelectLanguageRequest {
+            text: "Hello".to_owned(),
+            languages: vec!["eng".to_owned(), "xyz".to_owned()],
+        };
+
+        let converted = SelectLanguageRequest::try_from(request);
+
+        assert_eq!(
+            converted.unwrap_err(),
+            CsiError::InvalidParameters("Unknown language code: xyz".to_owned())
+        );
+    }
+}
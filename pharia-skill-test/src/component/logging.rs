@@ -0,0 +1,34 @@
+use pharia_skill::{Csi, Level, LogRecord};
+
+use super::{bindings::pharia::skill::logging, HostState};
+
+impl From<logging::Level> for Level {
+    fn from(value: logging::Level) -> Self {
+        match value {
+            logging::Level::Trace => Self::Trace,
+            logging::Level::Debug => Self::Debug,
+            logging::Level::Info => Self::Info,
+            logging::Level::Warn => Self::Warn,
+            logging::Level::Error => Self::Error,
+        }
+    }
+}
+
+impl<C: Csi> logging::Host for HostState<C> {
+    fn log(
+        &mut self,
+        level: logging::Level,
+        message: String,
+        fields: Vec<logging::Field>,
+    ) -> anyhow::Result<()> {
+        self.csi.log(LogRecord {
+            level: level.into(),
+            message,
+            fields: fields
+                .into_iter()
+                .map(|logging::Field { key, value }| (key, value))
+                .collect(),
+        });
+        Ok(())
+    }
+}
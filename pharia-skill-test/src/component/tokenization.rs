@@ -0,0 +1,46 @@
+use pharia_skill::{Csi, DetokenizeRequest, TokenizeRequest};
+
+use super::{bindings::pharia::skill::tokenization, trap, HostState};
+
+impl From<tokenization::TokenizeRequest> for TokenizeRequest {
+    fn from(value: tokenization::TokenizeRequest) -> Self {
+        let tokenization::TokenizeRequest { model, text } = value;
+        Self { model, text }
+    }
+}
+
+impl From<tokenization::DetokenizeRequest> for DetokenizeRequest {
+    fn from(value: tokenization::DetokenizeRequest) -> Self {
+        let tokenization::DetokenizeRequest { model, tokens } = value;
+        Self { model, tokens }
+    }
+}
+
+impl<C: Csi> tokenization::Host for HostState<C> {
+    fn tokenize(
+        &mut self,
+        requests: Vec<tokenization::TokenizeRequest>,
+    ) -> anyhow::Result<Vec<Vec<u32>>> {
+        self.csi
+            .try_tokenize_concurrently(requests.into_iter().map(Into::into).collect())
+            .map_err(trap)
+    }
+
+    fn detokenize(
+        &mut self,
+        requests: Vec<tokenization::DetokenizeRequest>,
+    ) -> anyhow::Result<Vec<String>> {
+        self.csi
+            .try_detokenize_concurrently(requests.into_iter().map(Into::into).collect())
+            .map_err(trap)
+    }
+
+    fn count_tokens(
+        &mut self,
+        requests: Vec<tokenization::TokenizeRequest>,
+    ) -> anyhow::Result<Vec<u32>> {
+        self.csi
+            .try_count_tokens_concurrently(requests.into_iter().map(Into::into).collect())
+            .map_err(trap)
+    }
+}
@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+
+use jiff::Timestamp;
+use pharia_skill::{
+    Csi, CsiError, CsiLayer, Document, DocumentPath, FilterCondition, IndexPath,
+    MetadataFieldValue, MetadataFilter, MetadataFilterCondition, Modality, SearchFilter,
+    SearchRequest, SearchResult, TextCursor,
+};
+use serde_json::Value;
+
+/// Term frequency saturation of BM25
+const K1: f64 = 1.2;
+/// Document length normalization of BM25
+const B: f64 = 0.75;
+
+/// An in-memory Document Index, to test skills doing retrieval without a Kernel.
+///
+/// As a [`CsiLayer`], it answers searches and document requests from the documents inserted into
+/// it, and passes all other requests on to the wrapped [`Csi`]:
+///
+/// ```
+/// use pharia_skill::{
+///     Csi, CsiExt, Document, DocumentPath, IndexPath, Modality, SearchRequest,
+/// };
+/// use pharia_skill_test::{FakeDocumentIndex, StubCsi};
+///
+/// let index = IndexPath::new("namespace", "collection", "index");
+/// let documents = FakeDocumentIndex::new()
+///     .with_index(index.clone())
+///     .with_document(Document {
+///         path: DocumentPath::new("namespace", "collection", "kernel"),
+///         contents: vec![Modality::Text {
+///             text: "The Kernel runs skills as WebAssembly components.".to_owned(),
+///         }],
+///         metadata: None,
+///     });
+/// let csi = StubCsi.layer(documents);
+///
+/// let results = csi.search(SearchRequest::new("How are skills run?", index));
+/// assert_eq!(results[0].document_path.name, "kernel");
+/// ```
+///
+/// Texts are split into chunks of [`FakeDocumentIndex::with_chunk_size`] words, which are scored
+/// against the query with BM25 over the words of all chunks in the collection. Only chunks
+/// sharing at least one word with the query are found. The [`TextCursor`]s of a result point to
+/// the content item and the character offsets of the chunk within it.
+///
+/// Metadata filters look up their field as a dot separated path into the metadata of a
+/// document, optionally prefixed with `metadata.`. If the metadata is an array, a condition
+/// holds if it holds for any of its elements. Documents match a search if all of its filters
+/// match: `with` if all its conditions hold, `with_one_of` if any does, and `without` if none
+/// does.
+#[derive(Clone, Debug)]
+pub struct FakeDocumentIndex {
+    indexes: Vec<IndexPath>,
+    documents: Vec<Document>,
+    chunk_size: usize,
+}
+
+/// A part of a text item of a document, which is scored on its own.
+struct Passage<'a> {
+    document: &'a Document,
+    item: usize,
+    /// Character offsets of the passage within the item
+    start: usize,
+    end: usize,
+    text: &'a str,
+    terms: Vec<String>,
+}
+
+impl FakeDocumentIndex {
+    /// Texts are split into chunks of this many words, unless configured otherwise.
+    pub const DEFAULT_CHUNK_SIZE: usize = 128;
+
+    /// An index without any documents or search indexes.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            indexes: Vec::new(),
+            documents: Vec::new(),
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Allow searching the collection of the path with the index of the path. Searching indexes
+    /// which have not been added fails, like it does in the Kernel.
+    #[must_use]
+    pub fn with_index(mut self, index_path: IndexPath) -> Self {
+        self.indexes.push(index_path);
+        self
+    }
+
+    /// Split texts into chunks of at most this many words.
+    #[must_use]
+    pub fn with_chunk_size(mut self, words: usize) -> Self {
+        self.chunk_size = words.max(1);
+        self
+    }
+
+    #[must_use]
+    pub fn with_document(mut self, document: Document) -> Self {
+        self.insert(document);
+        self
+    }
+
+    /// Add the document, replacing any document with the same path.
+    pub fn insert(&mut self, document: Document) {
+        match self
+            .documents
+            .iter_mut()
+            .find(|existing| existing.path == document.path)
+        {
+            Some(existing) => *existing = document,
+            None => self.documents.push(document),
+        }
+    }
+
+    fn document(&self, path: &DocumentPath) -> Result<&Document, CsiError> {
+        self.documents
+            .iter()
+            .find(|document| document.path == *path)
+            .ok_or_else(|| {
+                CsiError::InvalidParameters(format!(
+                    "Document not found: {}/{}/{}",
+                    path.namespace, path.collection, path.name
+                ))
+            })
+    }
+
+    fn search(&self, request: &SearchRequest) -> Result<Vec<SearchResult>, CsiError> {
+        let IndexPath {
+            namespace,
+            collection,
+            index,
+        } = &request.index_path;
+        if !self.indexes.iter().any(|known| {
+            known.namespace == *namespace
+                && known.collection == *collection
+                && known.index == *index
+        }) {
+            return Err(CsiError::InvalidParameters(format!(
+                "Index not found: {namespace}/{collection}/{index}"
+            )));
+        }
+
+        // Term statistics are computed over the whole collection, like a search engine would,
+        // so filters only decide which passages are returned, not how they are scored.
+        let passages = self
+            .documents
+            .iter()
+            .filter(|document| {
+                document.path.namespace == *namespace && document.path.collection == *collection
+            })
+            .flat_map(|document| self.passages(document))
+            .collect::<Vec<_>>();
+        let scorer = Bm25::new(&passages);
+        let query = terms(&request.query);
+
+        let mut results = passages
+            .iter()
+            .filter(|passage| {
+                request
+                    .filters
+                    .iter()
+                    .all(|filter| matches(filter, passage.document.metadata.as_ref()))
+            })
+            .map(|passage| (scorer.score(&query, passage), passage))
+            .filter(|(score, _)| *score > 0.0)
+            .filter(|(score, _)| request.min_score.is_none_or(|min| *score >= min))
+            .collect::<Vec<_>>();
+        // A stable sort keeps passages with equal scores in the order they were inserted.
+        results.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        Ok(results
+            .into_iter()
+            .take(request.max_results as usize)
+            .map(|(score, passage)| SearchResult {
+                document_path: passage.document.path.clone(),
+                content: passage.text.to_owned(),
+                score,
+                start: cursor(passage.item, passage.start),
+                end: cursor(passage.item, passage.end),
+            })
+            .collect())
+    }
+
+    /// The text items of the document, split into chunks of words.
+    fn passages<'a>(&self, document: &'a Document) -> Vec<Passage<'a>> {
+        let mut passages = Vec::new();
+        for (item, content) in document.contents.iter().enumerate() {
+            let Modality::Text { text: item_text } = content else {
+                continue;
+            };
+            let words = word_spans(item_text);
+            for chunk in words.chunks(self.chunk_size) {
+                let (start, end) = (chunk[0].0, chunk[chunk.len() - 1].1);
+                let text = &item_text[start..end];
+                passages.push(Passage {
+                    document,
+                    item,
+                    start: char_offset(item_text, start),
+                    end: char_offset(item_text, end),
+                    text,
+                    terms: terms(text),
+                });
+            }
+        }
+        passages
+    }
+}
+
+impl Default for FakeDocumentIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsiLayer for FakeDocumentIndex {
+    fn search<C: Csi + ?Sized>(
+        &self,
+        _inner: &C,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        requests
+            .iter()
+            .map(|request| self.search(request))
+            .collect()
+    }
+
+    fn documents<C: Csi + ?Sized>(
+        &self,
+        _inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document>, CsiError> {
+        paths
+            .iter()
+            .map(|path| self.document(path).cloned())
+            .collect()
+    }
+
+    fn documents_metadata<C: Csi + ?Sized>(
+        &self,
+        _inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Value>>, CsiError> {
+        paths
+            .iter()
+            .map(|path| Ok(self.document(path)?.metadata.clone()))
+            .collect()
+    }
+}
+
+fn cursor(item: usize, position: usize) -> TextCursor {
+    TextCursor {
+        item: u32::try_from(item).unwrap_or(u32::MAX),
+        position: u32::try_from(position).unwrap_or(u32::MAX),
+    }
+}
+
+/// The character offset of a byte offset into the text.
+fn char_offset(text: &str, byte_offset: usize) -> usize {
+    text[..byte_offset].chars().count()
+}
+
+/// Byte ranges of the whitespace separated words of the text.
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (offset, char) in text.char_indices() {
+        match (char.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                spans.push((word_start, offset));
+                start = None;
+            }
+            (false, None) => start = Some(offset),
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        spans.push((word_start, text.len()));
+    }
+    spans
+}
+
+/// The lowercase alphanumeric terms of the text, which are matched against each other.
+fn terms(text: &str) -> Vec<String> {
+    text.split(|char: char| !char.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Okapi BM25 statistics of a set of passages.
+struct Bm25 {
+    passages: f64,
+    average_length: f64,
+    /// Number of passages containing each term
+    document_frequency: HashMap<String, usize>,
+}
+
+// Counts of test documents are far too small to lose precision as `f64`.
+#[allow(clippy::cast_precision_loss)]
+impl Bm25 {
+    fn new(passages: &[Passage<'_>]) -> Self {
+        let mut document_frequency = HashMap::new();
+        for passage in passages {
+            let mut seen = passage.terms.clone();
+            seen.sort_unstable();
+            seen.dedup();
+            for term in seen {
+                *document_frequency.entry(term).or_insert(0) += 1;
+            }
+        }
+        let total_length = passages
+            .iter()
+            .map(|passage| passage.terms.len())
+            .sum::<usize>();
+        Self {
+            passages: passages.len() as f64,
+            average_length: total_length as f64 / passages.len().max(1) as f64,
+            document_frequency,
+        }
+    }
+
+    fn score(&self, query: &[String], passage: &Passage<'_>) -> f64 {
+        let length = passage.terms.len() as f64;
+        query
+            .iter()
+            .map(|term| {
+                let frequency = passage.terms.iter().filter(|t| *t == term).count() as f64;
+                if frequency == 0.0 {
+                    return 0.0;
+                }
+                let containing = self.document_frequency.get(term).copied().unwrap_or(0) as f64;
+                let idf = ((self.passages - containing + 0.5) / (containing + 0.5)).ln_1p();
+                let normalization = 1.0 - B + B * length / self.average_length.max(1.0);
+                idf * frequency * (K1 + 1.0) / (frequency + K1 * normalization)
+            })
+            .sum()
+    }
+}
+
+/// Whether a document with the metadata passes the filter.
+fn matches(filter: &SearchFilter, metadata: Option<&Value>) -> bool {
+    let holds = |condition: &FilterCondition| {
+        let FilterCondition::Metadata(filter) = condition;
+        holds(filter, metadata)
+    };
+    match filter {
+        SearchFilter::With(conditions) => conditions.iter().all(holds),
+        SearchFilter::WithOneOf(conditions) => conditions.iter().any(holds),
+        SearchFilter::Without(conditions) => !conditions.iter().any(holds),
+    }
+}
+
+fn holds(filter: &MetadataFilter, metadata: Option<&Value>) -> bool {
+    let items = match metadata {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(item) => vec![item],
+        None => vec![],
+    };
+    if items.is_empty() {
+        return matches!(filter.condition, MetadataFilterCondition::IsNull(_));
+    }
+    items
+        .into_iter()
+        .any(|item| satisfies(&filter.condition, field(item, &filter.field)))
+}
+
+/// The value at the dot separated path, or `None` if there is no value or it is `null`.
+fn field<'a>(item: &'a Value, path: &str) -> Option<&'a Value> {
+    let lookup = |path: &str| {
+        path.split('.')
+            .try_fold(item, |value, key| value.get(key))
+            .filter(|value| !value.is_null())
+    };
+    lookup(path).or_else(|| lookup(path.strip_prefix("metadata.")?))
+}
+
+fn satisfies(condition: &MetadataFilterCondition, value: Option<&Value>) -> bool {
+    let number = || value.and_then(Value::as_f64);
+    let timestamp = || value?.as_str()?.parse::<Timestamp>().ok();
+    match condition {
+        MetadataFilterCondition::GreaterThan(bound) => number().is_some_and(|n| n > *bound),
+        MetadataFilterCondition::GreaterThanOrEqualTo(bound) => {
+            number().is_some_and(|n| n >= *bound)
+        }
+        MetadataFilterCondition::LessThan(bound) => number().is_some_and(|n| n < *bound),
+        MetadataFilterCondition::LessThanOrEqualTo(bound) => number().is_some_and(|n| n <= *bound),
+        MetadataFilterCondition::After(bound) => timestamp().is_some_and(|t| t > *bound),
+        MetadataFilterCondition::AtOrAfter(bound) => timestamp().is_some_and(|t| t >= *bound),
+        MetadataFilterCondition::Before(bound) => timestamp().is_some_and(|t| t < *bound),
+        MetadataFilterCondition::AtOrBefore(bound) => timestamp().is_some_and(|t| t <= *bound),
+        MetadataFilterCondition::EqualTo(expected) => match (expected, value) {
+            (MetadataFieldValue::String(expected), Some(Value::String(actual))) => {
+                expected == actual
+            }
+            (MetadataFieldValue::Integer(expected), Some(actual)) => {
+                actual.as_i64() == Some(*expected)
+            }
+            (MetadataFieldValue::Boolean(expected), Some(Value::Bool(actual))) => {
+                expected == actual
+            }
+            _ => false,
+        },
+        MetadataFilterCondition::IsNull(_) => value.is_none(),
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{Mutex, PoisonError},
+};
+
+use jiff::Timestamp;
+use pharia_skill::{RunTrace, TraceStep};
+#[cfg(feature = "tracing")]
+use pharia_skill::{SpanExporter, SpanRecord};
+use serde_json::{json, Value};
+
+/// Writes every span of a [`pharia_skill::SpanCollector`] as one line of JSON to a file.
+///
+/// ```no_run
+/// use pharia_skill::{CsiExt, SpanCollector, Tracing};
+/// use pharia_skill_test::{DevCsi, JsonFileExporter};
+///
+/// let exporter = JsonFileExporter::create("spans.jsonl").unwrap();
+/// tracing::subscriber::set_global_default(SpanCollector::new(exporter)).unwrap();
+/// let csi = DevCsi::aleph_alpha("token").layer(Tracing);
+/// ```
+#[cfg(feature = "tracing")]
+pub struct JsonFileExporter {
+    file: Mutex<File>,
+}
+
+#[cfg(feature = "tracing")]
+impl JsonFileExporter {
+    /// Create the file, or truncate it if it exists.
+    ///
+    /// # Errors
+    /// Will return an error if the file cannot be created.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl SpanExporter for JsonFileExporter {
+    fn export(&self, span: SpanRecord) {
+        let Ok(mut line) = serde_json::to_vec(&span) else {
+            return;
+        };
+        line.push(b'\n');
+        // Tracing must not fail the skill, so a span which cannot be written is dropped.
+        self.file.lock().unwrap().write_all(&line).ok();
+    }
+}
+
+/// Writes the spans of a [`RunTrace`] as one line of OTLP-JSON per trace, the format of the
+/// OpenTelemetry file exporter. The file can be inspected as is, or loaded into any tool which
+/// understands OTLP, e.g. the OpenTelemetry collector.
+///
+/// ```no_run
+/// use pharia_skill::{CsiExt, RunTrace};
+/// use pharia_skill_test::{DevCsi, OtlpFileExporter};
+///
+/// let trace = RunTrace::new();
+/// let csi = DevCsi::aleph_alpha("token").layer(trace.clone());
+/// // run the skill with `csi` ...
+/// OtlpFileExporter::create("traces.jsonl").unwrap().export(&trace).unwrap();
+/// ```
+pub struct OtlpFileExporter {
+    file: Mutex<File>,
+}
+
+impl OtlpFileExporter {
+    /// Create the file, or truncate it if it exists.
+    ///
+    /// # Errors
+    /// Will return an error if the file cannot be created.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(File::create(path)?),
+        })
+    }
+
+    /// Append all steps recorded by the run trace so far.
+    ///
+    /// # Errors
+    /// Will return an error if the file cannot be written.
+    pub fn export(&self, trace: &RunTrace) -> io::Result<()> {
+        let mut line = serde_json::to_vec(&to_otlp_json(trace))?;
+        line.push(b'\n');
+        self.file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .write_all(&line)
+    }
+}
+
+/// Name of the service in the OTLP resource of exported traces.
+const SERVICE_NAME: &str = "pharia-skill";
+
+/// The steps recorded by the run trace, as an OTLP-JSON `ExportTraceServiceRequest`.
+#[must_use]
+pub fn to_otlp_json(trace: &RunTrace) -> Value {
+    let trace_id = format!("{:032x}", trace.trace_id());
+    let spans = trace
+        .steps()
+        .iter()
+        .map(|span| otlp_span(&trace_id, span))
+        .collect::<Vec<_>>();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [string_attribute("service.name", SERVICE_NAME)],
+            },
+            "scopeSpans": [{
+                "scope": { "name": "pharia_skill", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+/// `SPAN_KIND_INTERNAL`, as the spans are steps within the skill.
+const SPAN_KIND_INTERNAL: u8 = 1;
+const STATUS_CODE_OK: u8 = 1;
+const STATUS_CODE_ERROR: u8 = 2;
+
+fn otlp_span(trace_id: &str, span: &TraceStep) -> Value {
+    let mut attributes = Vec::new();
+    if let Some(input) = &span.input {
+        attributes.push(string_attribute("input", &input.to_string()));
+    }
+    if let Some(output) = &span.output {
+        attributes.push(string_attribute("output", &output.to_string()));
+    }
+    let status = match &span.error {
+        Some(error) => json!({ "code": STATUS_CODE_ERROR, "message": error }),
+        None => json!({ "code": STATUS_CODE_OK }),
+    };
+    // Spans still open are exported as if they ended now, so they are not dropped by consumers.
+    let end = span.end.unwrap_or_else(Timestamp::now);
+    json!({
+        "traceId": trace_id,
+        "spanId": format!("{:016x}", span.id),
+        "parentSpanId": span.parent_id.map(|id| format!("{id:016x}")).unwrap_or_default(),
+        "name": span.name,
+        "kind": SPAN_KIND_INTERNAL,
+        // OTLP-JSON encodes 64 bit integers as strings
+        "startTimeUnixNano": span.start.as_nanosecond().to_string(),
+        "endTimeUnixNano": end.as_nanosecond().to_string(),
+        "attributes": attributes,
+        "status": status,
+    })
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
+
+use pharia_skill::{Csi, CsiError, CsiLayer, LanguageCode, SelectLanguageRequest};
+use regex::Regex;
+
+/// Article 1 of the Universal Declaration of Human Rights and a few everyday sentences in every
+/// language of [`LanguageCode`], one language code and text per line, separated by a tab.
+const SAMPLES: &str = include_str!("language_samples.txt");
+
+/// Longest character n-grams of the profiles
+const MAX_GRAM: usize = 3;
+
+/// Probability of an n-gram which is not part of the profile of a language
+const UNSEEN: f64 = 1e-4;
+
+/// Scales the mean log-likelihoods of the n-grams of a text before they are turned into
+/// probabilities, so that the confidence does not depend on the length of the text.
+const SHARPNESS: f64 = 10.0;
+
+/// Confidence a language must at least reach to be detected, unless configured otherwise
+const DEFAULT_THRESHOLD: f64 = 0.6;
+
+static WORDS: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[\p{L}\p{M}]+").expect("pattern is valid"));
+
+static DEFAULT_PROFILES: LazyLock<Arc<Vec<(LanguageCode, Profile)>>> = LazyLock::new(|| {
+    let mut profiles = Vec::new();
+    for (language, text) in SAMPLES.lines().filter_map(|line| line.split_once('\t')) {
+        let language = language.parse().expect("samples are for known languages");
+        add_sample(&mut profiles, language, text);
+    }
+    Arc::new(profiles)
+});
+
+fn add_sample(profiles: &mut Vec<(LanguageCode, Profile)>, language: LanguageCode, text: &str) {
+    match profiles.iter_mut().find(|(known, _)| *known == language) {
+        Some((_, profile)) => profile.add(text),
+        None => profiles.push((language, Profile::new(text))),
+    }
+}
+
+/// How often the character n-grams occur in a text.
+#[derive(Clone, Debug, Default)]
+struct Profile {
+    counts: HashMap<String, f64>,
+    /// Number of n-grams of each length, the n-grams of length `n` at index `n - 1`
+    totals: [f64; MAX_GRAM],
+}
+
+impl Profile {
+    fn new(text: &str) -> Self {
+        let mut profile = Self::default();
+        profile.add(text);
+        profile
+    }
+
+    /// Count the n-grams of the words of the text, padded with a space so the n-grams at the
+    /// start and end of words stand out.
+    fn add(&mut self, text: &str) {
+        for word in WORDS.find_iter(text) {
+            let chars = format!(" {} ", word.as_str().to_lowercase())
+                .chars()
+                .collect::<Vec<_>>();
+            for n in 1..=MAX_GRAM {
+                for gram in chars.windows(n).filter(|gram| *gram != [' ']) {
+                    *self.counts.entry(gram.iter().collect()).or_default() += 1.0;
+                    self.totals[n - 1] += 1.0;
+                }
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Mean log-likelihood of the n-grams of the text, if they were drawn from this profile.
+    fn log_likelihood(&self, text: &Self) -> f64 {
+        let sum = text
+            .counts
+            .iter()
+            .map(|(gram, count)| {
+                let probability = self
+                    .counts
+                    .get(gram)
+                    .map_or(UNSEEN, |seen| seen / self.totals[gram.chars().count() - 1]);
+                count * probability.ln()
+            })
+            .sum::<f64>();
+        sum / text.totals.iter().sum::<f64>()
+    }
+}
+
+/// Detects the language of texts offline, so skills branching on the language can be tested
+/// without a Kernel.
+///
+/// Texts are compared with a profile of the character n-grams of each language, built from a
+/// translation of the first article of the Universal Declaration of Human Rights and a few
+/// everyday sentences. The requested languages are weighed by how likely the n-grams of the text
+/// are in their profile, and the most likely one is detected if its share, its confidence,
+/// reaches the threshold. Like the Kernel, it rather detects no language than one of two similar
+/// ones.
+///
+/// As a [`CsiLayer`], it answers language selection requests and passes all other requests on to
+/// the wrapped [`Csi`]. [`StubCsi`](crate::StubCsi) and [`MockCsi`](crate::MockCsi) detect
+/// languages with the default detector.
+///
+/// ```
+/// use pharia_skill::{Csi, LanguageCode, SelectLanguageRequest};
+/// use pharia_skill_test::StubCsi;
+///
+/// let language = StubCsi.select_language(SelectLanguageRequest::new(
+///     "Ich habe heute leider keine Zeit, lass uns morgen telefonieren.",
+///     [LanguageCode::Eng, LanguageCode::Deu],
+/// ));
+/// assert_eq!(language, Some(LanguageCode::Deu));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LanguageDetector {
+    profiles: Arc<Vec<(LanguageCode, Profile)>>,
+    threshold: f64,
+}
+
+impl LanguageDetector {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            profiles: DEFAULT_PROFILES.clone(),
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// Only detect languages with at least this confidence, between 0 and 1. Defaults to 0.6.
+    #[must_use]
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Add a text in the language to its profile, e.g. from the domain of the skill, to detect
+    /// the language more reliably.
+    #[must_use]
+    pub fn with_sample(mut self, language: LanguageCode, text: &str) -> Self {
+        add_sample(Arc::make_mut(&mut self.profiles), language, text);
+        self
+    }
+
+    /// The requested language of the text, if any is detected confidently enough.
+    #[must_use]
+    pub fn detect(&self, request: &SelectLanguageRequest) -> Option<LanguageCode> {
+        let text = Profile::new(&request.text);
+        if text.is_empty() {
+            return None;
+        }
+        let likelihoods = self
+            .profiles
+            .iter()
+            .filter(|(language, _)| request.languages.contains(language))
+            .map(|(language, profile)| (*language, profile.log_likelihood(&text)))
+            .collect::<Vec<_>>();
+        let (language, best) = likelihoods
+            .iter()
+            .copied()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        // Share of the most likely language, computed relative to it to avoid underflows
+        let confidence = 1.0
+            / likelihoods
+                .iter()
+                .map(|(_, likelihood)| ((likelihood - best) * SHARPNESS).exp())
+                .sum::<f64>();
+        (confidence >= self.threshold).then_some(language)
+    }
+}
+
+impl Default for LanguageDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsiLayer for LanguageDetector {
+    fn select_language<C: Csi + ?Sized>(
+        &self,
+        _inner: &C,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        Ok(requests
+            .iter()
+            .map(|request| self.detect(request))
+            .collect())
+    }
+}
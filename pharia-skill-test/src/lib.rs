@@ -1,18 +1,127 @@
-use std::time::Duration;
+mod cassette;
+mod chunking;
+#[cfg(feature = "component")]
+mod component;
+mod document_index;
+mod export;
+mod language;
+mod mock;
+mod sse;
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    io::BufReader,
+    sync::Mutex,
+    time::Duration,
+};
 
+pub use cassette::{RecordingCsi, ReplayCsi};
+pub use chunking::{BpeTokenizer, LocalChunker, Tokenizer, WhitespaceTokenizer};
+#[cfg(feature = "component")]
+pub use component::{ComponentRunner, SkillError, SkillMetadata};
+pub use document_index::FakeDocumentIndex;
+#[cfg(feature = "tracing")]
+pub use export::JsonFileExporter;
+pub use export::{to_otlp_json, OtlpFileExporter};
+pub use language::LanguageDetector;
+use mock::{respond, Script};
+pub use mock::{Matcher, MockCsiBuilder, MockRequest, MockResponse, On};
 use pharia_skill::{
-    ChatRequest, ChatResponse, ChunkRequest, Completion, CompletionRequest, Csi, Document,
-    DocumentPath, FinishReason, LanguageCode, Message, SearchRequest, SearchResult,
-    SelectLanguageRequest, TokenUsage,
+    ChatEvent, ChatRequest, ChatResponse, Chunk, ChunkRequest, Completion, CompletionEvent,
+    CompletionRequest, Csi, CsiError, DetokenizeRequest, Document, DocumentPath, Embedding,
+    EmbeddingRequest, FinishReason, LanguageCode, LogRecord, Message, SearchRequest, SearchResult,
+    SelectLanguageRequest, TokenUsage, TokenizeRequest,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use ureq::{json, serde_json::Value, Agent, AgentBuilder};
+use sse::EventStream;
+use ureq::{json, Agent, AgentBuilder};
+
+/// Number of dimensions of the embeddings of [`StubCsi`] and [`MockCsi`], unless requested
+/// otherwise.
+const STUB_EMBEDDING_DIMENSIONS: u32 = 64;
+
+/// Embeddings which only depend on the words of a text, so texts sharing words are similar.
+/// Good enough to test deduplication or clustering without a model.
+fn bag_of_words_embeddings(requests: Vec<EmbeddingRequest>) -> Vec<Vec<Embedding>> {
+    requests
+        .into_iter()
+        .map(|request| {
+            let dimensions = request
+                .dimensions
+                .unwrap_or(STUB_EMBEDDING_DIMENSIONS)
+                .max(1);
+            request
+                .texts
+                .iter()
+                .map(|text| bag_of_words_embedding(text, dimensions))
+                .collect()
+        })
+        .collect()
+}
+
+fn bag_of_words_embedding(text: &str, dimensions: u32) -> Embedding {
+    let mut embedding = vec![0.0; dimensions as usize];
+    let words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty());
+    for word in words {
+        let mut hasher = DefaultHasher::new();
+        word.to_lowercase().hash(&mut hasher);
+        let index = usize::try_from(hasher.finish() % u64::from(dimensions))
+            .expect("index is smaller than the dimensions");
+        embedding[index] += 1.0;
+    }
+    embedding
+}
+
+/// Tokenizes texts into their UTF-8 bytes, so that [`StubCsi`] and [`MockCsi`] can count and
+/// round-trip tokens without a model.
+fn byte_tokenize(requests: Vec<TokenizeRequest>) -> Vec<Vec<u32>> {
+    requests
+        .into_iter()
+        .map(|request| request.text.bytes().map(u32::from).collect())
+        .collect()
+}
+
+fn byte_detokenize(requests: Vec<DetokenizeRequest>) -> Vec<String> {
+    requests
+        .into_iter()
+        .map(|request| {
+            let bytes = request
+                .tokens
+                .into_iter()
+                .map(|token| u8::try_from(token).unwrap_or(b'?'))
+                .collect::<Vec<_>>();
+            String::from_utf8_lossy(&bytes).into_owned()
+        })
+        .collect()
+}
+
+/// Deserialize the metadata of a document into a concrete type.
+fn deserialize_metadata<Metadata>(document: Document) -> Result<Document<Metadata>, CsiError>
+where
+    Metadata: for<'a> Deserialize<'a>,
+{
+    let Document {
+        path,
+        contents,
+        metadata,
+    } = document;
+    Ok(Document {
+        path,
+        contents,
+        metadata: metadata.map(serde_json::from_value).transpose()?,
+    })
+}
 
 pub struct StubCsi;
 
 impl Csi for StubCsi {
-    fn chat_concurrently(&self, requests: Vec<ChatRequest>) -> Vec<ChatResponse> {
-        requests
+    fn try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        Ok(requests
             .iter()
             .map(|_| ChatResponse {
                 message: Message::new("user", ""),
@@ -23,11 +132,14 @@ impl Csi for StubCsi {
                     completion: 0,
                 },
             })
-            .collect()
+            .collect())
     }
 
-    fn complete_concurrently(&self, requests: Vec<CompletionRequest>) -> Vec<Completion> {
-        requests
+    fn try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        Ok(requests
             .into_iter()
             .map(|request| Completion {
                 text: request.prompt,
@@ -38,66 +150,168 @@ impl Csi for StubCsi {
                     completion: 0,
                 },
             })
-            .collect()
+            .collect())
     }
 
-    fn chunk_concurrently(&self, requests: Vec<ChunkRequest>) -> Vec<Vec<String>> {
-        requests
+    fn try_embed_concurrently(
+        &self,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        Ok(bag_of_words_embeddings(requests))
+    }
+
+    fn try_tokenize_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        Ok(byte_tokenize(requests))
+    }
+
+    fn try_detokenize_concurrently(
+        &self,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        Ok(byte_detokenize(requests))
+    }
+
+    fn try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        Ok(self
+            .try_chunk_with_offsets_concurrently(requests)?
             .into_iter()
-            .map(|request| vec![request.text])
+            .map(|chunks| chunks.into_iter().map(|chunk| chunk.text).collect())
+            .collect())
+    }
+
+    fn try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        let chunker = LocalChunker::default();
+        requests
+            .iter()
+            .map(|request| chunker.chunk(request))
             .collect()
     }
 
-    fn select_language_concurrently(
+    fn try_select_language_concurrently(
         &self,
         requests: Vec<SelectLanguageRequest>,
-    ) -> Vec<Option<LanguageCode>> {
-        requests.iter().map(|_| None).collect()
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        let detector = LanguageDetector::default();
+        Ok(requests
+            .iter()
+            .map(|request| detector.detect(request))
+            .collect())
     }
 
-    fn search_concurrently(&self, _requests: Vec<SearchRequest>) -> Vec<Vec<SearchResult>> {
-        vec![]
+    fn try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        Ok(requests.iter().map(|_| vec![]).collect())
     }
 
-    fn documents<Metadata>(
+    fn try_documents<Metadata>(
         &self,
-        _paths: Vec<DocumentPath>,
-    ) -> anyhow::Result<Vec<Document<Metadata>>>
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a>,
     {
-        Ok(vec![])
+        Ok(paths
+            .into_iter()
+            .map(|path| Document {
+                path,
+                contents: vec![],
+                metadata: None,
+            })
+            .collect())
     }
 
-    fn documents_metadata<Metadata>(
+    fn try_documents_metadata<Metadata>(
         &self,
-        _paths: Vec<DocumentPath>,
-    ) -> anyhow::Result<Vec<Option<Metadata>>>
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a>,
     {
-        Ok(vec![])
+        Ok(paths.iter().map(|_| None).collect())
     }
 }
 
+/// Answers chat and completion requests with a fixed text, or according to a script built with
+/// [`MockCsi::builder`].
 pub struct MockCsi {
+    /// Answer to chat and completion requests no rule matches
     response: String,
+    script: Script,
+    /// Answers chunk requests no rule matches
+    chunker: LocalChunker,
+    /// Answers language selection requests no rule matches
+    language_detector: LanguageDetector,
+    logs: Mutex<Vec<LogRecord>>,
 }
 
 impl MockCsi {
+    /// Answer every chat and completion request with the response.
     #[must_use]
     pub fn new(response: impl Into<String>) -> Self {
+        Self::builder().fallback(response).build()
+    }
+
+    /// Script the responses to individual requests.
+    #[must_use]
+    pub fn builder() -> MockCsiBuilder {
+        MockCsiBuilder::new()
+    }
+
+    fn scripted(
+        response: String,
+        script: Script,
+        chunker: LocalChunker,
+        language_detector: LanguageDetector,
+    ) -> Self {
         Self {
-            response: response.into(),
+            response,
+            script,
+            chunker,
+            language_detector,
+            logs: Mutex::new(Vec::new()),
         }
     }
+
+    /// Check that every rule with an expected number of calls was called that often.
+    ///
+    /// # Panics
+    /// Will panic with a description of each rule which was not, so it can be used as an
+    /// assertion.
+    pub fn verify(&self) {
+        let failures = self.script.verify();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    /// All records logged so far, oldest first.
+    ///
+    /// # Panics
+    /// Will panic if another thread panicked while logging.
+    #[must_use]
+    pub fn logs(&self) -> Vec<LogRecord> {
+        self.logs.lock().unwrap().clone()
+    }
 }
 
-impl Csi for MockCsi {
-    fn chat_concurrently(&self, requests: Vec<ChatRequest>) -> Vec<ChatResponse> {
-        requests
-            .iter()
-            .map(|_| ChatResponse {
+impl MockCsi {
+    /// The text split into word-sized chunks, so streaming consumers see multiple chunks.
+    fn chunks(text: &str) -> Vec<String> {
+        text.split_inclusive(' ').map(ToOwned::to_owned).collect()
+    }
+
+    fn chat_response(&self, request: &ChatRequest) -> Result<ChatResponse, CsiError> {
+        respond(&self.script.chat, request).unwrap_or_else(|| {
+            Ok(ChatResponse {
                 message: Message::new("user", self.response.clone()),
                 finish_reason: FinishReason::Stop,
                 logprobs: vec![],
@@ -106,13 +320,12 @@ impl Csi for MockCsi {
                     completion: 0,
                 },
             })
-            .collect()
+        })
     }
 
-    fn complete_concurrently(&self, requests: Vec<CompletionRequest>) -> Vec<Completion> {
-        requests
-            .iter()
-            .map(|_| Completion {
+    fn completion(&self, request: &CompletionRequest) -> Result<Completion, CsiError> {
+        respond(&self.script.complete, request).unwrap_or_else(|| {
+            Ok(Completion {
                 text: self.response.clone(),
                 finish_reason: FinishReason::Stop,
                 logprobs: vec![],
@@ -121,49 +334,225 @@ impl Csi for MockCsi {
                     completion: 0,
                 },
             })
+        })
+    }
+
+    /// The scripted document, or an empty one if no rule matches.
+    fn document(&self, path: DocumentPath) -> Result<Document, CsiError> {
+        respond(&self.script.document, &path).unwrap_or_else(|| {
+            Ok(Document {
+                path,
+                contents: vec![],
+                metadata: None,
+            })
+        })
+    }
+}
+
+impl Csi for MockCsi {
+    fn log(&self, record: LogRecord) {
+        self.logs.lock().unwrap().push(record);
+    }
+
+    fn try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        requests
+            .iter()
+            .map(|request| self.chat_response(request))
             .collect()
     }
 
-    fn chunk_concurrently(&self, requests: Vec<ChunkRequest>) -> Vec<Vec<String>> {
+    fn try_chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+        let events = self.chat_response(&request).map(|response| {
+            let begin = ChatEvent::MessageBegin {
+                role: "assistant".to_owned(),
+            };
+            let appends = Self::chunks(&response.message.content)
+                .into_iter()
+                .map(|content| ChatEvent::MessageAppend {
+                    content,
+                    logprobs: vec![],
+                });
+            let tool_calls = response
+                .message
+                .tool_calls
+                .into_iter()
+                .map(|tool_call| ChatEvent::ToolCall { tool_call });
+            let end = [
+                ChatEvent::MessageEnd {
+                    finish_reason: response.finish_reason,
+                },
+                ChatEvent::Usage {
+                    usage: response.usage,
+                },
+            ];
+            std::iter::once(begin)
+                .chain(appends)
+                .chain(tool_calls)
+                .chain(end)
+                .map(Ok)
+        });
+        // A failing request yields the error as its only event.
+        let (events, error) = match events {
+            Ok(events) => (Some(events), None),
+            Err(error) => (None, Some(Err(error))),
+        };
+        events.into_iter().flatten().chain(error)
+    }
+
+    fn try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        requests
+            .iter()
+            .map(|request| self.completion(request))
+            .collect()
+    }
+
+    fn try_embed_concurrently(
+        &self,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        Ok(bag_of_words_embeddings(requests))
+    }
+
+    fn try_tokenize_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        Ok(byte_tokenize(requests))
+    }
+
+    fn try_detokenize_concurrently(
+        &self,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        Ok(byte_detokenize(requests))
+    }
+
+    fn try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+        let events = self.completion(&request).map(|completion| {
+            let appends =
+                Self::chunks(&completion.text)
+                    .into_iter()
+                    .map(|text| CompletionEvent::Append {
+                        text,
+                        logprobs: vec![],
+                    });
+            let end = [
+                CompletionEvent::End {
+                    finish_reason: completion.finish_reason,
+                },
+                CompletionEvent::Usage {
+                    usage: completion.usage,
+                },
+            ];
+            appends.chain(end).map(Ok)
+        });
+        // A failing request yields the error as its only event.
+        let (events, error) = match events {
+            Ok(events) => (Some(events), None),
+            Err(error) => (None, Some(Err(error))),
+        };
+        events.into_iter().flatten().chain(error)
+    }
+
+    fn try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        // Scripted chunks are returned as they are, they only need to be part of the text if
+        // their offsets are asked for.
         requests
             .into_iter()
-            .map(|request| vec![request.text])
+            .map(|request| match respond(&self.script.chunk, &request) {
+                Some(chunks) => chunks,
+                None => Ok(self
+                    .chunker
+                    .chunk(&request)?
+                    .into_iter()
+                    .map(|chunk| chunk.text)
+                    .collect()),
+            })
             .collect()
     }
 
-    fn select_language_concurrently(
+    fn try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        requests
+            .into_iter()
+            .map(|request| match respond(&self.script.chunk, &request) {
+                Some(chunks) => self.chunker.locate(&request.text, chunks?),
+                None => self.chunker.chunk(&request),
+            })
+            .collect()
+    }
+
+    fn try_select_language_concurrently(
         &self,
         requests: Vec<SelectLanguageRequest>,
-    ) -> Vec<Option<LanguageCode>> {
-        requests.iter().map(|_| None).collect()
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        requests
+            .iter()
+            .map(|request| {
+                respond(&self.script.select_language, request)
+                    .unwrap_or_else(|| Ok(self.language_detector.detect(request)))
+            })
+            .collect()
     }
 
-    fn search_concurrently(&self, _requests: Vec<SearchRequest>) -> Vec<Vec<SearchResult>> {
-        vec![]
+    fn try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        requests
+            .iter()
+            .map(|request| respond(&self.script.search, request).unwrap_or(Ok(vec![])))
+            .collect()
     }
 
-    fn documents<Metadata>(
+    fn try_documents<Metadata>(
         &self,
-        _paths: Vec<DocumentPath>,
-    ) -> anyhow::Result<Vec<Document<Metadata>>>
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a>,
     {
-        Ok(vec![])
+        paths
+            .into_iter()
+            .map(|path| deserialize_metadata(self.document(path)?))
+            .collect()
     }
 
-    fn documents_metadata<Metadata>(
+    fn try_documents_metadata<Metadata>(
         &self,
-        _paths: Vec<DocumentPath>,
-    ) -> anyhow::Result<Vec<Option<Metadata>>>
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a>,
     {
-        Ok(vec![])
+        paths
+            .into_iter()
+            .map(|path| {
+                let metadata = self.document(path)?.metadata;
+                Ok(metadata.map(serde_json::from_value).transpose()?)
+            })
+            .collect()
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 enum Function {
     Complete,
@@ -173,6 +562,13 @@ enum Function {
     Chat,
     Documents,
     DocumentMetadata,
+    CompletionStream,
+    ChatStream,
+    Embed,
+    Tokenize,
+    Detokenize,
+    CountTokens,
+    ChunkWithOffsets,
 }
 
 #[derive(Serialize)]
@@ -188,6 +584,7 @@ pub struct DevCsi {
     address: String,
     agent: Agent,
     token: String,
+    logs: Mutex<Vec<LogRecord>>,
 }
 
 impl DevCsi {
@@ -203,9 +600,20 @@ impl DevCsi {
             address: address.into(),
             agent,
             token: token.into(),
+            logs: Mutex::new(Vec::new()),
         }
     }
 
+    /// All records logged so far, oldest first. The development endpoint has no logging
+    /// function, so logs are kept locally.
+    ///
+    /// # Panics
+    /// Will panic if another thread panicked while logging.
+    #[must_use]
+    pub fn logs(&self) -> Vec<LogRecord> {
+        self.logs.lock().unwrap().clone()
+    }
+
     /// Construct a new [`DevCsi`] that points to the Aleph Alpha hosted Kernel
     pub fn aleph_alpha(token: impl Into<String>) -> Self {
         Self::new("https://pharia-kernel.product.pharia.com", token)
@@ -215,7 +623,7 @@ impl DevCsi {
         &self,
         function: Function,
         payload: impl Serialize,
-    ) -> anyhow::Result<R> {
+    ) -> Result<R, CsiError> {
         let json = CsiRequest {
             version: Self::VERSION,
             function,
@@ -228,79 +636,169 @@ impl DevCsi {
             .send_json(json);
 
         match response {
-            Ok(response) => Ok(response.into_json::<R>()?),
+            Ok(response) => response
+                .into_json::<R>()
+                .map_err(|e| CsiError::Decode(e.to_string())),
             Err(ureq::Error::Status(status, response)) => {
-                panic!(
-                    "Failed Request: Status {status} {}",
-                    response.into_json::<Value>().unwrap_or_default()
-                );
+                let body = response.into_string().unwrap_or_default();
+                Err(status_error(function, status, body))
             }
-            Err(e) => {
-                panic!("{e}")
+            Err(e) => Err(CsiError::Transport(e.to_string())),
+        }
+    }
+
+    /// Request a stream of server-sent events from the Kernel.
+    fn csi_stream<E: DeserializeOwned>(
+        &self,
+        function: Function,
+        payload: impl Serialize,
+    ) -> EventStream<E> {
+        let json = CsiRequest {
+            version: Self::VERSION,
+            function,
+            payload,
+        };
+        let response = self
+            .agent
+            .post(&format!("{}/csi", &self.address))
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("Accept", "text/event-stream")
+            .send_json(json);
+
+        match response {
+            Ok(response) => EventStream::new(BufReader::new(response.into_reader())),
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                EventStream::failed(status_error(function, status, body))
             }
+            Err(e) => EventStream::failed(CsiError::Transport(e.to_string())),
         }
     }
 }
 
+/// Map a failed HTTP response of the Kernel to the matching [`CsiError`].
+fn status_error(function: Function, status: u16, body: String) -> CsiError {
+    let message = format!("Status {status} {body}");
+    let lowercase = body.to_lowercase();
+    if lowercase.contains("model")
+        && (lowercase.contains("unknown") || lowercase.contains("not found"))
+    {
+        return CsiError::UnknownModel(message);
+    }
+    match status {
+        400 | 422 => CsiError::InvalidParameters(message),
+        404 | 501 => CsiError::UnsupportedFunction(format!("{function:?}: {message}")),
+        _ => CsiError::Transport(message),
+    }
+}
+
 impl Csi for DevCsi {
-    fn chat_concurrently(&self, requests: Vec<ChatRequest>) -> Vec<ChatResponse> {
+    fn log(&self, record: LogRecord) {
+        self.logs.lock().unwrap().push(record);
+    }
+
+    fn try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
         self.csi_request(Function::Chat, json!({"requests": requests}))
-            .unwrap()
     }
 
-    fn complete_concurrently(&self, requests: Vec<CompletionRequest>) -> Vec<Completion> {
+    fn try_chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+        self.csi_stream(Function::ChatStream, json!({"request": request}))
+    }
+
+    fn try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
         self.csi_request(Function::Complete, json!({"requests": requests}))
-            .unwrap()
     }
 
-    fn chunk_concurrently(&self, requests: Vec<ChunkRequest>) -> Vec<Vec<String>> {
+    fn try_embed_concurrently(
+        &self,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        self.csi_request(Function::Embed, json!({"requests": requests}))
+    }
+
+    fn try_tokenize_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        self.csi_request(Function::Tokenize, json!({"requests": requests}))
+    }
+
+    fn try_detokenize_concurrently(
+        &self,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        self.csi_request(Function::Detokenize, json!({"requests": requests}))
+    }
+
+    fn try_count_tokens_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        self.csi_request(Function::CountTokens, json!({"requests": requests}))
+    }
+
+    fn try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+        self.csi_stream(Function::CompletionStream, json!({"request": request}))
+    }
+
+    fn try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
         self.csi_request(Function::Chunk, json!({"requests": requests}))
-            .unwrap()
     }
 
-    fn select_language_concurrently(
+    fn try_select_language_concurrently(
         &self,
         requests: Vec<SelectLanguageRequest>,
-    ) -> Vec<Option<LanguageCode>> {
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
         self.csi_request(Function::SelectLanguage, json!({"requests": requests}))
-            .unwrap()
     }
 
-    fn search_concurrently(&self, requests: Vec<SearchRequest>) -> Vec<Vec<SearchResult>> {
+    fn try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        self.csi_request(Function::ChunkWithOffsets, json!({"requests": requests}))
+    }
+
+    fn try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
         self.csi_request(Function::Search, json!({"requests": requests}))
-            .unwrap()
     }
 
-    fn documents<Metadata>(
+    fn try_documents<Metadata>(
         &self,
         paths: Vec<DocumentPath>,
-    ) -> anyhow::Result<Vec<Document<Metadata>>>
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a> + Serialize,
     {
-        Ok(self
-            .csi_request::<Vec<Document<Metadata>>>(
-                Function::Documents,
-                json!({"requests": paths}),
-            )?
-            .into_iter()
-            .collect())
+        self.csi_request(Function::Documents, json!({"requests": paths}))
     }
 
-    fn documents_metadata<Metadata>(
+    fn try_documents_metadata<Metadata>(
         &self,
         paths: Vec<DocumentPath>,
-    ) -> anyhow::Result<Vec<Option<Metadata>>>
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a> + Serialize,
     {
-        Ok(self
-            .csi_request::<Vec<Option<Metadata>>>(
-                Function::DocumentMetadata,
-                json!({"requests": paths}),
-            )?
-            .into_iter()
-            .collect())
+        self.csi_request(Function::DocumentMetadata, json!({"requests": paths}))
     }
 }
 
@@ -308,17 +806,163 @@ impl Csi for DevCsi {
 mod tests {
     use jiff::Timestamp;
     use pharia_skill::{
-        ChatParams, ChunkParams, ChunkRequest, CompletionParams, IndexPath, Modality,
+        cosine_similarity, ChatParams, ChunkParams, ChunkRequest, CompletionParams, IndexPath,
+        Modality,
     };
+    use schemars::JsonSchema;
 
     use super::*;
 
     #[test]
-    fn can_make_request() {
+    fn status_errors_are_mapped_to_csi_errors() {
+        let unknown_model = status_error(
+            Function::Chat,
+            400,
+            "Sorry, we could not find the model you requested: unknown model 'foo'".to_owned(),
+        );
+        let invalid = status_error(Function::Chunk, 400, "overlap too large".to_owned());
+        let unsupported = status_error(Function::Search, 404, String::new());
+        let unavailable = status_error(Function::Complete, 503, String::new());
+
+        assert!(matches!(unknown_model, CsiError::UnknownModel(_)));
+        assert!(matches!(invalid, CsiError::InvalidParameters(_)));
+        assert!(matches!(unsupported, CsiError::UnsupportedFunction(_)));
+        assert!(matches!(unavailable, CsiError::Transport(_)));
+    }
+
+    #[test]
+    fn mock_csi_streams_response_in_chunks() {
+        let csi = MockCsi::new("Hello, world!");
+
+        let content = csi
+            .chat_stream(ChatRequest::new("model", Message::user("Hi")))
+            .filter_map(|event| match event {
+                ChatEvent::MessageAppend { content, .. } => Some(content),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(content, vec!["Hello, ", "world!"]);
+    }
+
+    #[test]
+    fn mock_csi_answers_structured() {
+        #[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+        struct Weather {
+            city: String,
+            temperature: i32,
+        }
+        let csi = MockCsi::new("Sure! {\"city\": \"Heidelberg\", \"temperature\": 21}");
+
+        let output = csi.chat_structured::<Weather>(ChatRequest::new("model", Message::user("Hi")));
+
+        assert_eq!(
+            output.value,
+            Weather {
+                city: "Heidelberg".to_owned(),
+                temperature: 21
+            }
+        );
+        assert!(output.response.message.content.starts_with("Sure!"));
+    }
+
+    #[test]
+    fn invalid_structured_answer_is_a_decode_error() {
+        let csi = MockCsi::new("{\"temperature\": \"warm\"}");
+
+        let error = csi
+            .try_chat_structured::<u32>(ChatRequest::new("model", Message::user("Hi")))
+            .unwrap_err();
+
+        assert!(matches!(error, CsiError::Decode(_)));
+    }
+
+    #[test]
+    fn stub_embeddings_of_texts_sharing_words_are_similar() {
+        let embeddings = StubCsi.embed(EmbeddingRequest::new(
+            "model",
+            [
+                "The cat sat on the mat".to_owned(),
+                "the CAT sat on the mat!".to_owned(),
+                "Quarterly revenue grew".to_owned(),
+            ],
+        ));
+
+        assert_eq!(embeddings[0].len(), STUB_EMBEDDING_DIMENSIONS as usize);
+        assert!((cosine_similarity(&embeddings[0], &embeddings[1]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&embeddings[0], &embeddings[2]) < 0.5);
+    }
+
+    #[test]
+    fn stub_csi_round_trips_tokens() {
+        let tokens = StubCsi.tokenize(TokenizeRequest::new("model", "Grüß Gott"));
+        let count = StubCsi.count_tokens(TokenizeRequest::new("model", "Grüß Gott"));
+        let text = StubCsi.detokenize(DetokenizeRequest::new("model", tokens.clone()));
+
+        assert_eq!(count, 11);
+        assert_eq!(tokens.len(), 11);
+        assert_eq!(text, "Grüß Gott");
+    }
+
+    #[test]
+    fn stub_csi_chunks_with_offsets() {
+        let request = ChunkRequest::new("Hello, world!", ChunkParams::new("model", 10));
+
+        let chunks = StubCsi.chunk_with_offsets(request);
+
+        assert_eq!(
+            chunks,
+            vec![Chunk {
+                text: "Hello, world!".to_owned(),
+                byte_offset: 0,
+                char_offset: 0,
+                token_count: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn stub_csi_emulates_completion_stream() {
+        let events = StubCsi
+            .completion_stream(CompletionRequest::new("model", "Hello"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            vec![
+                CompletionEvent::Append {
+                    text: "Hello".to_owned(),
+                    logprobs: vec![]
+                },
+                CompletionEvent::End {
+                    finish_reason: FinishReason::Stop
+                },
+                CompletionEvent::Usage {
+                    usage: TokenUsage {
+                        prompt: 0,
+                        completion: 0
+                    }
+                },
+            ]
+        );
+    }
+
+    /// A [`DevCsi`] talking to the hosted Kernel, or `None` if no `PHARIA_AI_TOKEN` is set.
+    ///
+    /// These tests check the wire format against the real Kernel, so they run wherever the token
+    /// is available, e.g. in CI.
+    fn kernel_csi() -> Option<DevCsi> {
         drop(dotenvy::dotenv());
 
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let token = std::env::var("PHARIA_AI_TOKEN").ok()?;
+        Some(DevCsi::aleph_alpha(token))
+    }
+
+    #[test]
+    fn can_make_request() {
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let response = csi.complete(
             CompletionRequest::new(
@@ -346,10 +990,9 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
 
     #[test]
     fn can_make_multiple_requests() {
-        drop(dotenvy::dotenv());
-
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let params = CompletionParams {
             stop: vec!["<|start_header_id|>".into()],
@@ -377,10 +1020,9 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
 
     #[test]
     fn chunk() {
-        drop(dotenvy::dotenv());
-
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let response = csi.chunk(ChunkRequest::new(
             "123456",
@@ -391,11 +1033,28 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
     }
 
     #[test]
-    fn select_language() {
-        drop(dotenvy::dotenv());
+    fn chunk_with_offsets() {
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let response = csi.chunk_with_offsets(ChunkRequest::new(
+            "123456",
+            ChunkParams::new("llama-3.1-8b-instruct", 1),
+        ));
+
+        let offsets = response
+            .iter()
+            .map(|chunk| (chunk.text.as_str(), chunk.byte_offset))
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [("123", 0), ("456", 3)]);
+    }
+
+    #[test]
+    fn select_language() {
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let response = csi.select_language(SelectLanguageRequest::new(
             "A rising tide lifts all boats",
@@ -407,10 +1066,9 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
 
     #[test]
     fn search() {
-        drop(dotenvy::dotenv());
-
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let response = csi.search(
             SearchRequest::new("decoder", IndexPath::new("Kernel", "test", "asym-64"))
@@ -422,10 +1080,9 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
 
     #[test]
     fn chat() {
-        drop(dotenvy::dotenv());
-
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let request = ChatRequest::new(
             "llama-3.1-8b-instruct",
@@ -440,6 +1097,70 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
         assert!(!response.message.content.is_empty());
     }
 
+    #[test]
+    fn count_tokens() {
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
+
+        let request = TokenizeRequest::new("llama-3.1-8b-instruct", "Hello, how are you?");
+        let tokens = csi.tokenize(request.clone());
+        let count = csi.count_tokens(request);
+
+        assert_eq!(tokens.len(), count as usize);
+    }
+
+    #[test]
+    fn detokenize() {
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
+
+        let tokens = csi.tokenize(TokenizeRequest::new("llama-3.1-8b-instruct", "Hello"));
+        let text = csi.detokenize(DetokenizeRequest::new("llama-3.1-8b-instruct", tokens));
+
+        assert!(text.contains("Hello"));
+    }
+
+    #[test]
+    fn embed() {
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
+
+        let embeddings = csi.embed(EmbeddingRequest::new(
+            "luminous-base",
+            ["A rising tide".to_owned(), "lifts all boats".to_owned()],
+        ));
+
+        assert_eq!(embeddings.len(), 2);
+        assert!(!embeddings[0].is_empty());
+        assert_eq!(embeddings[0].len(), embeddings[1].len());
+    }
+
+    #[test]
+    fn chat_stream() {
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
+
+        let request = ChatRequest::new(
+            "llama-3.1-8b-instruct",
+            Message::user("Hello, how are you?"),
+        )
+        .with_params(ChatParams {
+            max_tokens: Some(10),
+            ..Default::default()
+        });
+        let events = csi.chat_stream(request).collect::<Vec<_>>();
+
+        assert!(matches!(
+            events.first(),
+            Some(ChatEvent::MessageBegin { .. })
+        ));
+        assert!(matches!(events.last(), Some(ChatEvent::Usage { .. })));
+    }
+
     #[test]
     fn documents() {
         #[derive(Debug, Deserialize, Serialize)]
@@ -448,10 +1169,9 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
             url: String,
         }
 
-        drop(dotenvy::dotenv());
-
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let path = DocumentPath::new("Kernel", "test", "kernel-docs");
         let response = csi.document::<Metadata>(path.clone()).unwrap();
@@ -471,10 +1191,9 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
             url: String,
         }
 
-        drop(dotenvy::dotenv());
-
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let path = DocumentPath::new("Kernel", "test", "kernel-docs");
         let response = csi.document_metadata::<Metadata>(path.clone()).unwrap();
@@ -484,10 +1203,9 @@ What is the capital of France?<|eot_id|><|start_header_id|>assistant<|end_header
 
     #[test]
     fn invalid_metadata() {
-        drop(dotenvy::dotenv());
-
-        let token = std::env::var("PHARIA_AI_TOKEN").unwrap();
-        let csi = DevCsi::aleph_alpha(token);
+        let Some(csi) = kernel_csi() else {
+            return;
+        };
 
         let path = DocumentPath::new("Kernel", "test", "kernel-docs");
         let response = csi.document_metadata::<String>(path.clone());
@@ -0,0 +1,456 @@
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use pharia_skill::{
+    ChatRequest, ChatResponse, ChunkRequest, Completion, CompletionRequest, CsiError, Document,
+    DocumentPath, FinishReason, LanguageCode, Message, Modality, SearchRequest, SearchResult,
+    SelectLanguageRequest, TokenUsage,
+};
+use regex::Regex;
+
+use crate::{LanguageDetector, LocalChunker, MockCsi};
+
+/// A request a [`MockCsi`] can be scripted for.
+pub trait MockRequest {
+    /// The model the request is sent to, if any.
+    fn model(&self) -> Option<&str> {
+        None
+    }
+
+    /// The text [`Matcher::regex`] is matched against.
+    fn text(&self) -> Cow<'_, str>;
+}
+
+impl MockRequest for ChatRequest {
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    /// The contents of all messages, one per line.
+    fn text(&self) -> Cow<'_, str> {
+        self.messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+            .into()
+    }
+}
+
+impl MockRequest for CompletionRequest {
+    fn model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.prompt)
+    }
+}
+
+impl MockRequest for SearchRequest {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.query)
+    }
+}
+
+impl MockRequest for ChunkRequest {
+    fn model(&self) -> Option<&str> {
+        Some(&self.params.model)
+    }
+
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.text)
+    }
+}
+
+impl MockRequest for DocumentPath {
+    /// The path as `namespace/collection/name`.
+    fn text(&self) -> Cow<'_, str> {
+        format!("{}/{}/{}", self.namespace, self.collection, self.name).into()
+    }
+}
+
+impl MockRequest for SelectLanguageRequest {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.text)
+    }
+}
+
+/// Common request matchers for the `on_*` methods of [`MockCsiBuilder`]. Any closure taking a
+/// reference to the request and returning a `bool` is a matcher as well.
+pub struct Matcher;
+
+impl Matcher {
+    /// Matches every request.
+    pub fn any<R>() -> impl Fn(&R) -> bool + Send + Sync + 'static {
+        |_| true
+    }
+
+    /// Matches requests sent to this model.
+    pub fn model<R: MockRequest>(
+        model: impl Into<String>,
+    ) -> impl Fn(&R) -> bool + Send + Sync + 'static {
+        let model = model.into();
+        move |request| request.model() == Some(model.as_str())
+    }
+
+    /// Matches requests whose [`MockRequest::text`] matches the regular expression.
+    ///
+    /// # Panics
+    /// Will panic if the pattern is not a valid regular expression.
+    pub fn regex<R: MockRequest>(
+        pattern: impl AsRef<str>,
+    ) -> impl Fn(&R) -> bool + Send + Sync + 'static {
+        let regex = Regex::new(pattern.as_ref()).expect("matcher pattern must be a valid regex");
+        move |request| regex.is_match(&request.text())
+    }
+}
+
+/// Something a scripted [`MockCsi`] can answer a request of type `R` with.
+///
+/// Besides the response type itself, e.g. [`ChatResponse`], this is implemented for shorthands
+/// like the text of a chat or completion response, for a [`CsiError`] to fail the request, and
+/// for a `Result` of both.
+pub trait MockResponse<R, T>: Clone + Send + Sync + 'static {
+    /// The response to the request, or the error it fails with.
+    ///
+    /// # Errors
+    /// Returns the error the mock is scripted to fail with.
+    fn into_response(self, request: &R) -> Result<T, CsiError>;
+}
+
+macro_rules! response_is_itself {
+    ($($request:ty => $response:ty),* $(,)?) => {
+        $(impl MockResponse<$request, $response> for $response {
+            fn into_response(self, _request: &$request) -> Result<$response, CsiError> {
+                Ok(self)
+            }
+        })*
+    };
+}
+
+response_is_itself!(
+    ChatRequest => ChatResponse,
+    CompletionRequest => Completion,
+    SearchRequest => Vec<SearchResult>,
+    ChunkRequest => Vec<String>,
+    DocumentPath => Document,
+    SelectLanguageRequest => Option<LanguageCode>,
+);
+
+fn no_usage() -> TokenUsage {
+    TokenUsage {
+        prompt: 0,
+        completion: 0,
+    }
+}
+
+macro_rules! response_from_text {
+    ($($text:ty),*) => {
+        $(
+            /// An assistant message with the text.
+            impl MockResponse<ChatRequest, ChatResponse> for $text {
+                fn into_response(self, _request: &ChatRequest) -> Result<ChatResponse, CsiError> {
+                    Ok(ChatResponse {
+                        message: Message::assistant(self),
+                        finish_reason: FinishReason::Stop,
+                        logprobs: vec![],
+                        usage: no_usage(),
+                    })
+                }
+            }
+
+            impl MockResponse<CompletionRequest, Completion> for $text {
+                fn into_response(self, _request: &CompletionRequest) -> Result<Completion, CsiError> {
+                    Ok(Completion {
+                        text: self.into(),
+                        finish_reason: FinishReason::Stop,
+                        logprobs: vec![],
+                        usage: no_usage(),
+                    })
+                }
+            }
+
+            /// A text document at the requested path, without metadata.
+            impl MockResponse<DocumentPath, Document> for $text {
+                fn into_response(self, request: &DocumentPath) -> Result<Document, CsiError> {
+                    Ok(Document {
+                        path: request.clone(),
+                        contents: vec![Modality::Text { text: self.into() }],
+                        metadata: None,
+                    })
+                }
+            }
+        )*
+    };
+}
+
+response_from_text!(&'static str, String);
+
+impl MockResponse<ChunkRequest, Vec<String>> for Vec<&'static str> {
+    fn into_response(self, _request: &ChunkRequest) -> Result<Vec<String>, CsiError> {
+        Ok(self.into_iter().map(ToOwned::to_owned).collect())
+    }
+}
+
+impl MockResponse<SelectLanguageRequest, Option<LanguageCode>> for LanguageCode {
+    fn into_response(
+        self,
+        _request: &SelectLanguageRequest,
+    ) -> Result<Option<LanguageCode>, CsiError> {
+        Ok(Some(self))
+    }
+}
+
+impl<R, T> MockResponse<R, T> for CsiError {
+    fn into_response(self, _request: &R) -> Result<T, CsiError> {
+        Err(self)
+    }
+}
+
+impl<R, T, M: MockResponse<R, T>> MockResponse<R, T> for Result<M, CsiError> {
+    fn into_response(self, request: &R) -> Result<T, CsiError> {
+        self?.into_response(request)
+    }
+}
+
+type Respond<R, T> = Box<dyn Fn(&R) -> Result<T, CsiError> + Send + Sync>;
+
+/// Answers the requests matched by one `on_*` call of the builder.
+pub(crate) struct Rule<R, T> {
+    /// Names the rule in failed verifications, e.g. `on_chat rule 2`
+    name: String,
+    matcher: Box<dyn Fn(&R) -> bool + Send + Sync>,
+    /// Used in order, the last one is repeated
+    responses: Vec<Respond<R, T>>,
+    expected_calls: Option<usize>,
+    calls: AtomicUsize,
+}
+
+impl<R, T> Rule<R, T> {
+    fn verify(&self) -> Option<String> {
+        let calls = self.calls.load(Ordering::Relaxed);
+        let expected = self.expected_calls?;
+        (calls != expected).then(|| {
+            format!(
+                "{} was expected to be called {expected} time(s), but was called {calls} time(s)",
+                self.name
+            )
+        })
+    }
+}
+
+/// The answer of the first rule matching the request, if any.
+pub(crate) fn respond<R, T>(rules: &[Rule<R, T>], request: &R) -> Option<Result<T, CsiError>> {
+    let rule = rules.iter().find(|rule| (rule.matcher)(request))?;
+    let call = rule.calls.fetch_add(1, Ordering::Relaxed);
+    let respond = rule.responses.get(call).or(rule.responses.last())?;
+    Some(respond(request))
+}
+
+/// The rules a [`MockCsi`] answers requests with, per function.
+#[derive(Default)]
+pub(crate) struct Script {
+    pub(crate) chat: Vec<Rule<ChatRequest, ChatResponse>>,
+    pub(crate) complete: Vec<Rule<CompletionRequest, Completion>>,
+    pub(crate) search: Vec<Rule<SearchRequest, Vec<SearchResult>>>,
+    pub(crate) chunk: Vec<Rule<ChunkRequest, Vec<String>>>,
+    pub(crate) document: Vec<Rule<DocumentPath, Document>>,
+    pub(crate) select_language: Vec<Rule<SelectLanguageRequest, Option<LanguageCode>>>,
+}
+
+impl Script {
+    /// A description of every rule which was not called as often as expected.
+    pub(crate) fn verify(&self) -> Vec<String> {
+        fn failures<R, T>(rules: &[Rule<R, T>]) -> impl Iterator<Item = String> + '_ {
+            rules.iter().filter_map(Rule::verify)
+        }
+        failures(&self.chat)
+            .chain(failures(&self.complete))
+            .chain(failures(&self.search))
+            .chain(failures(&self.chunk))
+            .chain(failures(&self.document))
+            .chain(failures(&self.select_language))
+            .collect()
+    }
+}
+
+/// Builds a [`MockCsi`] which answers requests according to a script.
+///
+/// Each `on_*` method adds a rule for the requests of one function matched by a [`Matcher`] or a
+/// closure, which is completed by specifying the response. The first rule matching a request
+/// answers it. Chat and completion requests no rule matches are answered with the fallback
+/// text, other requests with empty responses.
+///
+/// ```
+/// use pharia_skill::{ChatRequest, Csi, CsiError, Message};
+/// use pharia_skill_test::{Matcher, MockCsi};
+///
+/// let csi = MockCsi::builder()
+///     .on_chat(Matcher::regex("(?i)weather"))
+///     .times(2)
+///     .respond_in_order([Err(CsiError::Transport("timeout".to_owned())), Ok("Sunny")])
+///     .on_chat(Matcher::model("llama-3.3-70b-instruct"))
+///     .respond("Hello")
+///     .fallback("I don't know")
+///     .build();
+///
+/// let weather = ChatRequest::new("model", Message::user("How is the weather?"));
+/// assert!(csi.try_chat(weather.clone()).is_err());
+/// assert_eq!(csi.chat(weather).message.content, "Sunny");
+/// csi.verify();
+/// ```
+pub struct MockCsiBuilder {
+    fallback: String,
+    script: Script,
+    chunker: LocalChunker,
+    language_detector: LanguageDetector,
+}
+
+/// A rule of a [`MockCsiBuilder`] which still lacks its response.
+#[must_use = "the rule is only added once its response is specified"]
+pub struct On<R, T> {
+    builder: MockCsiBuilder,
+    name: String,
+    matcher: Box<dyn Fn(&R) -> bool + Send + Sync>,
+    expected_calls: Option<usize>,
+    rules: fn(&mut Script) -> &mut Vec<Rule<R, T>>,
+}
+
+macro_rules! on {
+    ($($(#[$doc:meta])* $name:ident: $request:ty => $response:ty, $field:ident;)*) => {
+        $($(#[$doc])*
+        pub fn $name(
+            self,
+            matcher: impl Fn(&$request) -> bool + Send + Sync + 'static,
+        ) -> On<$request, $response> {
+            let name = format!("{} rule {}", stringify!($name), self.script.$field.len() + 1);
+            On {
+                builder: self,
+                name,
+                matcher: Box::new(matcher),
+                expected_calls: None,
+                rules: |script| &mut script.$field,
+            }
+        })*
+    };
+}
+
+impl MockCsiBuilder {
+    pub(crate) fn new() -> Self {
+        Self {
+            fallback: String::new(),
+            script: Script::default(),
+            chunker: LocalChunker::default(),
+            language_detector: LanguageDetector::default(),
+        }
+    }
+
+    /// The text of chat and completion responses to requests no rule matches. Empty by default.
+    #[must_use]
+    pub fn fallback(mut self, response: impl Into<String>) -> Self {
+        self.fallback = response.into();
+        self
+    }
+
+    /// Chunk texts no rule matches with the chunker. By default, chunks are counted in words.
+    #[must_use]
+    pub fn chunker(mut self, chunker: LocalChunker) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    /// Detect the language of texts no rule matches with the detector.
+    #[must_use]
+    pub fn language_detector(mut self, language_detector: LanguageDetector) -> Self {
+        self.language_detector = language_detector;
+        self
+    }
+
+    on! {
+        /// Answer the matched chat requests, including streamed ones.
+        on_chat: ChatRequest => ChatResponse, chat;
+        /// Answer the matched completion requests, including streamed ones.
+        on_complete: CompletionRequest => Completion, complete;
+        on_search: SearchRequest => Vec<SearchResult>, search;
+        on_chunk: ChunkRequest => Vec<String>, chunk;
+        /// Answer requests for the matched documents, and for their metadata.
+        on_document: DocumentPath => Document, document;
+        on_select_language: SelectLanguageRequest => Option<LanguageCode>, select_language;
+    }
+
+    #[must_use]
+    pub fn build(self) -> MockCsi {
+        MockCsi::scripted(
+            self.fallback,
+            self.script,
+            self.chunker,
+            self.language_detector,
+        )
+    }
+}
+
+impl<R: 'static, T: 'static> On<R, T> {
+    /// Expect the rule to answer exactly `calls` requests, checked by [`MockCsi::verify`].
+    pub fn times(mut self, calls: usize) -> Self {
+        self.expected_calls = Some(calls);
+        self
+    }
+
+    /// Answer every matched request with the response.
+    #[must_use]
+    pub fn respond(self, response: impl MockResponse<R, T>) -> MockCsiBuilder {
+        self.respond_in_order([response])
+    }
+
+    /// Answer the matched requests with the responses in order, repeating the last one once
+    /// all have been used.
+    ///
+    /// # Panics
+    /// Will panic if there are no responses.
+    #[must_use]
+    pub fn respond_in_order<M: MockResponse<R, T>>(
+        self,
+        responses: impl IntoIterator<Item = M>,
+    ) -> MockCsiBuilder {
+        let responses = responses
+            .into_iter()
+            .map(|response| -> Respond<R, T> {
+                Box::new(move |request| response.clone().into_response(request))
+            })
+            .collect::<Vec<_>>();
+        assert!(!responses.is_empty(), "{} needs a response", self.name);
+        self.add(responses)
+    }
+
+    /// Answer every matched request with the response computed from it.
+    #[must_use]
+    pub fn respond_with<M: MockResponse<R, T>>(
+        self,
+        respond: impl Fn(&R) -> M + Send + Sync + 'static,
+    ) -> MockCsiBuilder {
+        self.add(vec![Box::new(move |request| {
+            respond(request).into_response(request)
+        })])
+    }
+
+    fn add(self, responses: Vec<Respond<R, T>>) -> MockCsiBuilder {
+        let Self {
+            mut builder,
+            name,
+            matcher,
+            expected_calls,
+            rules,
+        } = self;
+        rules(&mut builder.script).push(Rule {
+            name,
+            matcher,
+            responses,
+            expected_calls,
+            calls: AtomicUsize::new(0),
+        });
+        builder
+    }
+}
@@ -0,0 +1,164 @@
+use std::{io::BufRead, marker::PhantomData};
+
+use pharia_skill::CsiError;
+use serde::de::DeserializeOwned;
+use ureq::serde_json::{self, Map, Value};
+
+/// Iterator over the events of a server-sent event stream.
+///
+/// The name of each event selects the variant of `E`, the data of the event is its JSON payload.
+/// An `error` event, as well as any failure to read or decode an event, ends the stream.
+pub struct EventStream<E> {
+    reader: Option<Box<dyn BufRead + Send>>,
+    error: Option<CsiError>,
+    _event: PhantomData<E>,
+}
+
+impl<E> EventStream<E> {
+    pub fn new(reader: impl BufRead + Send + 'static) -> Self {
+        Self {
+            reader: Some(Box::new(reader)),
+            error: None,
+            _event: PhantomData,
+        }
+    }
+
+    /// A stream which only reports the error that prevented it from being opened.
+    pub fn failed(error: CsiError) -> Self {
+        Self {
+            reader: None,
+            error: Some(error),
+            _event: PhantomData,
+        }
+    }
+
+    /// Read the name and data of the next event, if there is one.
+    fn read_event(&mut self) -> Option<Result<(String, String), CsiError>> {
+        let reader = self.reader.as_mut()?;
+        let mut event = String::new();
+        let mut data = String::new();
+        loop {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(CsiError::Transport(e.to_string()))),
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                if data.is_empty() {
+                    continue;
+                }
+                return Some(Ok((event, data)));
+            }
+            // Comments and fields other than `event` and `data` are ignored
+            if let Some(value) = line.strip_prefix("event:") {
+                value.trim().clone_into(&mut event);
+            } else if let Some(value) = line.strip_prefix("data:") {
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value.strip_prefix(' ').unwrap_or(value));
+            }
+        }
+    }
+}
+
+impl<E: DeserializeOwned> Iterator for EventStream<E> {
+    type Item = Result<E, CsiError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        let result = match self.read_event()? {
+            Ok((event, data)) if event == "error" => Err(CsiError::Transport(data)),
+            Ok((event, data)) => serde_json::from_str::<Value>(&data)
+                .and_then(|data| {
+                    serde_json::from_value(Value::Object(Map::from_iter([(event, data)])))
+                })
+                .map_err(CsiError::from),
+            Err(error) => Err(error),
+        };
+        if result.is_err() {
+            self.reader = None;
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pharia_skill::{ChatEvent, FinishReason, TokenUsage, ToolCall};
+
+    use super::*;
+
+    #[test]
+    fn parses_chat_events() {
+        let body = "event: message_begin\ndata: {\"role\":\"assistant\"}\n\n\
+            event: message_append\ndata: {\"content\":\"Hello\",\"logprobs\":[]}\n\n\
+            : keep-alive\n\n\
+            event: message_end\ndata: {\"finish_reason\":\"stop\"}\n\n\
+            event: usage\ndata: {\"usage\":{\"prompt\":1,\"completion\":2}}\n\n";
+
+        let events = EventStream::<ChatEvent>::new(body.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                ChatEvent::MessageBegin {
+                    role: "assistant".to_owned()
+                },
+                ChatEvent::MessageAppend {
+                    content: "Hello".to_owned(),
+                    logprobs: vec![]
+                },
+                ChatEvent::MessageEnd {
+                    finish_reason: FinishReason::Stop
+                },
+                ChatEvent::Usage {
+                    usage: TokenUsage {
+                        prompt: 1,
+                        completion: 2
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_tool_calls() {
+        let body = "event: tool_call\n\
+            data: {\"tool_call\":{\"id\":\"call-1\",\"name\":\"search\",\"arguments\":\"{}\"}}\n\n";
+
+        let events = EventStream::<ChatEvent>::new(body.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            events,
+            vec![ChatEvent::ToolCall {
+                tool_call: ToolCall {
+                    id: "call-1".to_owned(),
+                    name: "search".to_owned(),
+                    arguments: "{}".to_owned(),
+                }
+            }]
+        );
+    }
+
+    #[test]
+    fn error_event_ends_the_stream() {
+        let body = "event: error\ndata: model overloaded\n\n\
+            event: message_begin\ndata: {\"role\":\"assistant\"}\n\n";
+
+        let events = EventStream::<ChatEvent>::new(body.as_bytes()).collect::<Vec<_>>();
+
+        assert_eq!(
+            events,
+            vec![Err(CsiError::Transport("model overloaded".to_owned()))]
+        );
+    }
+}
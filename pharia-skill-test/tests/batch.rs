@@ -0,0 +1,105 @@
+use std::sync::Mutex;
+
+use pharia_skill::{
+    Batch, ChatRequest, ChatResponse, CompletionRequest, Csi, CsiError, CsiExt, CsiLayer,
+    DocumentPath, IndexPath, LanguageCode, Message, SearchRequest, SearchResult,
+    SelectLanguageRequest, TokenizeRequest,
+};
+use pharia_skill_test::{MockCsi, StubCsi};
+
+/// Records the size of every chat and search batch passing through.
+#[derive(Default)]
+struct RecordCalls(Mutex<Vec<(&'static str, usize)>>);
+
+impl CsiLayer for RecordCalls {
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.0.lock().unwrap().push(("chat", requests.len()));
+        inner.try_chat_concurrently(requests)
+    }
+
+    fn search<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        self.0.lock().unwrap().push(("search", requests.len()));
+        inner.try_search_concurrently(requests)
+    }
+}
+
+fn search_request(query: &str) -> SearchRequest {
+    SearchRequest::new(query, IndexPath::new("namespace", "collection", "index"))
+}
+
+#[test]
+fn each_function_is_called_once_per_batch() {
+    let csi = MockCsi::new("Hello").layer(RecordCalls::default());
+    let mut batch = Batch::new();
+    let first = batch.search(search_request("first"));
+    let chat = batch.chat(ChatRequest::new("model", Message::user("Hi")));
+    let second = batch.search(search_request("second"));
+    let language = batch.select_language(SelectLanguageRequest::new(
+        "Hallo".to_owned(),
+        vec![LanguageCode::Deu],
+    ));
+
+    let mut responses = batch.send(&csi);
+
+    assert_eq!(responses.take(chat).message.content, "Hello");
+    assert!(responses.take(first).is_empty());
+    assert!(responses.take(second).is_empty());
+    assert_eq!(responses.take(language), Some(LanguageCode::Deu));
+    assert_eq!(
+        *csi.layer.0.lock().unwrap(),
+        vec![("search", 2), ("chat", 1)]
+    );
+}
+
+#[test]
+fn responses_match_their_handles() {
+    let mut batch = Batch::new();
+    let tokens = batch.tokenize(TokenizeRequest::new("model", "ab"));
+    let completion = batch.complete(CompletionRequest::new("model", "first"));
+    let count = batch.count_tokens(TokenizeRequest::new("model", "abc"));
+    let metadata = batch.document_metadata::<String>(DocumentPath::new("n", "c", "d"));
+    let document = batch.document::<String>(DocumentPath::new("n", "c", "d"));
+
+    let mut responses = batch.send(&StubCsi);
+
+    assert_eq!(responses.take(completion).text, "first");
+    assert_eq!(responses.take(count), 3);
+    assert_eq!(responses.take(tokens), vec![97, 98]);
+    assert_eq!(responses.take(metadata), None);
+    assert_eq!(
+        responses.take(document).path,
+        DocumentPath::new("n", "c", "d")
+    );
+}
+
+#[test]
+fn handle_of_other_batch_is_rejected() {
+    let mut batch = Batch::new();
+    batch.chat(ChatRequest::new("model", Message::user("Hi")));
+    let mut other = Batch::new();
+    other.chat(ChatRequest::new("model", Message::user("Hi")));
+    let foreign = other.tokenize(TokenizeRequest::new("model", "a"));
+
+    let mut responses = batch.send(&StubCsi);
+
+    assert!(responses.try_take(foreign).is_err());
+}
+
+#[test]
+fn dyn_csi_sends_batches() {
+    let csi: Box<dyn pharia_skill::DynCsi> = Box::new(MockCsi::new("Hello"));
+    let mut batch = Batch::new();
+    let chat = batch.chat(ChatRequest::new("model", Message::user("Hi")));
+
+    let mut responses = batch.send(&*csi);
+
+    assert_eq!(responses.take(chat).message.content, "Hello");
+}
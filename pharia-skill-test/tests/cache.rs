@@ -0,0 +1,80 @@
+use std::sync::Mutex;
+
+use pharia_skill::{
+    Cache, CachingCsi, Completion, CompletionParams, CompletionRequest, Csi, CsiError, CsiExt,
+    CsiLayer, Layered,
+};
+use pharia_skill_test::StubCsi;
+
+/// Records the prompts of all completion requests reaching the inner implementation.
+#[derive(Default)]
+struct RecordPrompts(Mutex<Vec<String>>);
+
+impl CsiLayer for RecordPrompts {
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.0
+            .lock()
+            .unwrap()
+            .extend(requests.iter().map(|request| request.prompt.clone()));
+        inner.try_complete_concurrently(requests)
+    }
+}
+
+fn caching_csi() -> CachingCsi<Layered<RecordPrompts, StubCsi>> {
+    StubCsi
+        .layer(RecordPrompts::default())
+        .layer(Cache::new(10))
+}
+
+fn sent_prompts(csi: &CachingCsi<Layered<RecordPrompts, StubCsi>>) -> Vec<String> {
+    csi.inner.layer.0.lock().unwrap().clone()
+}
+
+#[test]
+fn repeated_request_is_answered_from_cache() {
+    let csi = caching_csi();
+
+    let first = csi.complete(CompletionRequest::new("model", "Hello"));
+    let second = csi.complete(CompletionRequest::new("model", "Hello"));
+
+    assert_eq!(first, second);
+    assert_eq!(sent_prompts(&csi), vec!["Hello"]);
+    assert_eq!(csi.layer.store().len(), 1);
+}
+
+#[test]
+fn only_misses_of_a_batch_are_sent() {
+    let csi = caching_csi();
+    csi.complete(CompletionRequest::new("model", "b"));
+
+    let completions = csi.complete_concurrently(vec![
+        CompletionRequest::new("model", "a"),
+        CompletionRequest::new("model", "b"),
+        CompletionRequest::new("model", "c"),
+    ]);
+
+    let texts = completions
+        .into_iter()
+        .map(|completion| completion.text)
+        .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["a", "b", "c"]);
+    assert_eq!(sent_prompts(&csi), vec!["b", "a", "c"]);
+}
+
+#[test]
+fn random_sampling_is_not_cached() {
+    let csi = caching_csi();
+    let request = CompletionRequest::new("model", "Hello").with_params(CompletionParams {
+        temperature: Some(0.8),
+        ..Default::default()
+    });
+
+    csi.complete(request.clone());
+    csi.complete(request);
+
+    assert_eq!(sent_prompts(&csi), vec!["Hello", "Hello"]);
+}
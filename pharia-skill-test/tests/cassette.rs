@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use pharia_skill::{
+    ChatEvent, ChatRequest, CompletionRequest, Csi, CsiError, Document, DocumentPath, Message,
+    TokenizeRequest,
+};
+use pharia_skill_test::{Matcher, MockCsi, RecordingCsi, ReplayCsi};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// A cassette in the temporary directory, unique per test.
+fn cassette(name: &str) -> PathBuf {
+    std::env::temp_dir()
+        .join(format!("pharia-skill-test-{}", std::process::id()))
+        .join(format!("{name}.json"))
+}
+
+fn haiku(csi: &impl Csi, topic: &str) -> String {
+    csi.chat(ChatRequest::new(
+        "model",
+        Message::user(format!("Write a haiku about {topic}")),
+    ))
+    .message
+    .content
+}
+
+#[test]
+fn replays_recorded_responses() {
+    let path = cassette("replays_recorded_responses");
+    let mock = MockCsi::builder()
+        .on_chat(Matcher::regex("rain"))
+        .respond("Rain on the rooftop")
+        .on_chat(Matcher::regex("sun"))
+        .respond("Sun on the meadow")
+        .build();
+    let recording = RecordingCsi::new(mock, &path).unwrap();
+    let recorded = [haiku(&recording, "rain"), haiku(&recording, "sun")];
+    let tokens = recording.tokenize(TokenizeRequest::new("model", "Hi"));
+
+    let replay = ReplayCsi::load(&path).unwrap();
+
+    assert_eq!([haiku(&replay, "rain"), haiku(&replay, "sun")], recorded);
+    assert_eq!(replay.tokenize(TokenizeRequest::new("model", "Hi")), tokens);
+}
+
+#[test]
+fn equal_requests_are_replayed_in_order() {
+    let path = cassette("equal_requests_are_replayed_in_order");
+    let mock = MockCsi::builder()
+        .on_complete(Matcher::any())
+        .respond_in_order([Err(CsiError::Transport("timeout".to_owned())), Ok("done")])
+        .build();
+    let recording = RecordingCsi::new(mock, &path).unwrap();
+    let request = || CompletionRequest::new("model", "prompt");
+    assert!(recording.try_complete(request()).is_err());
+    recording.complete(request());
+
+    let replay = ReplayCsi::load(&path).unwrap();
+
+    assert_eq!(
+        replay.try_complete(request()).unwrap_err(),
+        CsiError::Transport("timeout".to_owned())
+    );
+    assert_eq!(replay.complete(request()).text, "done");
+    assert_eq!(replay.complete(request()).text, "done");
+}
+
+#[test]
+fn streams_are_replayed_event_by_event() {
+    let path = cassette("streams_are_replayed_event_by_event");
+    let recording = RecordingCsi::new(MockCsi::new("Hello, world!"), &path).unwrap();
+    let request = || ChatRequest::new("model", Message::user("Hi"));
+    let recorded = recording.chat_stream(request()).collect::<Vec<_>>();
+
+    let replay = ReplayCsi::load(&path).unwrap();
+    let replayed = replay.chat_stream(request()).collect::<Vec<_>>();
+
+    assert_eq!(replayed, recorded);
+    assert!(matches!(replayed[0], ChatEvent::MessageBegin { .. }));
+}
+
+#[test]
+fn documents_are_replayed_with_any_metadata_type() {
+    #[derive(Deserialize, Serialize)]
+    struct Metadata {
+        url: String,
+    }
+
+    let path = cassette("documents_are_replayed_with_any_metadata_type");
+    let document = DocumentPath::new("namespace", "collection", "doc");
+    let mock = MockCsi::builder()
+        .on_document(Matcher::any())
+        .respond_with(|path: &DocumentPath| Document {
+            path: path.clone(),
+            contents: vec![],
+            metadata: Some(json!({ "url": "https://example.com" })),
+        })
+        .build();
+    let recording = RecordingCsi::new(mock, &path).unwrap();
+    recording
+        .documents::<serde_json::Value>(vec![document.clone()])
+        .unwrap();
+
+    let replay = ReplayCsi::load(&path).unwrap();
+    let documents = replay.try_documents::<Metadata>(vec![document]).unwrap();
+
+    assert_eq!(
+        documents[0].metadata.as_ref().unwrap().url,
+        "https://example.com"
+    );
+}
+
+#[test]
+#[should_panic(expected = "No interaction recorded for Chat")]
+fn unmatched_requests_panic() {
+    let path = cassette("unmatched_requests_panic");
+    let recording = RecordingCsi::new(MockCsi::new("Rain"), &path).unwrap();
+    haiku(&recording, "rain");
+
+    let replay = ReplayCsi::load(&path).unwrap();
+
+    haiku(&replay, "snow");
+}
@@ -0,0 +1,119 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pharia_skill::{Chunk, ChunkParams, ChunkRequest, Csi, CsiError, CsiExt};
+use pharia_skill_test::{BpeTokenizer, LocalChunker, Matcher, MockCsi, StubCsi};
+
+fn request(text: &str, max_tokens: u32, overlap: u32) -> ChunkRequest {
+    ChunkRequest::new(
+        text,
+        ChunkParams::new("model", max_tokens).with_overlap(overlap),
+    )
+}
+
+#[test]
+fn chunks_hold_at_most_max_tokens_words() {
+    let chunks = StubCsi.chunk(request("one two  three four five", 2, 0));
+
+    assert_eq!(chunks, ["one two", "three four", "five"]);
+}
+
+#[test]
+fn chunks_overlap_and_know_their_offsets() {
+    let csi = MockCsi::new("Hello");
+
+    let chunks = csi.chunk_with_offsets(request("Grüß Gott, liebe Leute", 3, 1));
+
+    assert_eq!(
+        chunks,
+        [
+            Chunk {
+                text: "Grüß Gott, liebe".to_owned(),
+                byte_offset: 0,
+                char_offset: 0,
+                token_count: 3,
+            },
+            Chunk {
+                text: "liebe Leute".to_owned(),
+                byte_offset: 13,
+                char_offset: 11,
+                token_count: 2,
+            },
+        ]
+    );
+}
+
+#[test]
+fn overlap_must_be_less_than_max_tokens() {
+    let result = StubCsi.try_chunk(request("Hello, world!", 2, 2));
+
+    assert!(matches!(result, Err(CsiError::InvalidParameters(_))));
+}
+
+#[test]
+fn scripted_chunks_are_located() {
+    let csi = MockCsi::builder()
+        .on_chunk(Matcher::any())
+        .respond(vec!["one two", "two three"])
+        .build();
+
+    let chunks = csi.chunk_with_offsets(request("one two three", 2, 1));
+
+    let offsets = chunks
+        .iter()
+        .map(|chunk| (chunk.byte_offset, chunk.token_count))
+        .collect::<Vec<_>>();
+    assert_eq!(offsets, [(0, 2), (4, 2)]);
+}
+
+#[test]
+fn scripted_chunks_need_not_be_part_of_the_text() {
+    let csi = MockCsi::builder()
+        .on_chunk(Matcher::any())
+        .respond(vec!["a summary", "of the text"])
+        .build();
+
+    let chunks = csi.chunk(request("one two three", 2, 0));
+
+    assert_eq!(chunks, ["a summary", "of the text"]);
+}
+
+#[test]
+fn bpe_vocabularies_are_loaded_from_disk() {
+    let vocabulary = ["a", "b", " ", "ab", "abab", " ab"]
+        .iter()
+        .enumerate()
+        .map(|(rank, token)| format!("{} {rank}", STANDARD.encode(token)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let path = std::env::temp_dir().join(format!(
+        "pharia-skill-test-{}-vocabulary.tiktoken",
+        std::process::id()
+    ));
+    std::fs::write(&path, vocabulary).unwrap();
+    let tokenizer = BpeTokenizer::load(&path).unwrap();
+    let csi = MockCsi::builder()
+        .chunker(LocalChunker::new(tokenizer))
+        .build();
+
+    let merged = csi.chunk(request("abab ab", 1, 0));
+    let unknown = csi.chunk_with_offsets(request("abab ü", 2, 1));
+
+    assert_eq!(merged, ["abab", " ab"]);
+    let unknown = unknown
+        .into_iter()
+        .map(|chunk| (chunk.text, chunk.token_count))
+        .collect::<Vec<_>>();
+    assert_eq!(unknown, [("abab ".to_owned(), 2), (" ü".to_owned(), 2)]);
+}
+
+#[test]
+fn chunker_is_a_layer() {
+    let csi = MockCsi::builder()
+        .on_chunk(Matcher::any())
+        .respond(vec!["everything"])
+        .build()
+        .layer(LocalChunker::default());
+
+    let chunks = csi.chunk(request("one two three", 2, 0));
+
+    assert_eq!(chunks, ["one two", "three"]);
+}
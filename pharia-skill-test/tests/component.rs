@@ -0,0 +1,139 @@
+#![cfg(feature = "component")]
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+
+use pharia_skill::{ChatRequest, CsiError};
+use pharia_skill_test::{ComponentRunner, Matcher, MockCsi, SkillError};
+use serde_json::{json, Value};
+
+/// Build the hello world example as a component once, like the Kernel would receive it.
+/// Requires the `wasm32-wasip2` target.
+fn hello_world() -> &'static Path {
+    static COMPONENT: OnceLock<PathBuf> = OnceLock::new();
+    COMPONENT.get_or_init(|| build_example("hello-world"))
+}
+
+/// Build the traced haiku example as a component once.
+fn traced_haiku() -> &'static Path {
+    static COMPONENT: OnceLock<PathBuf> = OnceLock::new();
+    COMPONENT.get_or_init(|| build_example("traced-haiku"))
+}
+
+fn build_example(package: &str) -> PathBuf {
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let status = Command::new(env!("CARGO"))
+        .current_dir(workspace)
+        .args(["build", "-p", package, "--release"])
+        .args(["--target", "wasm32-wasip2"])
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "Building the example failed, is the wasm32-wasip2 target installed?"
+    );
+    let target = std::env::var_os("CARGO_TARGET_DIR")
+        .map_or_else(|| workspace.join("target"), PathBuf::from);
+    target
+        .join("wasm32-wasip2/release")
+        .join(format!("{}.wasm", package.replace('-', "_")))
+}
+
+/// The runner keeps its own copy of the WIT world, so the crate can be packaged on its own.
+#[test]
+fn wit_world_matches_the_sdk() {
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let sdk = std::fs::read_to_string(workspace.join("pharia-skill/src/wit/world.wit")).unwrap();
+    let runner =
+        std::fs::read_to_string(workspace.join("pharia-skill-test/src/wit/world.wit")).unwrap();
+
+    assert!(
+        sdk == runner,
+        "The WIT world of pharia-skill-test differs from pharia-skill, copy it over"
+    );
+}
+
+#[test]
+fn skill_component_runs_with_mock_csi() {
+    let csi = MockCsi::builder()
+        .on_chat(|request: &ChatRequest| request.messages[1].content == "Say hello to Homer")
+        .times(1)
+        .respond("Hello, Homer")
+        .build();
+    let mut runner = ComponentRunner::load(hello_world(), csi).unwrap();
+
+    let output = runner.run(&json!("Homer")).unwrap();
+
+    assert_eq!(output, json!({ "message": "Hello, Homer" }));
+    runner.csi().verify();
+}
+
+#[test]
+fn metadata_is_exported() {
+    let mut runner = ComponentRunner::load(hello_world(), MockCsi::new("Hello")).unwrap();
+
+    let metadata = runner.metadata().unwrap();
+
+    assert_eq!(
+        metadata.description.as_deref(),
+        Some("This can also return an `anyhow::Result<Output>` if you need handle errors.")
+    );
+    assert_eq!(metadata.input_schema["type"], "string");
+    assert_eq!(
+        metadata.output_schema["properties"]["message"]["type"],
+        "string"
+    );
+    assert_eq!(metadata.output_item_schema, None);
+}
+
+#[test]
+fn invalid_input_is_reported_by_skill() {
+    let mut runner = ComponentRunner::load(hello_world(), MockCsi::new("Hello")).unwrap();
+
+    let error = runner.run(&json!(42)).unwrap_err();
+
+    assert!(matches!(
+        error.downcast_ref::<SkillError>(),
+        Some(SkillError::InvalidInput(_))
+    ));
+}
+
+#[test]
+fn csi_errors_trap_skill() {
+    let csi = MockCsi::builder()
+        .on_chat(Matcher::any())
+        .respond(CsiError::UnknownModel("llama-3.1-8b-instruct".to_owned()))
+        .build();
+    let mut runner = ComponentRunner::load(hello_world(), csi).unwrap();
+
+    let error = runner.run(&json!("Homer")).unwrap_err();
+
+    assert_eq!(
+        error.downcast_ref::<CsiError>(),
+        Some(&CsiError::UnknownModel("llama-3.1-8b-instruct".to_owned()))
+    );
+}
+
+#[test]
+fn traced_skill_logs_its_trace() {
+    let csi = MockCsi::new("Leaves drift on the stream");
+    let mut runner = ComponentRunner::load(traced_haiku(), csi).unwrap();
+
+    let output = runner.run(&json!("autumn")).unwrap();
+
+    assert_eq!(output, json!({ "haiku": "Leaves drift on the stream" }));
+    let logs = runner.csi().logs();
+    let trace = logs
+        .iter()
+        .find(|record| record.message == "Trace of the skill run")
+        .and_then(|record| record.field("trace"))
+        .unwrap();
+    let trace = serde_json::from_str::<Value>(trace).unwrap();
+    assert_eq!(trace[0]["name"], "haiku");
+    assert_eq!(trace[0]["output"], output);
+    assert_eq!(trace[0]["children"][0]["name"], "prompt");
+    assert_eq!(trace[0]["children"][1]["name"], "chat");
+}
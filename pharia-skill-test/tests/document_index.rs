@@ -0,0 +1,250 @@
+use pharia_skill::{
+    Batch, ChatRequest, Csi, CsiError, CsiExt, Document, DocumentPath, FilterCondition, IndexPath,
+    Message, MetadataFieldValue, MetadataFilter, MetadataFilterCondition, Modality, SearchFilter,
+    SearchRequest, TextCursor,
+};
+use pharia_skill_test::{FakeDocumentIndex, MockCsi, StubCsi};
+use serde_json::{json, Value};
+
+fn index() -> IndexPath {
+    IndexPath::new("namespace", "collection", "index")
+}
+
+fn document(name: &str, text: &str, metadata: Value) -> Document {
+    Document {
+        path: DocumentPath::new("namespace", "collection", name),
+        contents: vec![Modality::Text {
+            text: text.to_owned(),
+        }],
+        metadata: Some(metadata),
+    }
+}
+
+fn documents() -> FakeDocumentIndex {
+    FakeDocumentIndex::new()
+        .with_index(index())
+        .with_document(document(
+            "kernel",
+            "The Kernel runs skills. Skills call the Kernel via the CSI.",
+            json!({ "year": 2024, "published": "2024-11-05T10:00:00Z", "author": "alice", "draft": false }),
+        ))
+        .with_document(document(
+            "skills",
+            "Skills are WebAssembly components.",
+            json!({ "year": 2025, "published": "2025-02-01T10:00:00Z", "author": "bob", "reviewer": null }),
+        ))
+        .with_document(document(
+            "weather",
+            "It rains in Heidelberg.",
+            json!([{ "year": 2023 }, { "author": "carol" }]),
+        ))
+}
+
+/// Names of the documents found, sorted so filters can be checked regardless of scores.
+fn search(documents: &FakeDocumentIndex, query: &str, filters: Vec<SearchFilter>) -> Vec<String> {
+    let csi = StubCsi.layer(documents.clone());
+    let request = SearchRequest::new(query, index())
+        .with_max_results(10)
+        .with_filters(filters);
+    let mut names = csi
+        .search(request)
+        .into_iter()
+        .map(|result| result.document_path.name)
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+fn filter(field: &str, condition: MetadataFilterCondition) -> FilterCondition {
+    FilterCondition::Metadata(MetadataFilter {
+        field: field.to_owned(),
+        condition,
+    })
+}
+
+#[test]
+fn results_are_ranked_by_bm25() {
+    let csi = MockCsi::new("Hello").layer(documents());
+
+    let results = csi.search(
+        SearchRequest::new("How does the Kernel run skills?", index()).with_max_results(10),
+    );
+
+    let names = results
+        .iter()
+        .map(|result| result.document_path.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["kernel", "skills"]);
+    assert!(results[0].score > results[1].score);
+}
+
+#[test]
+fn max_results_and_min_score_limit_results() {
+    let csi = MockCsi::new("Hello").layer(documents());
+    let request = || SearchRequest::new("How does the Kernel run skills?", index());
+
+    let first = csi.search(request());
+    let scored = csi.search(
+        request()
+            .with_max_results(10)
+            .with_min_score(Some(first[0].score)),
+    );
+
+    assert_eq!(first.len(), 1);
+    assert_eq!(scored, first);
+}
+
+#[test]
+fn cursors_point_to_the_chunk() {
+    let documents = FakeDocumentIndex::new()
+        .with_index(index())
+        .with_chunk_size(3)
+        .with_document(Document {
+            path: DocumentPath::new("namespace", "collection", "doc"),
+            contents: vec![
+                Modality::Image,
+                Modality::Text {
+                    text: "Grüße aus der  schönen Stadt Heidelberg".to_owned(),
+                },
+            ],
+            metadata: None,
+        });
+    let csi = MockCsi::new("Hello").layer(documents);
+
+    let results = csi.search(SearchRequest::new("Heidelberg", index()));
+
+    assert_eq!(results[0].content, "schönen Stadt Heidelberg");
+    assert_eq!(
+        results[0].start,
+        TextCursor {
+            item: 1,
+            position: 15
+        }
+    );
+    assert_eq!(
+        results[0].end,
+        TextCursor {
+            item: 1,
+            position: 39
+        }
+    );
+}
+
+#[test]
+fn number_and_timestamp_conditions() {
+    let documents = documents();
+    let after = "2024-12-01T00:00:00Z".parse().unwrap();
+
+    let recent = search(
+        &documents,
+        "Kernel skills",
+        vec![SearchFilter::With(vec![
+            filter(
+                "year",
+                MetadataFilterCondition::GreaterThanOrEqualTo(2024.0),
+            ),
+            filter("published", MetadataFilterCondition::After(after)),
+        ])],
+    );
+    let old = search(
+        &documents,
+        "Kernel skills rains",
+        vec![SearchFilter::With(vec![filter(
+            "metadata.year",
+            MetadataFilterCondition::LessThan(2025.0),
+        )])],
+    );
+
+    assert_eq!(recent, ["skills"]);
+    assert_eq!(old, ["kernel", "weather"]);
+}
+
+#[test]
+fn equality_and_null_conditions() {
+    let documents = documents();
+    let query = "Kernel skills rains";
+
+    let by_author = search(
+        &documents,
+        query,
+        vec![SearchFilter::WithOneOf(vec![
+            filter(
+                "author",
+                MetadataFilterCondition::EqualTo(MetadataFieldValue::String("bob".to_owned())),
+            ),
+            filter(
+                "year",
+                MetadataFilterCondition::EqualTo(MetadataFieldValue::Integer(2023)),
+            ),
+        ])],
+    );
+    let not_drafts = search(
+        &documents,
+        query,
+        vec![SearchFilter::Without(vec![filter(
+            "draft",
+            MetadataFilterCondition::EqualTo(MetadataFieldValue::Boolean(false)),
+        )])],
+    );
+    let unreviewed = search(
+        &documents,
+        query,
+        vec![SearchFilter::With(vec![filter(
+            "reviewer",
+            MetadataFilterCondition::IsNull(serde_bool::True),
+        )])],
+    );
+
+    assert_eq!(by_author, ["skills", "weather"]);
+    assert_eq!(not_drafts, ["skills", "weather"]);
+    assert_eq!(unreviewed, ["kernel", "skills", "weather"]);
+}
+
+#[test]
+fn documents_and_metadata_are_served() {
+    let csi = MockCsi::new("Hello").layer(documents());
+    let path = DocumentPath::new("namespace", "collection", "skills");
+
+    let document = csi.try_document::<Value>(path.clone()).unwrap();
+    let metadata = csi.document_metadata::<Value>(path).unwrap();
+    let missing = csi.try_document::<Value>(DocumentPath::new("namespace", "collection", "x"));
+
+    assert!(matches!(
+        &document.contents[0],
+        Modality::Text { text } if text == "Skills are WebAssembly components."
+    ));
+    assert_eq!(metadata.unwrap()["author"], "bob");
+    assert!(matches!(missing, Err(CsiError::InvalidParameters(_))));
+}
+
+#[test]
+fn unknown_indexes_and_other_functions() {
+    let csi = MockCsi::new("Hello").layer(documents());
+
+    let unknown = csi.try_search(SearchRequest::new(
+        "Kernel",
+        IndexPath::new("namespace", "collection", "other"),
+    ));
+    let chat = csi.chat(ChatRequest::new("model", Message::user("Hi")));
+
+    assert!(matches!(unknown, Err(CsiError::InvalidParameters(_))));
+    assert_eq!(chat.message.content, "Hello");
+}
+
+#[test]
+fn batches_are_served_by_the_index() {
+    let csi = StubCsi.layer(documents());
+    let mut batch = Batch::new();
+    let search = batch.search(SearchRequest::new("WebAssembly", index()));
+    let document = batch.document::<Value>(DocumentPath::new("namespace", "collection", "kernel"));
+
+    let mut responses = batch.send(&csi);
+
+    let results = responses.take(search);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].document_path.name, "skills");
+    assert_eq!(
+        responses.take(document).metadata.unwrap()["author"],
+        "alice"
+    );
+}
@@ -0,0 +1,63 @@
+use pharia_skill::{
+    ChatEvent, ChatRequest, CompletionRequest, Csi, DynCsi, IndexPath, Message, SearchRequest,
+};
+use pharia_skill_test::{MockCsi, StubCsi};
+
+#[pharia_skill::skill]
+fn can_compile_with_dyn_csi(csi: &dyn DynCsi, input: String) -> String {
+    csi.complete(CompletionRequest::new("model", input)).text
+}
+
+/// Pick the implementation at runtime, as a plugin registry would.
+fn choose_csi(mock: bool) -> Box<dyn DynCsi> {
+    if mock {
+        Box::new(MockCsi::new("Hello, world!"))
+    } else {
+        Box::new(StubCsi)
+    }
+}
+
+#[test]
+fn skill_accepts_dyn_csi() {
+    let output = can_compile_with_dyn_csi(&StubCsi, "Hello".to_owned());
+
+    assert_eq!(output, "Hello");
+}
+
+#[test]
+fn output_item_schema() {
+    use pharia_skill::bindings::exports::pharia::skill::streaming_skill_handler::Guest;
+
+    assert!(__pharia_skill::Skill::output_item_schema().is_none());
+}
+
+#[test]
+fn boxed_csi_keeps_native_streaming() {
+    let csi = choose_csi(true);
+
+    let appends = csi
+        .chat_stream(ChatRequest::new("model", Message::user("Hi")))
+        .filter(|event| matches!(event, ChatEvent::MessageAppend { .. }))
+        .count();
+
+    assert_eq!(appends, 2);
+}
+
+#[test]
+fn boxed_csi_can_be_passed_as_impl_csi() {
+    fn search_count(csi: &impl Csi) -> usize {
+        csi.search(SearchRequest::new(
+            "query",
+            IndexPath::new("ns", "collection", "index"),
+        ))
+        .len()
+    }
+
+    let csi = choose_csi(false);
+    let documents = csi
+        .try_documents::<ureq::serde_json::Value>(vec![])
+        .unwrap();
+
+    assert_eq!(search_count(&csi), 0);
+    assert!(documents.is_empty());
+}
@@ -0,0 +1,114 @@
+use std::sync::Mutex;
+
+use pharia_skill::{
+    ChatRequest, ChatResponse, Csi, CsiError, CsiExt, CsiLayer, Fallback, FinishReason, Message,
+    RetryPolicy,
+};
+use pharia_skill_test::StubCsi;
+
+/// Simulates models by their name, and records the models of each batch.
+///
+/// `filtered` answers are content filtered, `silent` answers are empty, `broken` fails and any
+/// other model answers with its name.
+#[derive(Default)]
+struct Models(Mutex<Vec<Vec<String>>>);
+
+impl CsiLayer for Models {
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        let models = requests
+            .iter()
+            .map(|request| request.model.clone())
+            .collect::<Vec<_>>();
+        self.0.lock().unwrap().push(models.clone());
+        if models.iter().any(|model| model == "broken") {
+            return Err(CsiError::Transport("Model is broken".to_owned()));
+        }
+        let responses = inner.try_chat_concurrently(requests)?;
+        Ok(responses
+            .into_iter()
+            .zip(models)
+            .map(|(response, model)| match model.as_str() {
+                "filtered" => ChatResponse {
+                    finish_reason: FinishReason::ContentFilter,
+                    ..response
+                },
+                "silent" => response,
+                _ => ChatResponse {
+                    message: Message::assistant(model),
+                    ..response
+                },
+            })
+            .collect())
+    }
+}
+
+fn request(model: &str) -> ChatRequest {
+    ChatRequest::new(model, Message::user("Hi"))
+}
+
+#[test]
+fn only_failed_requests_are_retried() {
+    let csi = StubCsi
+        .layer(Models::default())
+        .layer(Fallback::new(["backup"]));
+
+    let answers = csi
+        .try_chat_answered(vec![
+            request("good"),
+            request("filtered"),
+            request("silent"),
+        ])
+        .unwrap();
+
+    let models = answers
+        .iter()
+        .map(|answer| answer.model.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(models, vec!["good", "backup", "backup"]);
+    assert_eq!(answers[1].response.message.content, "backup");
+    assert_eq!(
+        *csi.inner.layer.0.lock().unwrap(),
+        vec![vec!["good", "filtered", "silent"], vec!["backup", "backup"]]
+    );
+}
+
+#[test]
+fn errors_are_retried_with_next_model() {
+    let csi = StubCsi
+        .layer(Models::default())
+        .layer(Fallback::new(["filtered", "good"]));
+
+    let response = csi.chat(request("broken"));
+
+    assert_eq!(response.message.content, "good");
+}
+
+#[test]
+fn last_answer_is_returned_if_all_models_fail() {
+    let csi = StubCsi
+        .layer(Models::default())
+        .layer(Fallback::new(["filtered"]));
+
+    let answers = csi.try_chat_answered(vec![request("silent")]).unwrap();
+
+    assert_eq!(answers[0].model, "filtered");
+    assert_eq!(
+        answers[0].response.finish_reason,
+        FinishReason::ContentFilter
+    );
+}
+
+#[test]
+fn policy_can_disable_fallback() {
+    let csi = StubCsi
+        .layer(Models::default())
+        .layer(Fallback::new(["good"]).with_chat_policy(RetryPolicy::never()));
+
+    let result = csi.try_chat(request("broken"));
+
+    assert!(matches!(result, Err(CsiError::Transport(_))));
+}
@@ -0,0 +1,80 @@
+use pharia_skill::{Csi, CsiExt, LanguageCode, SelectLanguageRequest};
+use pharia_skill_test::{LanguageDetector, Matcher, MockCsi, StubCsi};
+
+fn select(csi: &impl Csi, text: &str, languages: &[LanguageCode]) -> Option<LanguageCode> {
+    csi.select_language(SelectLanguageRequest::new(text, languages))
+}
+
+#[test]
+fn best_match_among_requested_languages_is_selected() {
+    use LanguageCode::{Deu, Eng, Fra, Jpn, Rus, Spa, Ukr, Zho};
+    let all = [Deu, Eng, Fra, Jpn, Rus, Spa, Ukr, Zho];
+    let texts = [
+        (
+            "Please send me the report before the meeting on Friday.",
+            Eng,
+        ),
+        (
+            "Bitte schick mir den Bericht vor der Besprechung am Freitag.",
+            Deu,
+        ),
+        (
+            "Envoie-moi le rapport avant la réunion de vendredi, s'il te plaît.",
+            Fra,
+        ),
+        (
+            "Por favor, envíame el informe antes de la reunión del viernes.",
+            Spa,
+        ),
+        ("Пожалуйста, пришли мне отчёт до встречи в пятницу.", Rus),
+        ("Будь ласка, надішли мені звіт до зустрічі в п'ятницю.", Ukr),
+        ("金曜日の会議の前に報告書を送ってください。", Jpn),
+        ("请在星期五开会之前把报告发给我。", Zho),
+    ];
+
+    for (text, expected) in texts {
+        assert_eq!(select(&StubCsi, text, &all), Some(expected), "{text}");
+    }
+}
+
+#[test]
+fn unclear_or_missing_languages_are_not_selected() {
+    let csi = MockCsi::new("Hello");
+
+    let numbers = select(&csi, "42 + 17 = 59", &[LanguageCode::Eng]);
+    let requested = select(
+        &csi,
+        "Das Auto steht vor dem Haus.",
+        &[LanguageCode::Eng, LanguageCode::Deu],
+    );
+    let unrequested = select(&csi, "Das Auto steht vor dem Haus.", &[]);
+
+    assert_eq!(numbers, None);
+    assert_eq!(requested, Some(LanguageCode::Deu));
+    assert_eq!(unrequested, None);
+}
+
+#[test]
+fn threshold_and_samples_can_be_configured() {
+    let text = "Hvor er toget?";
+    let languages = [LanguageCode::Dan, LanguageCode::Nob];
+    let strict = StubCsi.layer(LanguageDetector::new().with_threshold(1.0));
+    let trained = MockCsi::builder()
+        .language_detector(
+            LanguageDetector::new().with_sample(LanguageCode::Nob, "Hvor er toget til Bergen?"),
+        )
+        .build();
+
+    assert_eq!(select(&strict, text, &languages), None);
+    assert_eq!(select(&trained, text, &languages), Some(LanguageCode::Nob));
+}
+
+#[test]
+fn scripted_responses_take_precedence() {
+    let csi = MockCsi::builder()
+        .on_select_language(Matcher::any())
+        .respond(None)
+        .build();
+
+    assert_eq!(select(&csi, "Guten Tag", &[LanguageCode::Deu]), None);
+}
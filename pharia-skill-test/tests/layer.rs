@@ -0,0 +1,127 @@
+use std::sync::Mutex;
+
+use pharia_skill::{
+    ChatEvent, ChatRequest, ChatResponse, CompletionRequest, Csi, CsiError, CsiExt, CsiLayer,
+    CsiRequest, CsiResponse, Message,
+};
+use pharia_skill_test::{MockCsi, StubCsi};
+
+/// Records the models of all chat requests passing through.
+#[derive(Default)]
+struct RecordModels(Mutex<Vec<String>>);
+
+impl CsiLayer for RecordModels {
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.0
+            .lock()
+            .unwrap()
+            .extend(requests.iter().map(|request| request.model.clone()));
+        inner.try_chat_concurrently(requests)
+    }
+}
+
+/// Replaces the model of all chat requests.
+struct PinModel(&'static str);
+
+impl CsiLayer for PinModel {
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        let requests = requests
+            .into_iter()
+            .map(|request| ChatRequest {
+                model: self.0.to_owned(),
+                ..request
+            })
+            .collect();
+        inner.try_chat_concurrently(requests)
+    }
+}
+
+/// Records the size of all batches passing through.
+#[derive(Default)]
+struct RecordBatches(Mutex<Vec<usize>>);
+
+impl CsiLayer for RecordBatches {
+    fn batch<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CsiRequest>,
+    ) -> Result<Vec<CsiResponse>, CsiError> {
+        self.0.lock().unwrap().push(requests.len());
+        inner.try_batch(requests)
+    }
+}
+
+fn chat_batch() -> Vec<CsiRequest> {
+    vec![
+        CsiRequest::Chat(ChatRequest::new("model", Message::user("Hi"))),
+        CsiRequest::Complete(CompletionRequest::new("model", "Hi")),
+    ]
+}
+
+#[test]
+fn layer_intercepts_requests() {
+    let csi = MockCsi::new("Hello").layer(RecordModels::default());
+
+    let response = csi.chat(ChatRequest::new("model", Message::user("Hi")));
+
+    assert_eq!(response.message.content, "Hello");
+    assert_eq!(*csi.layer.0.lock().unwrap(), vec!["model"]);
+}
+
+#[test]
+fn last_layer_sees_requests_first() {
+    let csi = StubCsi
+        .layer(RecordModels::default())
+        .layer(PinModel("pinned"));
+
+    csi.chat(ChatRequest::new("model", Message::user("Hi")));
+
+    assert_eq!(*csi.inner.layer.0.lock().unwrap(), vec!["pinned"]);
+}
+
+#[test]
+fn other_functions_pass_through() {
+    let csi = MockCsi::new("Hello, world!").layer(RecordModels::default());
+
+    let completion = csi.complete(CompletionRequest::new("model", "Hi"));
+    let appends = csi
+        .chat_stream(ChatRequest::new("model", Message::user("Hi")))
+        .filter(|event| matches!(event, ChatEvent::MessageAppend { .. }))
+        .count();
+
+    assert_eq!(completion.text, "Hello, world!");
+    assert_eq!(appends, 2);
+    assert!(csi.layer.0.lock().unwrap().is_empty());
+}
+
+#[test]
+fn batches_go_through_the_layer() {
+    let csi = StubCsi
+        .layer(RecordModels::default())
+        .layer(PinModel("pinned"));
+
+    let responses = csi.try_batch(chat_batch()).unwrap();
+
+    assert_eq!(responses.len(), 2);
+    assert_eq!(*csi.inner.layer.0.lock().unwrap(), vec!["pinned"]);
+}
+
+#[test]
+fn pass_through_layers_can_forward_batches_as_a_whole() {
+    let csi = StubCsi
+        .layer(RecordModels::default())
+        .layer(RecordBatches::default());
+
+    csi.try_batch(chat_batch()).unwrap();
+
+    assert_eq!(*csi.layer.0.lock().unwrap(), vec![2]);
+    assert_eq!(*csi.inner.layer.0.lock().unwrap(), vec!["model"]);
+}
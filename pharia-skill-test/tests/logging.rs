@@ -0,0 +1,34 @@
+use pharia_skill::{Csi, Level, LogRecord};
+#[cfg(feature = "tracing")]
+use pharia_skill::{CsiExt, Tracing};
+use pharia_skill_test::MockCsi;
+
+fn summarize(csi: &impl Csi, text: &str) -> usize {
+    csi.log(
+        LogRecord::new(Level::Info, "Summarizing").with_field("length", text.len().to_string()),
+    );
+    text.len()
+}
+
+#[test]
+fn logs_are_captured_by_test_double() {
+    let csi = MockCsi::new("Hello");
+
+    summarize(&csi, "Some text");
+
+    let logs = csi.logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].level, Level::Info);
+    assert_eq!(logs[0].message, "Summarizing");
+    assert_eq!(logs[0].field("length"), Some("9"));
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn layers_pass_logs_on() {
+    let csi = MockCsi::new("Hello").layer(Tracing);
+
+    summarize(&csi, "Some text");
+
+    assert_eq!(csi.inner.logs().len(), 1);
+}
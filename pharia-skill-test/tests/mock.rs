@@ -0,0 +1,194 @@
+use pharia_skill::{
+    ChatEvent, ChatRequest, ChunkParams, ChunkRequest, CompletionRequest, Csi, CsiError,
+    DocumentPath, IndexPath, LanguageCode, Message, SearchRequest, SearchResult,
+    SelectLanguageRequest, TextCursor,
+};
+use pharia_skill_test::{Matcher, MockCsi, StubCsi};
+use serde_json::json;
+
+/// Answers the question from the first search result, or asks the model directly.
+fn answer(csi: &impl Csi, question: &str) -> String {
+    let index = IndexPath::new("namespace", "collection", "index");
+    let results = csi.search(SearchRequest::new(question, index));
+    let context = results
+        .first()
+        .map(|result| result.content.as_str())
+        .unwrap_or_default();
+    let prompt = format!("{context}\n{question}");
+    csi.chat(ChatRequest::new(
+        "llama-3.1-8b-instruct",
+        Message::user(prompt),
+    ))
+    .message
+    .content
+}
+
+fn search_result(content: &str) -> SearchResult {
+    SearchResult {
+        document_path: DocumentPath::new("namespace", "collection", "doc"),
+        content: content.to_owned(),
+        score: 1.0,
+        start: TextCursor {
+            item: 0,
+            position: 0,
+        },
+        end: TextCursor {
+            item: 0,
+            position: 0,
+        },
+    }
+}
+
+#[test]
+fn rules_answer_matching_requests() {
+    let csi = MockCsi::builder()
+        .on_search(|request: &SearchRequest| request.query.contains("capital"))
+        .times(1)
+        .respond(vec![search_result("Berlin is the capital.")])
+        .on_chat(Matcher::regex("Berlin is the capital"))
+        .times(1)
+        .respond("Berlin")
+        .fallback("I don't know")
+        .build();
+
+    assert_eq!(answer(&csi, "What is the capital?"), "Berlin");
+    assert_eq!(answer(&csi, "What is the meaning of life?"), "I don't know");
+    csi.verify();
+}
+
+#[test]
+fn every_search_request_gets_a_result_list() {
+    let requests = || {
+        let index = IndexPath::new("namespace", "collection", "index");
+        vec![
+            SearchRequest::new("first", index.clone()),
+            SearchRequest::new("second", index),
+        ]
+    };
+
+    let stubbed = StubCsi.search_concurrently(requests());
+    let mocked = MockCsi::new("").search_concurrently(requests());
+
+    assert_eq!(stubbed, vec![vec![], vec![]]);
+    assert_eq!(mocked, vec![vec![], vec![]]);
+}
+
+#[test]
+fn sequences_repeat_the_last_response() {
+    let csi = MockCsi::builder()
+        .on_complete(Matcher::model("model"))
+        .respond_in_order([Err(CsiError::Transport("timeout".to_owned())), Ok("a")])
+        .build();
+    let request = || CompletionRequest::new("model", "prompt");
+
+    assert!(csi.try_complete(request()).is_err());
+    assert_eq!(csi.complete(request()).text, "a");
+    assert_eq!(csi.complete(request()).text, "a");
+}
+
+#[test]
+fn first_matching_rule_wins() {
+    let csi = MockCsi::builder()
+        .on_chat(Matcher::model("a"))
+        .respond("first")
+        .on_chat(Matcher::any())
+        .respond("second")
+        .build();
+
+    assert_eq!(
+        csi.chat(ChatRequest::new("a", Message::user("Hi")))
+            .message
+            .content,
+        "first"
+    );
+    assert_eq!(
+        csi.chat(ChatRequest::new("b", Message::user("Hi")))
+            .message
+            .content,
+        "second"
+    );
+}
+
+#[test]
+fn responses_can_be_computed_from_requests() {
+    let csi = MockCsi::builder()
+        .on_chunk(Matcher::any())
+        .respond_with(|request: &ChunkRequest| {
+            request
+                .text
+                .split(". ")
+                .map(ToOwned::to_owned)
+                .collect::<Vec<_>>()
+        })
+        .on_select_language(Matcher::regex("Hallo"))
+        .respond(LanguageCode::Deu)
+        .build();
+
+    let chunks = csi.chunk(ChunkRequest::new("One. Two", ChunkParams::new("model", 10)));
+    let language = csi.select_language(SelectLanguageRequest::new(
+        "Hallo Welt",
+        vec![LanguageCode::Eng, LanguageCode::Deu],
+    ));
+
+    assert_eq!(chunks, ["One", "Two"]);
+    assert_eq!(language, Some(LanguageCode::Deu));
+}
+
+#[test]
+fn documents_carry_scripted_metadata() {
+    let path = DocumentPath::new("namespace", "collection", "doc");
+    let csi = MockCsi::builder()
+        .on_document(Matcher::regex("/doc$"))
+        .respond_with(|path: &DocumentPath| pharia_skill::Document {
+            path: path.clone(),
+            contents: vec![],
+            metadata: Some(json!({ "url": "https://example.com" })),
+        })
+        .build();
+
+    let documents = csi
+        .documents::<serde_json::Value>(vec![path.clone()])
+        .unwrap();
+    let metadata = csi.document_metadata::<serde_json::Value>(path).unwrap();
+
+    assert_eq!(documents[0].metadata, metadata);
+    assert_eq!(metadata.unwrap()["url"], "https://example.com");
+}
+
+#[test]
+fn streams_follow_the_script() {
+    let csi = MockCsi::builder()
+        .on_chat(Matcher::any())
+        .respond_in_order([
+            Err(CsiError::UnknownModel("model".to_owned())),
+            Ok("Hello, world!"),
+        ])
+        .build();
+    let request = || ChatRequest::new("model", Message::user("Hi"));
+
+    let failed = csi.try_chat_stream(request()).collect::<Vec<_>>();
+    let content = csi
+        .chat_stream(request())
+        .filter_map(|event| match event {
+            ChatEvent::MessageAppend { content, .. } => Some(content),
+            _ => None,
+        })
+        .collect::<String>();
+
+    assert_eq!(failed, [Err(CsiError::UnknownModel("model".to_owned()))]);
+    assert_eq!(content, "Hello, world!");
+}
+
+#[test]
+#[should_panic(expected = "on_chat rule 1 was expected to be called 2 time(s), but was called 1")]
+fn verify_reports_missing_calls() {
+    let csi = MockCsi::builder()
+        .on_chat(Matcher::any())
+        .times(2)
+        .respond("Hello")
+        .build();
+
+    csi.chat(ChatRequest::new("model", Message::user("Hi")));
+
+    csi.verify();
+}
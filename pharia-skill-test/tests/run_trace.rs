@@ -0,0 +1,152 @@
+use pharia_skill::{ChatRequest, CompletionRequest, Csi, CsiExt, CsiRequest, Message, RunTrace};
+use pharia_skill_test::{to_otlp_json, MockCsi};
+use serde_json::json;
+
+/// A skill with a sub-step, which finds the trace of its run on its own.
+fn answer(csi: &impl Csi, question: &str) -> String {
+    RunTrace::current().in_step("answer", |step| {
+        step.set_input(&question);
+        let response = csi.chat(ChatRequest::new("model", Message::user(question)));
+        let answer = response.message.content;
+        step.set_output(&answer);
+        answer
+    })
+}
+
+#[test]
+fn csi_calls_are_nested_in_sub_steps() {
+    let trace = RunTrace::new();
+    let csi = MockCsi::new("42").layer(trace.clone());
+
+    let output = {
+        let _current = trace.set_current();
+        trace.in_step("skill", |run| {
+            run.set_input(&"What is the answer?");
+            answer(&csi, "What is the answer?")
+        })
+    };
+
+    assert_eq!(output, "42");
+    let tree = trace.tree();
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree[0].step.name, "skill");
+    let step = &tree[0].children[0];
+    assert_eq!(step.step.name, "answer");
+    assert_eq!(step.step.output, Some(json!("42")));
+    let call = &step.children[0].step;
+    assert_eq!(call.name, "chat");
+    let input = call.input.as_ref().unwrap();
+    assert_eq!(input[0]["messages"][0]["content"], "What is the answer?");
+    let output = call.output.as_ref().unwrap();
+    assert_eq!(output[0]["message"]["content"], "42");
+}
+
+#[test]
+fn calls_outside_of_a_run_are_roots() {
+    let trace = RunTrace::new();
+    let csi = MockCsi::new("42").layer(trace.clone());
+
+    csi.chat(ChatRequest::new("model", Message::user("Hi")));
+
+    let steps = trace.steps();
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].parent_id, None);
+    assert!(steps[0].end.is_some());
+}
+
+#[test]
+fn batch_is_recorded_as_one_span() {
+    let trace = RunTrace::new();
+    let csi = MockCsi::new("42").layer(trace.clone());
+
+    csi.try_batch(vec![
+        CsiRequest::Chat(ChatRequest::new("model", Message::user("Hi"))),
+        CsiRequest::Complete(CompletionRequest::new("model", "Hi")),
+    ])
+    .unwrap();
+
+    let steps = trace.steps();
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].name, "batch");
+    let input = steps[0].input.as_ref().unwrap();
+    assert_eq!(input[1]["complete"]["prompt"], "Hi");
+    let output = steps[0].output.as_ref().unwrap();
+    assert_eq!(output[0]["chat"]["message"]["content"], "42");
+}
+
+#[test]
+fn stream_span_records_all_events() {
+    let trace = RunTrace::new();
+    let csi = MockCsi::new("Hello, world!").layer(trace.clone());
+
+    let events = csi
+        .chat_stream(ChatRequest::new("model", Message::user("Hi")))
+        .count();
+
+    let steps = trace.steps();
+    assert_eq!(steps[0].name, "chat_stream");
+    let output = steps[0].output.as_ref().unwrap();
+    assert_eq!(output.as_array().unwrap().len(), events);
+}
+
+#[test]
+fn stream_span_is_closed_at_its_end() {
+    let trace = RunTrace::new();
+    let csi = MockCsi::new("Hello, world!").layer(trace.clone());
+
+    let mut stream = csi.chat_stream(ChatRequest::new("model", Message::user("Hi")));
+    stream.by_ref().for_each(drop);
+    csi.chat(ChatRequest::new("model", Message::user("Hi")));
+
+    let steps = trace.steps();
+    assert!(steps[0].end.is_some());
+    assert_eq!(steps[1].parent_id, None);
+    drop(stream);
+}
+
+#[test]
+fn abandoned_stream_records_events_so_far() {
+    let trace = RunTrace::new();
+    let csi = MockCsi::new("Hello, world!").layer(trace.clone());
+
+    csi.chat_stream(ChatRequest::new("model", Message::user("Hi")))
+        .take(2)
+        .for_each(drop);
+
+    let steps = trace.steps();
+    let output = steps[0].output.as_ref().unwrap();
+    assert_eq!(output.as_array().unwrap().len(), 2);
+    assert!(steps[0].end.is_some());
+}
+
+#[test]
+fn otlp_export_links_spans() {
+    let trace = RunTrace::new();
+    let csi = MockCsi::new("42").layer(trace.clone());
+    trace.in_step("skill", |_| {
+        csi.chat(ChatRequest::new("model", Message::user("Hi")));
+    });
+
+    let export = to_otlp_json(&trace);
+
+    let spans = &export["resourceSpans"][0]["scopeSpans"][0]["spans"];
+    let [run, call] = spans.as_array().unwrap().as_slice() else {
+        panic!("expected two spans, got {spans}");
+    };
+    let trace_id = format!("{:032x}", trace.trace_id());
+    assert_eq!(run["traceId"], trace_id);
+    assert_eq!(call["traceId"], trace_id);
+    assert_eq!(run["parentSpanId"], "");
+    assert_eq!(call["parentSpanId"], run["spanId"]);
+    assert_eq!(call["name"], "chat");
+    assert_eq!(call["status"]["code"], 1);
+    let attributes = call["attributes"].as_array().unwrap();
+    assert_eq!(attributes[0]["key"], "input");
+    assert!(attributes[0]["value"]["stringValue"]
+        .as_str()
+        .unwrap()
+        .contains("Hi"));
+    let start = call["startTimeUnixNano"].as_str().unwrap();
+    let end = call["endTimeUnixNano"].as_str().unwrap();
+    assert!(start.parse::<i128>().unwrap() <= end.parse::<i128>().unwrap());
+}
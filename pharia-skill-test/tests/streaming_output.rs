@@ -0,0 +1,46 @@
+use pharia_skill::Csi;
+use pharia_skill_test::StubCsi;
+use schemars::{schema::RootSchema, schema_for};
+use ureq::json;
+
+/// Repeats the input.
+#[pharia_skill::skill]
+fn can_compile_with_stream(_csi: &impl Csi, input: String) -> impl Iterator<Item = String> + '_ {
+    (1..=3).map(move |i| input.repeat(i))
+}
+
+#[test]
+fn mock_csi() {
+    let output = can_compile_with_stream(&StubCsi, "a".to_owned()).collect::<Vec<_>>();
+    assert_eq!(output, vec!["a", "aa", "aaa"]);
+}
+
+#[test]
+fn run_collects_items() {
+    use pharia_skill::bindings::exports::pharia::skill::skill_handler::Guest;
+    let output = __pharia_skill::Skill::run(b"\"a\"".to_vec()).unwrap();
+
+    assert_eq!(output, b"[\"a\",\"aa\",\"aaa\"]");
+}
+
+#[test]
+fn metadata() {
+    use pharia_skill::bindings::exports::pharia::skill::{skill_handler, streaming_skill_handler};
+    let metadata = <__pharia_skill::Skill as skill_handler::Guest>::metadata();
+    let output_item_schema =
+        <__pharia_skill::Skill as streaming_skill_handler::Guest>::output_item_schema();
+
+    let input_schema =
+        pharia_skill::bindings::json::from_slice::<RootSchema>(&metadata.input_schema).unwrap();
+    let output_schema =
+        pharia_skill::bindings::json::from_slice::<RootSchema>(&metadata.output_schema).unwrap();
+    let output_item_schema =
+        pharia_skill::bindings::json::from_slice::<RootSchema>(&output_item_schema.unwrap())
+            .unwrap();
+
+    assert_eq!(input_schema, schema_for!(String));
+    assert_eq!(output_schema, schema_for!(Vec<String>));
+    assert_eq!(output_item_schema, schema_for!(String));
+    assert_eq!(metadata.description.unwrap(), "Repeats the input.");
+    assert!(jsonschema::meta::is_valid(&json!(output_item_schema)));
+}
@@ -0,0 +1,194 @@
+use pharia_skill::{
+    tool, Agent, ChatEvent, ChatRequest, ChatResponse, Csi, FinishReason, Message, TokenUsage,
+    Tool, ToolCall, ToolMode,
+};
+use pharia_skill_test::{Matcher, MockCsi};
+use ureq::json;
+
+/// Get the current temperature in a city.
+#[tool]
+fn get_temperature(city: String, unit: Option<String>) -> anyhow::Result<String> {
+    match city.as_str() {
+        "Heidelberg" => Ok(format!(
+            "21 {}",
+            unit.unwrap_or_else(|| "celsius".to_owned())
+        )),
+        _ => Err(anyhow::anyhow!("Unknown city {city}")),
+    }
+}
+
+/// Calls the first tool for the city the user asks about, then answers with the tool output.
+fn tool_calling_csi() -> MockCsi {
+    MockCsi::builder()
+        .on_chat(Matcher::any())
+        .respond_with(|request: &ChatRequest| {
+            let last = request.messages.last().unwrap();
+            if last.role == "tool" || last.content.starts_with("Output of") {
+                return response(Message::assistant(last.content.clone()));
+            }
+            let arguments = json!({ "city": last.content });
+            match request.tools.first() {
+                Some(tool) => response(Message {
+                    tool_calls: vec![ToolCall {
+                        id: "call-1".to_owned(),
+                        name: tool.name.clone(),
+                        arguments: arguments.to_string(),
+                    }],
+                    ..Message::assistant("")
+                }),
+                None => response(Message::assistant(
+                    json!({ "name": "get_temperature", "arguments": arguments }).to_string(),
+                )),
+            }
+        })
+        .build()
+}
+
+fn response(message: Message) -> ChatResponse {
+    ChatResponse {
+        finish_reason: if message.tool_calls.is_empty() {
+            FinishReason::Stop
+        } else {
+            FinishReason::ToolCalls
+        },
+        message,
+        logprobs: vec![],
+        usage: TokenUsage {
+            prompt: 0,
+            completion: 0,
+        },
+    }
+}
+
+#[test]
+fn tool_definition() {
+    let definition = GetTemperature.definition();
+
+    assert_eq!(definition.name, "get_temperature");
+    assert_eq!(
+        definition.description.as_deref(),
+        Some("Get the current temperature in a city.")
+    );
+    assert_eq!(definition.parameters["required"], json!(["city"]));
+    assert!(jsonschema::meta::is_valid(&definition.parameters));
+}
+
+#[test]
+fn invoke_tool() {
+    let output = GetTemperature
+        .invoke(r#"{"city": "Heidelberg", "unit": "kelvin"}"#)
+        .unwrap();
+    let error = GetTemperature
+        .invoke(r#"{"city": "Atlantis"}"#)
+        .unwrap_err();
+
+    assert_eq!(output, "21 kelvin");
+    assert_eq!(error.to_string(), "Unknown city Atlantis");
+    assert!(GetTemperature.invoke(r#"{"unit": "kelvin"}"#).is_err());
+}
+
+#[test]
+fn agent_describes_tools_in_prompt() {
+    let csi = tool_calling_csi();
+    let agent = Agent::new(&csi, "llama-3.1-8b-instruct").with_tool(GetTemperature);
+
+    let output = agent.run(vec![Message::user("Heidelberg")]).unwrap();
+
+    assert_eq!(
+        output.response.message.content,
+        "Output of `get_temperature`: 21 celsius"
+    );
+    assert_eq!(output.steps, 2);
+    assert_eq!(output.messages.len(), 5);
+    assert_eq!(output.messages[0].role, "system");
+    assert!(output.messages[0]
+        .content
+        .contains("\"name\":\"get_temperature\""));
+}
+
+#[test]
+fn agent_without_tools_leaves_prompt_alone() {
+    let csi = MockCsi::builder()
+        .on_chat(Matcher::any())
+        .respond_with(|request: &ChatRequest| {
+            response(Message::assistant(
+                json!({
+                    "name": "get_temperature",
+                    "arguments": { "messages": request.messages.len() }
+                })
+                .to_string(),
+            ))
+        })
+        .build();
+    let agent = Agent::new(&csi, "llama-3.1-8b-instruct");
+
+    let output = agent.run(vec![Message::user("Heidelberg")]).unwrap();
+
+    assert_eq!(
+        output.response.message.content,
+        r#"{"arguments":{"messages":1},"name":"get_temperature"}"#
+    );
+    assert_eq!(output.steps, 1);
+}
+
+#[test]
+fn agent_runs_native_tools_until_final_answer() {
+    let csi = tool_calling_csi();
+    let agent = Agent::new(&csi, "llama-3.1-8b-instruct")
+        .with_tool(GetTemperature)
+        .with_tool_mode(ToolMode::Native);
+
+    let output = agent.run(vec![Message::user("Heidelberg")]).unwrap();
+
+    assert_eq!(output.response.message.content, "21 celsius");
+    assert_eq!(output.steps, 2);
+    assert_eq!(output.messages.len(), 4);
+}
+
+#[test]
+fn streamed_chats_emit_tool_calls() {
+    let csi = tool_calling_csi();
+    let request = ChatRequest::new("model", Message::user("Heidelberg"))
+        .with_tools(vec![GetTemperature.definition()]);
+
+    let events = csi.chat_stream(request).collect::<Vec<_>>();
+
+    let calls = events
+        .iter()
+        .filter_map(|event| match event {
+            ChatEvent::ToolCall { tool_call } => Some(tool_call.name.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(calls, ["get_temperature"]);
+    assert!(events.contains(&ChatEvent::MessageEnd {
+        finish_reason: FinishReason::ToolCalls
+    }));
+}
+
+#[test]
+fn agent_reports_tool_errors_to_the_model() {
+    let csi = tool_calling_csi();
+    let agent = Agent::new(&csi, "llama-3.1-8b-instruct")
+        .with_tool(GetTemperature)
+        .with_tool_mode(ToolMode::Native);
+
+    let output = agent.run(vec![Message::user("Atlantis")]).unwrap();
+
+    assert_eq!(
+        output.response.message.content,
+        "Error: Unknown city Atlantis"
+    );
+}
+
+#[test]
+fn agent_gives_up_after_max_steps() {
+    let csi = tool_calling_csi();
+    let agent = Agent::new(&csi, "llama-3.1-8b-instruct")
+        .with_tool(GetTemperature)
+        .with_max_steps(1);
+
+    let result = agent.run(vec![Message::user("Heidelberg")]);
+
+    assert!(result.is_err());
+}
@@ -0,0 +1,190 @@
+#![cfg(feature = "tracing")]
+
+use std::sync::{Arc, Mutex};
+
+use pharia_skill::{
+    ChatRequest, ChatResponse, Csi, CsiError, CsiExt, CsiLayer, CsiLogger, CsiRequest, IndexPath,
+    Level, LogRecord, Message, SearchRequest, SearchResult, SpanCollector, SpanRecord, TokenUsage,
+    Tracing,
+};
+use pharia_skill_test::{MockCsi, StubCsi};
+use serde_json::json;
+
+/// Reports a fixed token usage for every chat response.
+struct FixedUsage;
+
+impl CsiLayer for FixedUsage {
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        Ok(inner
+            .try_chat_concurrently(requests)?
+            .into_iter()
+            .map(|response| ChatResponse {
+                usage: TokenUsage {
+                    prompt: 10,
+                    completion: 5,
+                },
+                ..response
+            })
+            .collect())
+    }
+}
+
+/// Fails every search.
+struct FailingSearch;
+
+impl CsiLayer for FailingSearch {
+    fn search<C: Csi + ?Sized>(
+        &self,
+        _inner: &C,
+        _requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        Err(CsiError::Transport("index unavailable".to_owned()))
+    }
+}
+
+/// Run `f` with a collector installed and return the spans it exported.
+fn collect_spans(f: impl FnOnce()) -> Vec<SpanRecord> {
+    let spans = Arc::new(Mutex::new(Vec::new()));
+    let exported = spans.clone();
+    let collector = SpanCollector::new(move |span| exported.lock().unwrap().push(span));
+    tracing::subscriber::with_default(collector, f);
+    let spans = spans.lock().unwrap().clone();
+    spans
+}
+
+#[test]
+fn batched_call_opens_one_span() {
+    let csi = MockCsi::new("Hello").layer(FixedUsage).layer(Tracing);
+    let requests = vec![
+        ChatRequest::new("model-a", Message::user("Hi")),
+        ChatRequest::new("model-b", Message::user("Hi")),
+    ];
+
+    let spans = collect_spans(|| {
+        csi.chat_concurrently(requests);
+    });
+
+    assert_eq!(spans.len(), 1);
+    let span = &spans[0];
+    assert_eq!(span.name, "csi");
+    assert_eq!(span.target, "pharia_skill::csi");
+    assert_eq!(
+        json!(span.fields),
+        json!({
+            "function": "chat",
+            "batch_size": 2,
+            "model": "model-a, model-b",
+            "prompt_tokens": 20,
+            "completion_tokens": 10,
+            "finish_reason": "stop",
+        })
+    );
+    assert!(span.duration_ms >= 0.0);
+}
+
+#[test]
+fn batch_is_traced_as_a_whole() {
+    let csi = MockCsi::new("Hello").layer(FixedUsage).layer(Tracing);
+    let index = IndexPath::new("namespace", "collection", "index");
+    let requests = vec![
+        CsiRequest::Chat(ChatRequest::new("model", Message::user("Hi"))),
+        CsiRequest::Search(SearchRequest::new("query", index)),
+    ];
+
+    let spans = collect_spans(|| {
+        csi.try_batch(requests).unwrap();
+    });
+
+    assert_eq!(spans.len(), 1);
+    assert_eq!(
+        json!(spans[0].fields),
+        json!({
+            "function": "batch",
+            "batch_size": 2,
+            "model": "model",
+            "prompt_tokens": 10,
+            "completion_tokens": 5,
+            "finish_reason": "stop",
+        })
+    );
+}
+
+#[test]
+fn stream_span_records_finish_reason() {
+    let csi = MockCsi::new("Hello, world!").layer(Tracing);
+
+    let spans = collect_spans(|| {
+        csi.chat_stream(ChatRequest::new("model", Message::user("Hi")))
+            .for_each(drop);
+    });
+
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].fields["function"], "chat_stream");
+    assert_eq!(spans[0].fields["finish_reason"], "stop");
+}
+
+#[test]
+fn failed_call_records_error() {
+    let csi = StubCsi.layer(FailingSearch).layer(Tracing);
+    let index = IndexPath::new("namespace", "collection", "index");
+
+    let spans = collect_spans(|| {
+        assert!(csi.try_search(SearchRequest::new("query", index)).is_err());
+    });
+
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0].fields["function"], "search");
+    assert_eq!(
+        spans[0].fields["error"],
+        "Transport error: index unavailable"
+    );
+    assert!(!spans[0].fields.contains_key("model"));
+}
+
+#[test]
+fn tracing_events_are_forwarded() {
+    let csi = Arc::new(MockCsi::new("Hello"));
+    let logger = CsiLogger::new(csi.clone()).with_level(Level::Debug);
+
+    tracing::subscriber::with_default(logger, || {
+        tracing::trace!("too verbose");
+        tracing::debug!(document = "a.txt", chunks = 3, "Chunked document");
+        tracing::warn!("Falling back");
+    });
+
+    let logs = csi.logs();
+    assert_eq!(
+        logs,
+        vec![
+            LogRecord::new(Level::Debug, "Chunked document")
+                .with_field("document", "a.txt")
+                .with_field("chunks", "3"),
+            LogRecord::new(Level::Warn, "Falling back"),
+        ]
+    );
+}
+
+#[test]
+fn closed_spans_are_forwarded() {
+    let csi = Arc::new(MockCsi::new("Hello"));
+    let logger = CsiLogger::new(csi.clone());
+    let traced = csi.clone().layer(Tracing);
+
+    tracing::subscriber::with_default(logger, || {
+        traced.chat(ChatRequest::new("llama", Message::user("Hi")));
+    });
+
+    let logs = csi.logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].level, Level::Info);
+    assert_eq!(logs[0].message, "csi");
+    assert_eq!(logs[0].field("function"), Some("chat"));
+    assert_eq!(logs[0].field("batch_size"), Some("1"));
+    assert_eq!(logs[0].field("model"), Some("llama"));
+    assert_eq!(logs[0].field("finish_reason"), Some("stop"));
+    assert!(logs[0].field("duration_ms").unwrap().parse::<f64>().is_ok());
+}
@@ -0,0 +1,79 @@
+use pharia_skill::{
+    Budget, ChatRequest, ChatResponse, CompletionRequest, Csi, CsiError, CsiExt, CsiLayer,
+    CsiRequest, Message, ModelPrice, TokenUsage, UsageTracker,
+};
+use pharia_skill_test::MockCsi;
+
+/// Pretends every chat request used 100 prompt and 20 completion tokens.
+struct FixedUsage;
+
+impl CsiLayer for FixedUsage {
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        let mut responses = inner.try_chat_concurrently(requests)?;
+        for response in &mut responses {
+            response.usage = TokenUsage {
+                prompt: 100,
+                completion: 20,
+            };
+        }
+        Ok(responses)
+    }
+}
+
+fn request(model: &str) -> ChatRequest {
+    ChatRequest::new(model, Message::user("Hi"))
+}
+
+#[test]
+fn usage_is_reported_per_model() {
+    let tracker = UsageTracker::new().with_price("big", ModelPrice::new(10.0, 30.0));
+    let csi = MockCsi::new("Hello").layer(FixedUsage).layer(tracker);
+
+    csi.chat_concurrently(vec![request("big"), request("small")]);
+    csi.chat(request("big"));
+
+    let report = csi.layer.report();
+    assert_eq!(report.models["big"].requests, 2);
+    assert_eq!(report.models["big"].prompt_tokens, 200);
+    assert_eq!(report.models["small"].completion_tokens, 20);
+    assert_eq!(report.total_tokens(), 360);
+    assert!((report.total_cost() - 0.0032).abs() < 1e-9);
+}
+
+#[test]
+fn batched_requests_are_tracked() {
+    let csi = MockCsi::new("Hello")
+        .layer(FixedUsage)
+        .layer(UsageTracker::new());
+
+    csi.try_batch(vec![
+        CsiRequest::Chat(request("model")),
+        CsiRequest::Complete(CompletionRequest::new("model", "Hi")),
+        CsiRequest::Chat(request("model")),
+    ])
+    .unwrap();
+
+    let report = csi.layer.report();
+    assert_eq!(report.models["model"].requests, 3);
+    assert_eq!(report.models["model"].prompt_tokens, 200);
+}
+
+#[test]
+fn requests_fail_once_budget_is_used_up() {
+    let tracker = UsageTracker::new().with_budget(Budget::Tokens(200));
+    let csi = MockCsi::new("Hello").layer(FixedUsage).layer(tracker);
+
+    let first = csi.try_chat(request("model"));
+    let second = csi.try_chat(request("model"));
+    let third = csi.try_chat(request("model"));
+    let stream = csi.try_chat_stream(request("model")).collect::<Vec<_>>();
+
+    assert!(first.is_ok());
+    assert!(second.is_ok());
+    assert!(matches!(third, Err(CsiError::BudgetExceeded(_))));
+    assert!(matches!(stream[..], [Err(CsiError::BudgetExceeded(_))]));
+}
@@ -0,0 +1,116 @@
+use std::sync::Mutex;
+
+use pharia_skill::{
+    Completion, CompletionRequest, Csi, CsiError, CsiExt, CsiFunction, CsiLayer, CsiRequest,
+    CsiResponse, TokenizeRequest, Windowing,
+};
+use pharia_skill_test::StubCsi;
+
+/// Records the function and size of every batch passing through.
+#[derive(Default)]
+struct RecordBatches(Mutex<Vec<(CsiFunction, usize)>>);
+
+impl CsiLayer for RecordBatches {
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.0
+            .lock()
+            .unwrap()
+            .push((CsiFunction::Complete, requests.len()));
+        inner.try_complete_concurrently(requests)
+    }
+
+    fn tokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        self.0
+            .lock()
+            .unwrap()
+            .push((CsiFunction::Tokenize, requests.len()));
+        inner.try_tokenize_concurrently(requests)
+    }
+}
+
+fn prompts(count: usize) -> Vec<CompletionRequest> {
+    (0..count)
+        .map(|i| CompletionRequest::new("model", i.to_string()))
+        .collect()
+}
+
+#[test]
+fn large_batches_are_split_into_windows() {
+    let csi = StubCsi
+        .layer(RecordBatches::default())
+        .layer(Windowing::new(4));
+
+    let completions = csi.complete_concurrently(prompts(10));
+
+    let texts = completions.into_iter().map(|c| c.text).collect::<Vec<_>>();
+    let expected = (0..10).map(|i| i.to_string()).collect::<Vec<_>>();
+    assert_eq!(texts, expected);
+    let sizes = csi.inner.layer.0.lock().unwrap().clone();
+    assert_eq!(
+        sizes,
+        vec![
+            (CsiFunction::Complete, 4),
+            (CsiFunction::Complete, 4),
+            (CsiFunction::Complete, 2)
+        ]
+    );
+}
+
+#[test]
+fn window_size_can_be_set_per_function() {
+    let windowing = Windowing::new(4).with_window(CsiFunction::Complete, 8);
+
+    assert_eq!(windowing.window(CsiFunction::Complete), 8);
+    assert_eq!(windowing.window(CsiFunction::Chat), 4);
+}
+
+#[test]
+fn empty_batch_is_not_sent() {
+    let csi = StubCsi
+        .layer(RecordBatches::default())
+        .layer(Windowing::new(4));
+
+    let completions = csi.complete_concurrently(Vec::new());
+
+    assert!(completions.is_empty());
+    assert!(csi.inner.layer.0.lock().unwrap().is_empty());
+}
+
+#[test]
+fn windows_of_different_functions_are_interleaved() {
+    let csi = StubCsi
+        .layer(RecordBatches::default())
+        .layer(Windowing::new(2));
+    let mut requests = prompts(5)
+        .into_iter()
+        .map(CsiRequest::Complete)
+        .collect::<Vec<_>>();
+    requests.insert(1, CsiRequest::Tokenize(TokenizeRequest::new("model", "a")));
+    requests.push(CsiRequest::Tokenize(TokenizeRequest::new("model", "b")));
+
+    let responses = csi.try_interleaved(requests).unwrap();
+
+    let sizes = csi.inner.layer.0.lock().unwrap().clone();
+    assert_eq!(
+        sizes,
+        vec![
+            (CsiFunction::Complete, 2),
+            (CsiFunction::Tokenize, 2),
+            (CsiFunction::Complete, 2),
+            (CsiFunction::Complete, 1)
+        ]
+    );
+    assert!(matches!(&responses[0], CsiResponse::Complete(c) if c.text == "0"));
+    assert!(matches!(&responses[1], CsiResponse::Tokenize(tokens) if tokens == &[97]));
+    assert!(matches!(&responses[2], CsiResponse::Complete(c) if c.text == "1"));
+    assert!(matches!(&responses[5], CsiResponse::Complete(c) if c.text == "4"));
+    assert!(matches!(&responses[6], CsiResponse::Tokenize(tokens) if tokens == &[98]));
+}
@@ -0,0 +1,266 @@
+use anyhow::bail;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{ChatParams, ChatRequest, ChatResponse, Csi, Message, ToolCall, ToolDefinition};
+
+/// A function the model can call. Use the [`crate::tool`] macro to implement it for a plain
+/// Rust function.
+pub trait Tool {
+    /// Describes the tool to the model.
+    fn definition(&self) -> ToolDefinition;
+
+    /// Call the tool with the arguments requested by the model, serialized as a JSON object.
+    /// The output is sent back to the model as is.
+    ///
+    /// # Errors
+    /// Will return an error if the arguments are invalid or the tool fails.
+    fn invoke(&self, arguments: &str) -> anyhow::Result<String>;
+}
+
+/// How an [`Agent`] tells the model about its tools, and learns about the calls of the model.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToolMode {
+    /// Describe the tools in the system prompt, and parse calls from the answer of the model.
+    /// Works with every model, also on Kernels without native tool calling.
+    #[default]
+    Prompt,
+    /// Send the tool definitions with the chat request, and take the tool calls from the
+    /// response. Requires a model and a Kernel supporting tool calling.
+    Native,
+}
+
+/// Lets a model call tools in a loop until it produces a final answer.
+///
+/// In each step, the conversation is sent to the model together with the descriptions of all
+/// tools. If the model requests tool calls, they are executed and their results are appended to
+/// the conversation for the next step. Errors of a tool are reported back to the model, so it
+/// gets a chance to correct its call.
+pub struct Agent<'a, C: ?Sized> {
+    csi: &'a C,
+    model: String,
+    params: ChatParams,
+    tools: Vec<Box<dyn Tool + 'a>>,
+    tool_mode: ToolMode,
+    max_steps: usize,
+}
+
+/// The result of an [`Agent`] run.
+#[derive(Clone, Debug)]
+pub struct AgentOutput {
+    /// The final answer of the model
+    pub response: ChatResponse,
+    /// The whole conversation, including tool calls, tool results and the final answer
+    pub messages: Vec<Message>,
+    /// The number of chat requests needed to arrive at the final answer
+    pub steps: usize,
+}
+
+impl<'a, C: Csi + ?Sized> Agent<'a, C> {
+    /// The number of chat requests after which an agent gives up by default.
+    pub const DEFAULT_MAX_STEPS: usize = 10;
+
+    pub fn new(csi: &'a C, model: impl Into<String>) -> Self {
+        Self {
+            csi,
+            model: model.into(),
+            params: ChatParams::default(),
+            tools: Vec::new(),
+            tool_mode: ToolMode::default(),
+            max_steps: Self::DEFAULT_MAX_STEPS,
+        }
+    }
+
+    #[must_use]
+    pub fn with_params(mut self, params: ChatParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    #[must_use]
+    pub fn with_tool(mut self, tool: impl Tool + 'a) -> Self {
+        self.tools.push(Box::new(tool));
+        self
+    }
+
+    #[must_use]
+    pub fn with_tool_mode(mut self, tool_mode: ToolMode) -> Self {
+        self.tool_mode = tool_mode;
+        self
+    }
+
+    /// The maximum number of chat requests before the agent gives up.
+    #[must_use]
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Continue the conversation until the model gives a final answer.
+    ///
+    /// # Errors
+    /// Will return an error if a chat request fails, or if the model did not give a final answer
+    /// within the maximum number of steps.
+    pub fn run(&self, messages: impl Into<Vec<Message>>) -> anyhow::Result<AgentOutput> {
+        let definitions = self
+            .tools
+            .iter()
+            .map(|tool| tool.definition())
+            .collect::<Vec<_>>();
+        let mut messages = messages.into();
+        let tools = match self.tool_mode {
+            ToolMode::Prompt => {
+                if !definitions.is_empty() {
+                    describe_tools(&mut messages, &definitions);
+                }
+                Vec::new()
+            }
+            ToolMode::Native => definitions.clone(),
+        };
+
+        for step in 1..=self.max_steps {
+            let request = ChatRequest {
+                model: self.model.clone(),
+                messages: messages.clone(),
+                params: self.params.clone(),
+                tools: tools.clone(),
+            };
+            let response = self.csi.try_chat(request)?;
+            messages.push(response.message.clone());
+
+            let calls = match self.tool_mode {
+                // Without tools, an answer in JSON is a final answer, too.
+                ToolMode::Prompt if definitions.is_empty() => Vec::new(),
+                ToolMode::Prompt => parse_tool_call(&response.message.content, step)
+                    .into_iter()
+                    .collect(),
+                ToolMode::Native => response.message.tool_calls.clone(),
+            };
+            if calls.is_empty() {
+                return Ok(AgentOutput {
+                    response,
+                    messages,
+                    steps: step,
+                });
+            }
+            for call in &calls {
+                let result = self.invoke(&definitions, call);
+                messages.push(match self.tool_mode {
+                    ToolMode::Prompt => {
+                        Message::user(format!("Output of `{}`: {result}", call.name))
+                    }
+                    ToolMode::Native => Message::tool(&call.id, result),
+                });
+            }
+        }
+
+        bail!(
+            "The agent did not arrive at a final answer within {} steps",
+            self.max_steps
+        )
+    }
+
+    /// Execute a tool call, reporting failures in a way the model can react to.
+    fn invoke(&self, definitions: &[ToolDefinition], call: &ToolCall) -> String {
+        let tool = definitions
+            .iter()
+            .position(|definition| definition.name == call.name)
+            .map(|index| &self.tools[index]);
+        match tool {
+            Some(tool) => tool
+                .invoke(&call.arguments)
+                .unwrap_or_else(|error| format!("Error: {error:#}")),
+            None => format!("Error: There is no tool named `{}`", call.name),
+        }
+    }
+}
+
+/// Tell the model about the tools, and how to call them, in the system prompt.
+fn describe_tools(messages: &mut Vec<Message>, definitions: &[ToolDefinition]) {
+    let mut instruction = String::from(
+        "You have access to the following functions. To call a function, respond only with \
+        JSON of the form {\"name\": function name, \"arguments\": object of argument names \
+        and values}. You will then receive the output of the function. If you do not need to \
+        call a function, answer directly.\n",
+    );
+    for definition in definitions {
+        instruction.push('\n');
+        instruction.push_str(
+            &serde_json::to_string(definition).expect("tool definitions can be serialized"),
+        );
+    }
+    match messages.first_mut() {
+        Some(message) if message.role == "system" => {
+            message.content.push_str("\n\n");
+            message.content.push_str(&instruction);
+        }
+        _ => messages.insert(0, Message::system(instruction)),
+    }
+}
+
+/// A tool call in the answer of a model, as described by [`describe_tools`].
+#[derive(Deserialize)]
+struct PromptedToolCall {
+    name: String,
+    #[serde(alias = "parameters")]
+    arguments: Value,
+}
+
+/// The tool call the answer consists of, if any. The JSON may be wrapped in a Markdown code
+/// block. Calls are numbered by the step they were made in.
+fn parse_tool_call(content: &str, step: usize) -> Option<ToolCall> {
+    let content = content.trim();
+    let json = content
+        .strip_prefix("```json")
+        .or_else(|| content.strip_prefix("```"))
+        .and_then(|block| block.strip_suffix("```"))
+        .unwrap_or(content);
+    let call = serde_json::from_str::<PromptedToolCall>(json).ok()?;
+    call.arguments.is_object().then(|| ToolCall {
+        id: format!("call-{step}"),
+        name: call.name,
+        arguments: call.arguments.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn tool_call_is_parsed_from_code_block() {
+        let content = "```json\n{\"name\": \"search\", \"parameters\": {\"query\": \"Rust\"}}\n```";
+
+        let call = parse_tool_call(content, 2).unwrap();
+
+        assert_eq!(call.id, "call-2");
+        assert_eq!(call.name, "search");
+        assert_eq!(
+            call.parse_arguments::<Value>().unwrap(),
+            json!({ "query": "Rust" })
+        );
+    }
+
+    #[test]
+    fn prose_is_a_final_answer() {
+        assert_eq!(parse_tool_call("It is 21 degrees.", 1), None);
+        assert_eq!(parse_tool_call("{\"name\": \"search\"}", 1), None);
+    }
+
+    #[test]
+    fn tools_are_described_in_system_prompt() {
+        let mut messages = vec![Message::system("Be concise."), Message::user("Hi")];
+
+        describe_tools(&mut messages, &[ToolDefinition::new("search", json!({}))]);
+
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0]
+            .content
+            .starts_with("Be concise.\n\nYou have access"));
+        assert!(messages[0]
+            .content
+            .ends_with(r#"{"name":"search","description":null,"parameters":{}}"#));
+    }
+}
@@ -1,17 +1,21 @@
+mod batch;
 mod chunking;
 mod document_index;
 mod inference;
 mod language;
+mod logging;
+mod tokenization;
 
-use std::str::FromStr;
-
-use exports::pharia::skill::skill_handler::Error;
-use pharia::skill;
+use exports::pharia::skill::{skill_handler::Error, streaming_skill_handler};
+use pharia::skill::{self, streaming_output::OutputStream};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::{
-    ChatRequest, ChatResponse, ChunkRequest, Completion, CompletionRequest, Document, DocumentPath,
-    LanguageCode, SearchRequest, SearchResult, SelectLanguageRequest,
+    ActiveStep, ChatEvent, ChatRequest, ChatResponse, Chunk, ChunkRequest, Completion,
+    CompletionEvent, CompletionRequest, Csi, CsiError, CsiRequest, CsiResponse, DetokenizeRequest,
+    Document, DocumentPath, Embedding, EmbeddingRequest, LanguageCode, Layered, Level, LogRecord,
+    RunTrace, RunTraceCsi, SearchRequest, SearchResult, SelectLanguageRequest, TokenizeRequest,
 };
 
 wit_bindgen::generate!({
@@ -25,22 +29,144 @@ wit_bindgen::generate!({
 /// CSI implementation for the WASI environment.
 pub struct WitCsi;
 
+/// The CSI handed to skills.
+#[cfg(not(feature = "tracing"))]
+pub type SkillCsi = WitCsi;
+
+/// The CSI handed to skills, with a span for every call.
+#[cfg(feature = "tracing")]
+pub type SkillCsi = crate::TracedCsi<WitCsi>;
+
+/// The CSI handed to skills.
+#[cfg(not(feature = "tracing"))]
+#[must_use]
+pub fn skill_csi() -> SkillCsi {
+    WitCsi
+}
+
+/// The CSI handed to skills.
+///
+/// Also forwards the events of the skill to the Kernel, unless the skill installed its own
+/// global subscriber before.
+#[cfg(feature = "tracing")]
+#[must_use]
+pub fn skill_csi() -> SkillCsi {
+    static LOGGER: std::sync::Once = std::sync::Once::new();
+    LOGGER.call_once(|| {
+        tracing::subscriber::set_global_default(crate::CsiLogger::new(WitCsi)).ok();
+    });
+    Layered::new(crate::Tracing, WitCsi)
+}
+
+/// Run a skill defined with `#[skill(trace)]`.
+///
+/// The run is recorded in a step with the raw input and the output or error of the skill,
+/// containing the sub-steps of the skill and every CSI call. Afterwards, the trace is logged to
+/// the Kernel as the `trace` field of a debug record.
+pub fn run_traced<I, T: RunOutcome>(
+    name: &str,
+    raw_input: &[u8],
+    input: I,
+    run: impl FnOnce(&RunTraceCsi<SkillCsi>, I) -> T,
+) -> T {
+    let trace = RunTrace::new();
+    let _current = trace.set_current();
+    let csi = Layered::new(trace.clone(), skill_csi());
+    let output = trace.in_step(name, |step| {
+        if let Ok(input) = serde_json::from_slice::<Value>(raw_input) {
+            step.set_input(&input);
+        }
+        let output = run(&csi, input);
+        output.record(step);
+        output
+    });
+    if let Ok(tree) = serde_json::to_string(&trace.tree()) {
+        csi.inner
+            .log(LogRecord::new(Level::Debug, "Trace of the skill run").with_field("trace", tree));
+    }
+    output
+}
+
+/// The result of a skill run, which can be recorded in the step of a traced run.
+pub trait RunOutcome {
+    /// Record the output or the error of the run.
+    fn record(&self, step: &ActiveStep);
+}
+
+impl RunOutcome for Result<Vec<u8>, Error> {
+    fn record(&self, step: &ActiveStep) {
+        match self {
+            Ok(output) => {
+                if let Ok(output) = serde_json::from_slice::<Value>(output) {
+                    step.set_output(&output);
+                }
+            }
+            Err(error) => step.set_error(error),
+        }
+    }
+}
+
+/// The items a streaming skill wrote to its output stream, and how the run ended.
+pub struct WrittenStream {
+    items: Vec<Value>,
+    result: Result<(), streaming_skill_handler::Error>,
+}
+
+impl RunOutcome for WrittenStream {
+    fn record(&self, step: &ActiveStep) {
+        step.set_output(&self.items);
+        if let Err(error) = &self.result {
+            step.set_error(error);
+        }
+    }
+}
+
+impl From<WrittenStream> for Result<(), streaming_skill_handler::Error> {
+    fn from(value: WrittenStream) -> Self {
+        value.result
+    }
+}
+
 impl super::Csi for WitCsi {
-    fn chunk_concurrently(&self, requests: Vec<ChunkRequest>) -> Vec<Vec<String>> {
-        skill::chunking::chunk(&requests.into_iter().map(Into::into).collect::<Vec<_>>())
+    fn try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        Ok(skill::chunking::chunk(
+            &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+        ))
     }
 
-    fn search_concurrently(&self, requests: Vec<SearchRequest>) -> Vec<Vec<SearchResult>> {
-        skill::document_index::search(&requests.into_iter().map(Into::into).collect::<Vec<_>>())
+    fn try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        Ok(skill::chunking::chunk_with_offsets(
+            &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .map(|chunks| chunks.into_iter().map(Into::into).collect())
+        .collect())
+    }
+
+    fn try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        Ok(
+            skill::document_index::search(
+                &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+            )
             .into_iter()
             .map(|results| results.into_iter().map(Into::into).collect())
-            .collect()
+            .collect(),
+        )
     }
 
-    fn documents<Metadata>(
+    fn try_documents<Metadata>(
         &self,
         paths: Vec<DocumentPath>,
-    ) -> anyhow::Result<Vec<Document<Metadata>>>
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a>,
     {
@@ -50,10 +176,10 @@ impl super::Csi for WitCsi {
             .collect()
     }
 
-    fn documents_metadata<Metadata>(
+    fn try_documents_metadata<Metadata>(
         &self,
         paths: Vec<DocumentPath>,
-    ) -> anyhow::Result<Vec<Option<Metadata>>>
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a>,
     {
@@ -65,29 +191,123 @@ impl super::Csi for WitCsi {
         .collect()
     }
 
-    fn chat_concurrently(&self, requests: Vec<ChatRequest>) -> Vec<ChatResponse> {
-        skill::inference::chat(&requests.into_iter().map(Into::into).collect::<Vec<_>>())
+    fn try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        if !requests.iter().any(inference::needs_chat_with_tools) {
+            return Ok(skill::inference::chat(
+                &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+            )
             .into_iter()
             .map(Into::into)
-            .collect()
+            .collect());
+        }
+        Ok(skill::inference::chat_with_tools(
+            &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .map(Into::into)
+        .collect())
     }
 
-    fn complete_concurrently(&self, requests: Vec<CompletionRequest>) -> Vec<Completion> {
-        skill::inference::complete(&requests.into_iter().map(Into::into).collect::<Vec<_>>())
-            .into_iter()
-            .map(Into::into)
-            .collect()
+    /// Chats with tools, response formats or tool messages can not be streamed by the Kernel. Their
+    /// response arrives as a single message chunk, followed by a [`ChatEvent::ToolCall`] for each
+    /// call of a tool.
+    fn try_chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+        let events: Box<dyn Iterator<Item = _>> = if inference::needs_chat_with_tools(&request) {
+            match self.try_chat(request) {
+                Ok(response) => Box::new(response.into_events().into_iter().map(Ok)),
+                Err(error) => Box::new(std::iter::once(Err(error))),
+            }
+        } else {
+            let stream = skill::inference::ChatStream::new(&request.into());
+            Box::new(std::iter::from_fn(move || {
+                stream.next().map(|event| Ok(event.into()))
+            }))
+        };
+        events
+    }
+
+    fn try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        Ok(
+            skill::inference::complete(&requests.into_iter().map(Into::into).collect::<Vec<_>>())
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        )
+    }
+
+    fn try_embed_concurrently(
+        &self,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        Ok(skill::inference::embed(
+            &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+        ))
+    }
+
+    fn try_tokenize_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        Ok(skill::tokenization::tokenize(
+            &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+        ))
+    }
+
+    fn try_detokenize_concurrently(
+        &self,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        Ok(skill::tokenization::detokenize(
+            &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+        ))
     }
 
-    fn select_language_concurrently(
+    fn try_count_tokens_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        Ok(skill::tokenization::count_tokens(
+            &requests.into_iter().map(Into::into).collect::<Vec<_>>(),
+        ))
+    }
+
+    fn try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+        let stream = skill::inference::CompletionStream::new(&request.into());
+        std::iter::from_fn(move || stream.next().map(|event| Ok(event.into())))
+    }
+
+    fn try_select_language_concurrently(
         &self,
         requests: Vec<SelectLanguageRequest>,
-    ) -> Vec<Option<LanguageCode>> {
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
         skill::language::select_language(&requests.into_iter().map(Into::into).collect::<Vec<_>>())
             .into_iter()
-            .map(|l| l.map(|l| LanguageCode::from_str(&l).expect("Unknown language code")))
+            .map(|l| l.as_deref().map(language::parse_language).transpose())
             .collect()
     }
+
+    fn try_batch(&self, requests: Vec<CsiRequest>) -> Result<Vec<CsiResponse>, CsiError> {
+        skill::batch::batch(&requests.into_iter().map(Into::into).collect::<Vec<_>>())
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect()
+    }
+    fn log(&self, record: LogRecord) {
+        let (level, message, fields) = record.into_wit();
+        skill::logging::log(level, &message, &fields);
+    }
 }
 
 /// Newtype so we can create `From` trait implementations for `anyhow::Result` and `String`.
@@ -114,6 +334,97 @@ impl<T: Serialize> From<HandlerResult<T>> for Result<Vec<u8>, Error> {
     }
 }
 
+impl<T: Serialize> HandlerResult<T> {
+    /// Write the output as the only item of the output stream.
+    ///
+    /// # Errors
+    /// Will error if the skill failed or its output cannot be serialized.
+    pub fn write_to(self, output: &OutputStream) -> Result<(), streaming_skill_handler::Error> {
+        output.write(&Result::<Vec<u8>, Error>::from(self)?);
+        Ok(())
+    }
+
+    /// Like [`HandlerResult::write_to`], but also keeps the written output for the trace.
+    pub fn write_recorded(self, output: &OutputStream) -> WrittenStream {
+        let items = self
+            .0
+            .as_ref()
+            .ok()
+            .and_then(|value| serde_json::to_value(value).ok())
+            .into_iter()
+            .collect();
+        WrittenStream {
+            items,
+            result: self.write_to(output),
+        }
+    }
+}
+
+/// Newtype so we can create `From` trait implementations for iterators and `anyhow::Result`s of
+/// iterators, which are returned by streaming skills.
+pub struct HandlerStream<I: Iterator>(Result<I, Error>);
+
+impl<I: Iterator> From<I> for HandlerStream<I> {
+    fn from(items: I) -> Self {
+        Self(Ok(items))
+    }
+}
+
+impl<I: Iterator> From<anyhow::Result<I>> for HandlerStream<I> {
+    fn from(result: anyhow::Result<I>) -> Self {
+        Self(result.map_err(|error| Error::Internal(error.to_string())))
+    }
+}
+
+impl<I> HandlerStream<I>
+where
+    I: Iterator,
+    I::Item: Serialize,
+{
+    /// Collect all items into a single output, for callers which do not support streaming.
+    pub fn collect(self) -> HandlerResult<Vec<I::Item>> {
+        HandlerResult(self.0.map(Iterator::collect))
+    }
+
+    /// Write each item to the output stream as soon as it is produced.
+    ///
+    /// # Errors
+    /// Will error if the skill failed or an item cannot be serialized.
+    pub fn write_to(self, output: &OutputStream) -> Result<(), streaming_skill_handler::Error> {
+        self.write(output, |_| {})
+    }
+
+    /// Like [`HandlerStream::write_to`], but also keeps the written items for the trace.
+    pub fn write_recorded(self, output: &OutputStream) -> WrittenStream {
+        let mut items = Vec::new();
+        let result = self.write(output, |item| {
+            items.extend(serde_json::to_value(item).ok());
+        });
+        WrittenStream { items, result }
+    }
+
+    fn write(
+        self,
+        output: &OutputStream,
+        mut written: impl FnMut(&I::Item),
+    ) -> Result<(), streaming_skill_handler::Error> {
+        for item in self.0? {
+            output.write(&json::to_vec(&item)?);
+            written(&item);
+        }
+        Ok(())
+    }
+}
+
+impl From<Error> for streaming_skill_handler::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Internal(message) => Self::Internal(message),
+            Error::InvalidInput(message) => Self::InvalidInput(message),
+        }
+    }
+}
+
 /// JSON serialization and deserialization helpers for the main skill macro.
 pub mod json {
     pub use schemars::{schema_for, JsonSchema};
@@ -146,6 +457,139 @@ pub mod json {
     }
 }
 
+/// Helpers for the tool macro.
+pub mod tool {
+    use anyhow::Context;
+    pub use anyhow::Error;
+    use schemars::{
+        r#gen::SchemaGenerator,
+        schema::{InstanceType, ObjectValidation, RootSchema, SchemaObject},
+        JsonSchema,
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{Map, Value};
+
+    /// Builds the JSON schema of the arguments of a tool, one function parameter at a time.
+    #[derive(Default)]
+    pub struct Parameters {
+        generator: SchemaGenerator,
+        object: ObjectValidation,
+    }
+
+    impl Parameters {
+        #[must_use]
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        pub fn add<T: JsonSchema>(&mut self, name: &str, required: bool) {
+            let schema = self.generator.subschema_for::<T>();
+            self.object.properties.insert(name.to_owned(), schema);
+            if required {
+                self.object.required.insert(name.to_owned());
+            }
+        }
+
+        #[must_use]
+        pub fn into_schema(mut self) -> Value {
+            let schema = RootSchema {
+                meta_schema: self.generator.settings().meta_schema.clone(),
+                schema: SchemaObject {
+                    instance_type: Some(InstanceType::Object.into()),
+                    object: Some(Box::new(self.object)),
+                    ..SchemaObject::default()
+                },
+                definitions: self.generator.take_definitions(),
+            };
+            serde_json::to_value(schema).expect("JSON schemas can always be serialized")
+        }
+    }
+
+    /// The arguments of a tool call, taken out one function parameter at a time.
+    pub struct Arguments(Map<String, Value>);
+
+    impl Arguments {
+        /// # Errors
+        /// Will error if the arguments are not a JSON object.
+        pub fn parse(arguments: &str) -> anyhow::Result<Self> {
+            if arguments.trim().is_empty() {
+                return Ok(Self(Map::new()));
+            }
+            serde_json::from_str(arguments)
+                .map(Self)
+                .context("Tool arguments must be a JSON object")
+        }
+
+        /// # Errors
+        /// Will error if the argument is missing or has the wrong type.
+        pub fn take<T: DeserializeOwned>(&mut self, name: &str) -> anyhow::Result<T> {
+            let value = self.0.remove(name).unwrap_or(Value::Null);
+            serde_json::from_value(value).with_context(|| format!("Invalid argument `{name}`"))
+        }
+    }
+
+    /// Newtype so we can create `From` trait implementations for `anyhow::Result` and plain values.
+    pub struct ToolOutput<T: Serialize>(anyhow::Result<T>);
+
+    impl<T: Serialize> From<T> for ToolOutput<T> {
+        fn from(value: T) -> Self {
+            Self(Ok(value))
+        }
+    }
+
+    impl<T: Serialize> From<anyhow::Result<T>> for ToolOutput<T> {
+        fn from(result: anyhow::Result<T>) -> Self {
+            Self(result)
+        }
+    }
+
+    impl<T: Serialize> From<ToolOutput<T>> for anyhow::Result<String> {
+        /// Strings are passed to the model as is, everything else as JSON.
+        fn from(output: ToolOutput<T>) -> Self {
+            Ok(match serde_json::to_value(output.0?)? {
+                Value::String(text) => text,
+                value => value.to_string(),
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn optional_parameters_are_not_required() {
+            let mut parameters = Parameters::new();
+            parameters.add::<String>("city", true);
+            parameters.add::<Option<u32>>("days", false);
+
+            let schema = parameters.into_schema();
+
+            assert_eq!(schema["type"], "object");
+            assert_eq!(schema["properties"]["city"]["type"], "string");
+            assert_eq!(schema["required"], serde_json::json!(["city"]));
+        }
+
+        #[test]
+        fn missing_optional_argument_is_none() {
+            let mut arguments = Arguments::parse(r#"{"city": "Heidelberg"}"#).unwrap();
+
+            assert_eq!(arguments.take::<String>("city").unwrap(), "Heidelberg");
+            assert_eq!(arguments.take::<Option<u32>>("days").unwrap(), None);
+            assert!(arguments.take::<String>("country").is_err());
+        }
+
+        #[test]
+        fn string_output_is_not_quoted() {
+            let text = anyhow::Result::<String>::from(ToolOutput::from("Sunny")).unwrap();
+            let number = anyhow::Result::<String>::from(ToolOutput::from(42)).unwrap();
+
+            assert_eq!(text, "Sunny");
+            assert_eq!(number, "42");
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,6 +601,20 @@ mod tests {
         assert_eq!(output, b"\"Hello, world!\"".to_vec());
     }
 
+    #[test]
+    fn collect_stream() {
+        let stream = HandlerStream::from(["Hello", "world"].into_iter());
+        let output = Result::<Vec<u8>, Error>::from(stream.collect()).unwrap();
+        assert_eq!(output, b"[\"Hello\",\"world\"]".to_vec());
+    }
+
+    #[test]
+    fn dont_collect_failed_stream() {
+        let result: anyhow::Result<std::vec::IntoIter<&str>> = Err(anyhow::anyhow!("Hello"));
+        let error = Result::<Vec<u8>, Error>::from(HandlerStream::from(result).collect());
+        assert_eq!(error, Err(Error::Internal("Hello".to_owned())));
+    }
+
     #[test]
     fn dont_serialize_error() {
         let result = HandlerResult::<&str>(Err(Error::Internal("Hello, world!".to_owned())));
@@ -166,4 +624,32 @@ mod tests {
             "Error::Internal(\"Hello, world!\")".to_owned()
         );
     }
+    #[test]
+    fn outcome_of_run_is_recorded() {
+        let trace = RunTrace::new();
+
+        trace.in_step("run", |step| {
+            Ok::<_, Error>(b"{\"haiku\":\"Leaves\"}".to_vec()).record(step);
+        });
+        trace.in_step("failed", |step| {
+            Err::<Vec<u8>, _>(Error::Internal("Boom".to_owned())).record(step);
+        });
+        trace.in_step("stream", |step| {
+            WrittenStream {
+                items: vec!["Leaves".into()],
+                result: Err(streaming_skill_handler::Error::Internal("Boom".to_owned())),
+            }
+            .record(step);
+        });
+
+        let steps = trace.steps();
+        assert_eq!(
+            steps[0].output,
+            Some(serde_json::json!({ "haiku": "Leaves" }))
+        );
+        assert_eq!(steps[0].error, None);
+        assert!(steps[1].error.as_ref().unwrap().contains("Boom"));
+        assert_eq!(steps[2].output, Some(serde_json::json!(["Leaves"])));
+        assert!(steps[2].error.as_ref().unwrap().contains("Boom"));
+    }
 }
@@ -0,0 +1,55 @@
+use crate::{CsiError, CsiRequest, CsiResponse};
+
+use super::{inference::needs_chat_with_tools, language::parse_language, pharia::skill::batch};
+
+impl From<CsiRequest> for batch::CsiRequest {
+    fn from(value: CsiRequest) -> Self {
+        match value {
+            CsiRequest::Chunk(request) => Self::Chunk(request.into()),
+            CsiRequest::ChunkWithOffsets(request) => Self::ChunkWithOffsets(request.into()),
+            CsiRequest::Search(request) => Self::Search(request.into()),
+            CsiRequest::Documents(path) => Self::Documents(path.into()),
+            CsiRequest::DocumentsMetadata(path) => Self::DocumentMetadata(path.into()),
+            CsiRequest::Chat(request) if needs_chat_with_tools(&request) => {
+                Self::ChatWithTools(request.into())
+            }
+            CsiRequest::Chat(request) => Self::Chat(request.into()),
+            CsiRequest::Complete(request) => Self::Complete(request.into()),
+            CsiRequest::Embed(request) => Self::Embed(request.into()),
+            CsiRequest::Tokenize(request) => Self::Tokenize(request.into()),
+            CsiRequest::Detokenize(request) => Self::Detokenize(request.into()),
+            CsiRequest::CountTokens(request) => Self::CountTokens(request.into()),
+            CsiRequest::SelectLanguage(request) => Self::SelectLanguage(request.into()),
+        }
+    }
+}
+
+impl TryFrom<batch::CsiResponse> for CsiResponse {
+    type Error = CsiError;
+
+    fn try_from(value: batch::CsiResponse) -> Result<Self, Self::Error> {
+        Ok(match value {
+            batch::CsiResponse::Chunk(chunks) => Self::Chunk(chunks),
+            batch::CsiResponse::ChunkWithOffsets(chunks) => {
+                Self::ChunkWithOffsets(chunks.into_iter().map(Into::into).collect())
+            }
+            batch::CsiResponse::Search(results) => {
+                Self::Search(results.into_iter().map(Into::into).collect())
+            }
+            batch::CsiResponse::Documents(document) => Self::Documents(document.try_into()?),
+            batch::CsiResponse::DocumentMetadata(metadata) => {
+                Self::DocumentsMetadata(metadata.map(|m| serde_json::from_slice(&m)).transpose()?)
+            }
+            batch::CsiResponse::Chat(response) => Self::Chat(response.into()),
+            batch::CsiResponse::ChatWithTools(response) => Self::Chat(response.into()),
+            batch::CsiResponse::Complete(completion) => Self::Complete(completion.into()),
+            batch::CsiResponse::Embed(embeddings) => Self::Embed(embeddings),
+            batch::CsiResponse::Tokenize(tokens) => Self::Tokenize(tokens),
+            batch::CsiResponse::Detokenize(text) => Self::Detokenize(text),
+            batch::CsiResponse::CountTokens(count) => Self::CountTokens(count),
+            batch::CsiResponse::SelectLanguage(language) => {
+                Self::SelectLanguage(language.as_deref().map(parse_language).transpose()?)
+            }
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{ChunkParams, ChunkRequest};
+use crate::{Chunk, ChunkParams, ChunkRequest};
 
 use super::pharia::skill::chunking;
 
@@ -27,6 +27,23 @@ impl From<ChunkRequest> for chunking::ChunkRequest {
     }
 }
 
+impl From<chunking::TextChunk> for Chunk {
+    fn from(value: chunking::TextChunk) -> Self {
+        let chunking::TextChunk {
+            text,
+            byte_offset,
+            char_offset,
+            token_count,
+        } = value;
+        Self {
+            text,
+            byte_offset,
+            char_offset,
+            token_count,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
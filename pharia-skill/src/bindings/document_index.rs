@@ -1,8 +1,9 @@
 use serde::Deserialize;
 
 use crate::{
-    Document, DocumentPath, FilterCondition, IndexPath, MetadataFieldValue, MetadataFilter,
-    MetadataFilterCondition, Modality, SearchFilter, SearchRequest, SearchResult, TextCursor,
+    CsiError, Document, DocumentPath, FilterCondition, IndexPath, MetadataFieldValue,
+    MetadataFilter, MetadataFilterCondition, Modality, SearchFilter, SearchRequest, SearchResult,
+    TextCursor,
 };
 
 use super::pharia::skill::document_index;
@@ -173,7 +174,7 @@ impl<Metadata> TryFrom<document_index::Document> for Document<Metadata>
 where
     Metadata: for<'a> Deserialize<'a>,
 {
-    type Error = anyhow::Error;
+    type Error = CsiError;
 
     fn try_from(value: document_index::Document) -> Result<Self, Self::Error> {
         let document_index::Document {
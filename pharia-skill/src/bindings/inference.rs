@@ -1,6 +1,7 @@
 use crate::{
-    ChatParams, ChatRequest, ChatResponse, Completion, CompletionParams, CompletionRequest,
-    Distribution, FinishReason, Logprob, Logprobs, Message, TokenUsage,
+    ChatEvent, ChatParams, ChatRequest, ChatResponse, Completion, CompletionEvent,
+    CompletionParams, CompletionRequest, Distribution, EmbeddingRequest, FinishReason, Logprob,
+    Logprobs, Message, Representation, ResponseFormat, TokenUsage, ToolCall, ToolDefinition,
 };
 
 use super::pharia::skill::inference;
@@ -39,6 +40,17 @@ impl From<inference::FinishReason> for FinishReason {
     }
 }
 
+impl From<inference::FinishReasonWithTools> for FinishReason {
+    fn from(value: inference::FinishReasonWithTools) -> Self {
+        match value {
+            inference::FinishReasonWithTools::Stop => Self::Stop,
+            inference::FinishReasonWithTools::Length => Self::Length,
+            inference::FinishReasonWithTools::ContentFilter => Self::ContentFilter,
+            inference::FinishReasonWithTools::ToolCalls => Self::ToolCalls,
+        }
+    }
+}
+
 impl From<Logprobs> for inference::Logprobs {
     fn from(value: Logprobs) -> Self {
         match value {
@@ -108,9 +120,74 @@ impl From<inference::Completion> for Completion {
     }
 }
 
+impl From<inference::CompletionEvent> for CompletionEvent {
+    fn from(value: inference::CompletionEvent) -> Self {
+        match value {
+            inference::CompletionEvent::Append(inference::CompletionAppend { text, logprobs }) => {
+                Self::Append {
+                    text,
+                    logprobs: logprobs.into_iter().map(Into::into).collect(),
+                }
+            }
+            inference::CompletionEvent::End(finish_reason) => Self::End {
+                finish_reason: finish_reason.into(),
+            },
+            inference::CompletionEvent::Usage(usage) => Self::Usage {
+                usage: usage.into(),
+            },
+        }
+    }
+}
+
+impl From<ToolCall> for inference::ToolCall {
+    fn from(value: ToolCall) -> Self {
+        let ToolCall {
+            id,
+            name,
+            arguments,
+        } = value;
+        Self {
+            id,
+            name,
+            arguments,
+        }
+    }
+}
+
+impl From<inference::ToolCall> for ToolCall {
+    fn from(value: inference::ToolCall) -> Self {
+        let inference::ToolCall {
+            id,
+            name,
+            arguments,
+        } = value;
+        Self {
+            id,
+            name,
+            arguments,
+        }
+    }
+}
+
+impl From<ToolDefinition> for inference::ToolDefinition {
+    fn from(value: ToolDefinition) -> Self {
+        let ToolDefinition {
+            name,
+            description,
+            parameters,
+        } = value;
+        Self {
+            name,
+            description,
+            parameters: parameters.to_string().into_bytes(),
+        }
+    }
+}
+
+// Only for chats without tools, see `needs_chat_with_tools`.
 impl From<Message> for inference::Message {
     fn from(value: Message) -> Self {
-        let Message { role, content } = value;
+        let Message { role, content, .. } = value;
         Self { role, content }
     }
 }
@@ -118,7 +195,41 @@ impl From<Message> for inference::Message {
 impl From<inference::Message> for Message {
     fn from(value: inference::Message) -> Self {
         let inference::Message { role, content } = value;
-        Self { role, content }
+        Self::new(role, content)
+    }
+}
+
+impl From<Message> for inference::MessageWithTools {
+    fn from(value: Message) -> Self {
+        let Message {
+            role,
+            content,
+            tool_calls,
+            tool_call_id,
+        } = value;
+        Self {
+            role,
+            content,
+            tool_calls: tool_calls.into_iter().map(Into::into).collect(),
+            tool_call_id,
+        }
+    }
+}
+
+impl From<inference::MessageWithTools> for Message {
+    fn from(value: inference::MessageWithTools) -> Self {
+        let inference::MessageWithTools {
+            role,
+            content,
+            tool_calls,
+            tool_call_id,
+        } = value;
+        Self {
+            role,
+            content,
+            tool_calls: tool_calls.into_iter().map(Into::into).collect(),
+            tool_call_id,
+        }
     }
 }
 
@@ -131,6 +242,8 @@ impl From<ChatParams> for inference::ChatParams {
             frequency_penalty,
             presence_penalty,
             logprobs,
+            // Only sent via `chat-with-tools`, see `needs_chat_with_tools`.
+            response_format: _,
         } = value;
         Self {
             max_tokens,
@@ -143,12 +256,35 @@ impl From<ChatParams> for inference::ChatParams {
     }
 }
 
+impl From<ResponseFormat> for inference::ResponseFormat {
+    fn from(value: ResponseFormat) -> Self {
+        match value {
+            ResponseFormat::Text => Self::Text,
+            ResponseFormat::JsonSchema(schema) => Self::JsonSchema(schema.to_string().into_bytes()),
+        }
+    }
+}
+
+/// Whether the request needs `chat-with-tools`, because it offers tools to the model, contains
+/// tool calls or their results, or constrains the response format. All other requests are sent
+/// via `chat`, which Kernels have supported since version 0.3.
+pub(super) fn needs_chat_with_tools(request: &ChatRequest) -> bool {
+    !request.tools.is_empty()
+        || request.params.response_format.is_some()
+        || request
+            .messages
+            .iter()
+            .any(|message| !message.tool_calls.is_empty() || message.tool_call_id.is_some())
+}
+
+// Only for chats without tools, see `needs_chat_with_tools`.
 impl From<ChatRequest> for inference::ChatRequest {
     fn from(value: ChatRequest) -> Self {
         let ChatRequest {
             model,
             messages,
             params,
+            ..
         } = value;
         Self {
             model,
@@ -158,6 +294,24 @@ impl From<ChatRequest> for inference::ChatRequest {
     }
 }
 
+impl From<ChatRequest> for inference::ChatWithToolsRequest {
+    fn from(value: ChatRequest) -> Self {
+        let ChatRequest {
+            model,
+            messages,
+            mut params,
+            tools,
+        } = value;
+        Self {
+            model,
+            messages: messages.into_iter().map(Into::into).collect(),
+            response_format: params.response_format.take().map(Into::into),
+            params: params.into(),
+            tools: tools.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
 impl From<inference::ChatResponse> for ChatResponse {
     fn from(value: inference::ChatResponse) -> Self {
         let inference::ChatResponse {
@@ -175,6 +329,73 @@ impl From<inference::ChatResponse> for ChatResponse {
     }
 }
 
+impl From<inference::ChatWithToolsResponse> for ChatResponse {
+    fn from(value: inference::ChatWithToolsResponse) -> Self {
+        let inference::ChatWithToolsResponse {
+            message,
+            finish_reason,
+            logprobs,
+            usage,
+        } = value;
+        Self {
+            message: message.into(),
+            finish_reason: finish_reason.into(),
+            logprobs: logprobs.into_iter().map(Into::into).collect(),
+            usage: usage.into(),
+        }
+    }
+}
+
+impl From<inference::ChatEvent> for ChatEvent {
+    fn from(value: inference::ChatEvent) -> Self {
+        match value {
+            inference::ChatEvent::MessageBegin(role) => Self::MessageBegin { role },
+            inference::ChatEvent::MessageAppend(inference::MessageAppend { content, logprobs }) => {
+                Self::MessageAppend {
+                    content,
+                    logprobs: logprobs.into_iter().map(Into::into).collect(),
+                }
+            }
+            inference::ChatEvent::ToolCall(tool_call) => Self::ToolCall {
+                tool_call: tool_call.into(),
+            },
+            inference::ChatEvent::MessageEnd(finish_reason) => Self::MessageEnd {
+                finish_reason: finish_reason.into(),
+            },
+            inference::ChatEvent::Usage(usage) => Self::Usage {
+                usage: usage.into(),
+            },
+        }
+    }
+}
+
+impl From<Representation> for inference::Representation {
+    fn from(value: Representation) -> Self {
+        match value {
+            Representation::Symmetric => Self::Symmetric,
+            Representation::Document => Self::Document,
+            Representation::Query => Self::Query,
+        }
+    }
+}
+
+impl From<EmbeddingRequest> for inference::EmbeddingRequest {
+    fn from(value: EmbeddingRequest) -> Self {
+        let EmbeddingRequest {
+            model,
+            texts,
+            representation,
+            dimensions,
+        } = value;
+        Self {
+            model,
+            texts,
+            representation: representation.into(),
+            dimensions,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -265,4 +486,122 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_chat_event_conversion() {
+        let content = "Hello";
+        let event = inference::ChatEvent::MessageAppend(inference::MessageAppend {
+            content: content.into(),
+            logprobs: vec![],
+        });
+
+        let converted = ChatEvent::from(event);
+
+        assert_eq!(
+            converted,
+            ChatEvent::MessageAppend {
+                content: content.into(),
+                logprobs: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_chat_request_with_tools_conversion() {
+        let model = "llama-3.1-8b-instruct";
+        let parameters = serde_json::json!({"type": "object"});
+        let request = ChatRequest::new(model, Message::user("What is the weather?"))
+            .and_message(Message::tool("call-1", "Sunny"))
+            .and_tool(
+                ToolDefinition::new("get_weather", parameters.clone()).with_description("Weather"),
+            );
+
+        assert!(needs_chat_with_tools(&request));
+        let converted = inference::ChatWithToolsRequest::from(request);
+
+        assert_eq!(
+            converted.tools,
+            vec![inference::ToolDefinition {
+                name: "get_weather".into(),
+                description: Some("Weather".into()),
+                parameters: parameters.to_string().into_bytes(),
+            }]
+        );
+        assert_eq!(converted.messages[1].role, "tool");
+        assert_eq!(
+            converted.messages[1].tool_call_id.as_deref(),
+            Some("call-1")
+        );
+    }
+
+    #[test]
+    fn test_chat_request_without_tools_conversion() {
+        let request = ChatRequest::new("llama-3.1-8b-instruct", Message::user("Hello"));
+
+        assert!(!needs_chat_with_tools(&request));
+        let converted = inference::ChatRequest::from(request);
+
+        assert_eq!(
+            converted.messages,
+            vec![inference::Message {
+                role: "user".into(),
+                content: "Hello".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_chat_request_with_response_format_conversion() {
+        let schema = serde_json::json!({"type": "object"});
+        let request = ChatRequest::new("llama-3.1-8b-instruct", Message::user("Hello"))
+            .with_params(ChatParams {
+                response_format: Some(ResponseFormat::JsonSchema(schema.clone())),
+                ..ChatParams::default()
+            });
+
+        assert!(needs_chat_with_tools(&request));
+        let converted = inference::ChatWithToolsRequest::from(request);
+
+        assert_eq!(
+            converted.response_format,
+            Some(inference::ResponseFormat::JsonSchema(
+                schema.to_string().into_bytes()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_chat_response_with_tool_calls_conversion() {
+        let tool_call = inference::ToolCall {
+            id: "call-1".into(),
+            name: "get_weather".into(),
+            arguments: r#"{"city":"Heidelberg"}"#.into(),
+        };
+        let response = inference::ChatWithToolsResponse {
+            message: inference::MessageWithTools {
+                role: "assistant".into(),
+                content: String::new(),
+                tool_calls: vec![tool_call],
+                tool_call_id: None,
+            },
+            finish_reason: inference::FinishReasonWithTools::ToolCalls,
+            logprobs: vec![],
+            usage: inference::TokenUsage {
+                prompt: 1,
+                completion: 1,
+            },
+        };
+
+        let converted = ChatResponse::from(response);
+
+        assert_eq!(converted.finish_reason, FinishReason::ToolCalls);
+        assert_eq!(
+            converted.message.tool_calls,
+            vec![ToolCall {
+                id: "call-1".into(),
+                name: "get_weather".into(),
+                arguments: r#"{"city":"Heidelberg"}"#.into(),
+            }]
+        );
+    }
 }
@@ -1,4 +1,6 @@
-use crate::SelectLanguageRequest;
+use std::str::FromStr;
+
+use crate::{CsiError, LanguageCode, SelectLanguageRequest};
 
 use super::pharia::skill::language;
 
@@ -12,6 +14,12 @@ impl From<SelectLanguageRequest> for language::SelectLanguageRequest {
     }
 }
 
+/// Parse a language code returned by the host.
+pub(super) fn parse_language(code: &str) -> Result<LanguageCode, CsiError> {
+    LanguageCode::from_str(code)
+        .map_err(|_| CsiError::Decode(format!("Unknown language code: {code}")))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,30 @@
+use crate::{Level, LogRecord};
+
+use super::pharia::skill::logging;
+
+impl From<Level> for logging::Level {
+    fn from(value: Level) -> Self {
+        match value {
+            Level::Trace => Self::Trace,
+            Level::Debug => Self::Debug,
+            Level::Info => Self::Info,
+            Level::Warn => Self::Warn,
+            Level::Error => Self::Error,
+        }
+    }
+}
+
+impl LogRecord {
+    pub(super) fn into_wit(self) -> (logging::Level, String, Vec<logging::Field>) {
+        let Self {
+            level,
+            message,
+            fields,
+        } = self;
+        let fields = fields
+            .into_iter()
+            .map(|(key, value)| logging::Field { key, value })
+            .collect();
+        (level.into(), message, fields)
+    }
+}
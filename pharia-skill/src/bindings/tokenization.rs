@@ -0,0 +1,17 @@
+use crate::{DetokenizeRequest, TokenizeRequest};
+
+use super::pharia::skill::tokenization;
+
+impl From<TokenizeRequest> for tokenization::TokenizeRequest {
+    fn from(value: TokenizeRequest) -> Self {
+        let TokenizeRequest { model, text } = value;
+        Self { model, text }
+    }
+}
+
+impl From<DetokenizeRequest> for tokenization::DetokenizeRequest {
+    fn from(value: DetokenizeRequest) -> Self {
+        let DetokenizeRequest { model, tokens } = value;
+        Self { model, tokens }
+    }
+}
@@ -1,34 +1,162 @@
+pub mod batch;
 pub mod chunking;
 pub mod document_index;
+pub mod dynamic;
+pub mod embedding;
+pub mod error;
 pub mod inference;
 pub mod language;
+pub mod logging;
+pub mod structured;
+pub mod tokenization;
 
-use chunking::ChunkRequest;
+use batch::{CsiRequest, CsiResponse};
+use chunking::{Chunk, ChunkRequest};
 use document_index::{Document, SearchResult};
-use inference::{ChatRequest, ChatResponse, Completion, CompletionRequest};
+use embedding::{Embedding, EmbeddingRequest};
+use error::CsiError;
+use inference::{
+    ChatEvent, ChatRequest, ChatResponse, Completion, CompletionEvent, CompletionRequest,
+};
 use language::SelectLanguageRequest;
-use serde::{Deserialize, Serialize};
+use logging::LogRecord;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use structured::StructuredResponse;
+use tokenization::{DetokenizeRequest, TokenizeRequest};
 
 use crate::{DocumentPath, LanguageCode, SearchRequest};
 
 /// Cognitive System Interface
+///
+/// Every function comes in two flavors: the `try_*` methods report failures as a [`CsiError`],
+/// while their infallible counterparts panic with the error message instead.
+/// Implementors only need to provide the `try_*_concurrently` methods.
 pub trait Csi {
     /// Chunk the given text into smaller pieces that fit within the
     /// maximum token amount for a given model.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
     fn chunk(&self, request: ChunkRequest) -> Vec<String> {
         self.chunk_concurrently(vec![request]).remove(0)
     }
 
     /// Process multiple chunking requests at once
-    fn chunk_concurrently(&self, requests: Vec<ChunkRequest>) -> Vec<Vec<String>>;
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn chunk_concurrently(&self, requests: Vec<ChunkRequest>) -> Vec<Vec<String>> {
+        expect_success(self.try_chunk_concurrently(requests))
+    }
+
+    /// Chunk the given text into smaller pieces that fit within the
+    /// maximum token amount for a given model.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_chunk(&self, request: ChunkRequest) -> Result<Vec<String>, CsiError> {
+        Ok(self.try_chunk_concurrently(vec![request])?.remove(0))
+    }
+
+    /// Process multiple chunking requests at once
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError>;
+
+    /// Chunk the given text like [`Csi::chunk`], but also report the position of each chunk in
+    /// the text and its number of tokens.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn chunk_with_offsets(&self, request: ChunkRequest) -> Vec<Chunk> {
+        self.chunk_with_offsets_concurrently(vec![request])
+            .remove(0)
+    }
+
+    /// Process multiple chunking requests with offsets at once
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn chunk_with_offsets_concurrently(&self, requests: Vec<ChunkRequest>) -> Vec<Vec<Chunk>> {
+        expect_success(self.try_chunk_with_offsets_concurrently(requests))
+    }
+
+    /// Chunk the given text like [`Csi::try_chunk`], but also report the position of each chunk
+    /// in the text and its number of tokens.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_chunk_with_offsets(&self, request: ChunkRequest) -> Result<Vec<Chunk>, CsiError> {
+        Ok(self
+            .try_chunk_with_offsets_concurrently(vec![request])?
+            .remove(0))
+    }
+
+    /// Process multiple chunking requests with offsets at once
+    ///
+    /// By default, the chunks of [`Csi::try_chunk_concurrently`] are located in the text and
+    /// their tokens counted via [`Csi::try_count_tokens_concurrently`], so implementations only
+    /// need to override this if they report offsets natively.
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        let chunks = self.try_chunk_concurrently(requests.clone())?;
+        requests
+            .into_iter()
+            .zip(chunks)
+            .map(|(request, chunks)| {
+                let counts = self.try_count_tokens_concurrently(
+                    chunks
+                        .iter()
+                        .map(|chunk| TokenizeRequest::new(&request.params.model, chunk))
+                        .collect(),
+                )?;
+                chunking::locate_chunks(&request.text, chunks, counts)
+            })
+            .collect()
+    }
 
     /// Search for documents in a given index.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
     fn search(&self, request: SearchRequest) -> Vec<SearchResult> {
         self.search_concurrently(vec![request]).remove(0)
     }
 
     /// Process multiple search requests at once
-    fn search_concurrently(&self, requests: Vec<SearchRequest>) -> Vec<Vec<SearchResult>>;
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn search_concurrently(&self, requests: Vec<SearchRequest>) -> Vec<Vec<SearchResult>> {
+        expect_success(self.try_search_concurrently(requests))
+    }
+
+    /// Search for documents in a given index.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_search(&self, request: SearchRequest) -> Result<Vec<SearchResult>, CsiError> {
+        Ok(self.try_search_concurrently(vec![request])?.remove(0))
+    }
+
+    /// Process multiple search requests at once
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError>;
 
     /// Retrieve a document from the Document Index by its path.
     ///
@@ -45,10 +173,35 @@ pub trait Csi {
     ///
     /// # Errors
     /// Will return an error if document metadata cannot be deserialized.
-    fn documents<'m, Metadata>(
+    fn documents<Metadata>(
         &self,
         paths: Vec<DocumentPath>,
     ) -> anyhow::Result<Vec<Document<Metadata>>>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        Ok(self.try_documents(paths)?)
+    }
+
+    /// Retrieve a document from the Document Index by its path.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails or document metadata cannot be deserialized.
+    fn try_document<Metadata>(&self, path: DocumentPath) -> Result<Document<Metadata>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        Ok(self.try_documents(vec![path])?.remove(0))
+    }
+
+    /// Retrieve multiple documents from the Document Index by their paths.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails or document metadata cannot be deserialized.
+    fn try_documents<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a> + Serialize;
 
@@ -71,38 +224,419 @@ pub trait Csi {
         &self,
         paths: Vec<DocumentPath>,
     ) -> anyhow::Result<Vec<Option<Metadata>>>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        Ok(self.try_documents_metadata(paths)?)
+    }
+
+    /// Retrieve a document's metadata from the Document Index by its path.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails or metadata cannot be deserialized.
+    fn try_document_metadata<Metadata>(
+        &self,
+        path: DocumentPath,
+    ) -> Result<Option<Metadata>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        Ok(self.try_documents_metadata(vec![path])?.remove(0))
+    }
+
+    /// Retrieve multiple documents' metadata from the Document Index by their paths.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails or metadata cannot be deserialized.
+    fn try_documents_metadata<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
     where
         Metadata: for<'a> Deserialize<'a> + Serialize;
 
     /// Send messages with a particular role to a model and receive a response.
     /// Provides a higher level interface than completion for chat scenarios.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
     fn chat(&self, request: ChatRequest) -> ChatResponse {
         self.chat_concurrently(vec![request]).remove(0)
     }
 
     /// Process multiple chat requests at once
-    fn chat_concurrently(&self, requests: Vec<ChatRequest>) -> Vec<ChatResponse>;
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn chat_concurrently(&self, requests: Vec<ChatRequest>) -> Vec<ChatResponse> {
+        expect_success(self.try_chat_concurrently(requests))
+    }
+
+    /// Send messages with a particular role to a model and receive a response.
+    /// Provides a higher level interface than completion for chat scenarios.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_chat(&self, request: ChatRequest) -> Result<ChatResponse, CsiError> {
+        Ok(self.try_chat_concurrently(vec![request])?.remove(0))
+    }
+
+    /// Process multiple chat requests at once
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError>;
+
+    /// Stream the response to a chat request as it is generated.
+    /// Dropping the returned iterator stops the generation.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn chat_stream(&self, request: ChatRequest) -> impl Iterator<Item = ChatEvent> {
+        self.try_chat_stream(request).map(expect_success)
+    }
+
+    /// Stream the response to a chat request as it is generated.
+    /// Dropping the returned iterator stops the generation.
+    ///
+    /// A failure is reported as an error item, after which the stream ends.
+    /// By default, the whole response is requested via [`Csi::try_chat`] and then emitted
+    /// as a single message chunk followed by its tool calls, so implementations only need to
+    /// override this if they support streaming natively.
+    fn try_chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+        let events = match self.try_chat(request) {
+            Ok(response) => response.into_events().into_iter().map(Ok).collect(),
+            Err(error) => vec![Err(error)],
+        };
+        events.into_iter()
+    }
+
+    /// Chat with a model which answers with a value of type `T`.
+    ///
+    /// # Panics
+    /// Will panic if the request fails or the model does not give a valid answer.
+    fn chat_structured<T>(&self, request: ChatRequest) -> StructuredResponse<T>
+    where
+        T: JsonSchema + DeserializeOwned,
+    {
+        expect_success(self.try_chat_structured(request))
+    }
+
+    /// Chat with a model which answers with a value of type `T`.
+    ///
+    /// The model is instructed to answer with JSON matching the schema of `T`, both in the
+    /// system prompt and via [`crate::ResponseFormat::JsonSchema`], unless the request already
+    /// specifies a response format. The first JSON in the answer which matches the schema is
+    /// used, even if it is surrounded by prose. If none matches, the model is asked again with
+    /// the validation error, up to three times in total. Enable the `jsonschema` feature to
+    /// report all violations of the schema instead of only the first one.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails or the model does not give a valid answer.
+    fn try_chat_structured<T>(
+        &self,
+        request: ChatRequest,
+    ) -> Result<StructuredResponse<T>, CsiError>
+    where
+        T: JsonSchema + DeserializeOwned,
+    {
+        structured::chat(self, request)
+    }
 
     /// Generate a completion for a given prompt using a specific model.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
     fn complete(&self, request: CompletionRequest) -> Completion {
         self.complete_concurrently(vec![request]).remove(0)
     }
 
     /// Process multiple completion requests at once
-    fn complete_concurrently(&self, requests: Vec<CompletionRequest>) -> Vec<Completion>;
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn complete_concurrently(&self, requests: Vec<CompletionRequest>) -> Vec<Completion> {
+        expect_success(self.try_complete_concurrently(requests))
+    }
+
+    /// Generate a completion for a given prompt using a specific model.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_complete(&self, request: CompletionRequest) -> Result<Completion, CsiError> {
+        Ok(self.try_complete_concurrently(vec![request])?.remove(0))
+    }
+
+    /// Process multiple completion requests at once
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError>;
+
+    /// Stream a completion for a given prompt as it is generated.
+    /// Dropping the returned iterator stops the generation.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = CompletionEvent> {
+        self.try_completion_stream(request).map(expect_success)
+    }
+
+    /// Stream a completion for a given prompt as it is generated.
+    /// Dropping the returned iterator stops the generation.
+    ///
+    /// A failure is reported as an error item, after which the stream ends.
+    /// By default, the whole completion is requested via [`Csi::try_complete`] and then
+    /// emitted as a single text chunk, so implementations only need to override this if they
+    /// support streaming natively.
+    fn try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+        let events = match self.try_complete(request) {
+            Ok(completion) => completion.into_events().into_iter().map(Ok).collect(),
+            Err(error) => vec![Err(error)],
+        };
+        events.into_iter()
+    }
+
+    /// Embed texts into vectors, whose similarity reflects the similarity in meaning of the texts.
+    /// Returns one embedding per text.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn embed(&self, request: EmbeddingRequest) -> Vec<Embedding> {
+        self.embed_concurrently(vec![request]).remove(0)
+    }
+
+    /// Process multiple embedding requests at once
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn embed_concurrently(&self, requests: Vec<EmbeddingRequest>) -> Vec<Vec<Embedding>> {
+        expect_success(self.try_embed_concurrently(requests))
+    }
+
+    /// Embed texts into vectors, whose similarity reflects the similarity in meaning of the texts.
+    /// Returns one embedding per text.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_embed(&self, request: EmbeddingRequest) -> Result<Vec<Embedding>, CsiError> {
+        Ok(self.try_embed_concurrently(vec![request])?.remove(0))
+    }
+
+    /// Process multiple embedding requests at once
+    ///
+    /// Implementations which do not support embeddings can rely on the default, which fails
+    /// with [`CsiError::UnsupportedFunction`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_embed_concurrently(
+        &self,
+        _requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        Err(CsiError::UnsupportedFunction("embed".to_owned()))
+    }
+
+    /// Split a text into the ids of the tokens the model sees.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn tokenize(&self, request: TokenizeRequest) -> Vec<u32> {
+        self.tokenize_concurrently(vec![request]).remove(0)
+    }
+
+    /// Process multiple tokenize requests at once
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn tokenize_concurrently(&self, requests: Vec<TokenizeRequest>) -> Vec<Vec<u32>> {
+        expect_success(self.try_tokenize_concurrently(requests))
+    }
+
+    /// Split a text into the ids of the tokens the model sees.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_tokenize(&self, request: TokenizeRequest) -> Result<Vec<u32>, CsiError> {
+        Ok(self.try_tokenize_concurrently(vec![request])?.remove(0))
+    }
+
+    /// Process multiple tokenize requests at once
+    ///
+    /// Implementations which do not support tokenization can rely on the default, which fails
+    /// with [`CsiError::UnsupportedFunction`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_tokenize_concurrently(
+        &self,
+        _requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        Err(CsiError::UnsupportedFunction("tokenize".to_owned()))
+    }
+
+    /// Turn token ids back into text.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn detokenize(&self, request: DetokenizeRequest) -> String {
+        self.detokenize_concurrently(vec![request]).remove(0)
+    }
+
+    /// Process multiple detokenize requests at once
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn detokenize_concurrently(&self, requests: Vec<DetokenizeRequest>) -> Vec<String> {
+        expect_success(self.try_detokenize_concurrently(requests))
+    }
+
+    /// Turn token ids back into text.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_detokenize(&self, request: DetokenizeRequest) -> Result<String, CsiError> {
+        Ok(self.try_detokenize_concurrently(vec![request])?.remove(0))
+    }
+
+    /// Process multiple detokenize requests at once
+    ///
+    /// Implementations which do not support tokenization can rely on the default, which fails
+    /// with [`CsiError::UnsupportedFunction`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_detokenize_concurrently(
+        &self,
+        _requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        Err(CsiError::UnsupportedFunction("detokenize".to_owned()))
+    }
+
+    /// The number of tokens the model sees for a text.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn count_tokens(&self, request: TokenizeRequest) -> u32 {
+        self.count_tokens_concurrently(vec![request]).remove(0)
+    }
+
+    /// Process multiple count tokens requests at once
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
+    fn count_tokens_concurrently(&self, requests: Vec<TokenizeRequest>) -> Vec<u32> {
+        expect_success(self.try_count_tokens_concurrently(requests))
+    }
+
+    /// The number of tokens the model sees for a text.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_count_tokens(&self, request: TokenizeRequest) -> Result<u32, CsiError> {
+        Ok(self.try_count_tokens_concurrently(vec![request])?.remove(0))
+    }
+
+    /// Process multiple count tokens requests at once
+    ///
+    /// By default, the texts are tokenized via [`Csi::try_tokenize_concurrently`] and the
+    /// tokens counted, so implementations only need to override this if they can count tokens
+    /// without transferring them.
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_count_tokens_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        Ok(self
+            .try_tokenize_concurrently(requests)?
+            .iter()
+            .map(|tokens| u32::try_from(tokens.len()).unwrap_or(u32::MAX))
+            .collect())
+    }
 
     /// Select the detected language for the provided input based on the list of possible languages.
     /// If no language matches, None is returned.
     ///
     /// text: Text input
     /// languages: All languages that should be considered during detection.
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
     fn select_language(&self, request: SelectLanguageRequest) -> Option<LanguageCode> {
         self.select_language_concurrently(vec![request]).remove(0)
     }
 
     /// Process multiple select language requests at once
+    ///
+    /// # Panics
+    /// Will panic if the request fails.
     fn select_language_concurrently(
         &self,
         requests: Vec<SelectLanguageRequest>,
-    ) -> Vec<Option<LanguageCode>>;
+    ) -> Vec<Option<LanguageCode>> {
+        expect_success(self.try_select_language_concurrently(requests))
+    }
+
+    /// Select the detected language for the provided input based on the list of possible languages.
+    /// If no language matches, None is returned.
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn try_select_language(
+        &self,
+        request: SelectLanguageRequest,
+    ) -> Result<Option<LanguageCode>, CsiError> {
+        Ok(self
+            .try_select_language_concurrently(vec![request])?
+            .remove(0))
+    }
+
+    /// Process multiple select language requests at once
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_select_language_concurrently(
+        &self,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError>;
+
+    /// Process requests to different functions at once, e.g. a chat request together with the
+    /// searches it depends on. Usually called via [`crate::Batch`], which keeps track of the
+    /// response type of each request.
+    ///
+    /// By default, the requests are grouped by function and each group is sent with the
+    /// matching `try_*_concurrently` method, so implementations only need to override this if
+    /// they can process mixed requests in a single call.
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn try_batch(&self, requests: Vec<CsiRequest>) -> Result<Vec<CsiResponse>, CsiError> {
+        batch::send_batch(self, requests)
+    }
+
+    /// Emit a log message, which the Kernel shows together with the other logs of the skill
+    /// run. Logging never fails, by default the record is discarded.
+    fn log(&self, _record: LogRecord) {}
+}
+
+/// Unwrap the result of a fallible CSI call for the infallible methods.
+fn expect_success<T>(result: Result<T, CsiError>) -> T {
+    result.unwrap_or_else(|error| panic!("{error}"))
 }
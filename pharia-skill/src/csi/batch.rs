@@ -0,0 +1,372 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    chunking::{Chunk, ChunkRequest},
+    document_index::{Document, DocumentPath, SearchRequest, SearchResult},
+    embedding::{Embedding, EmbeddingRequest},
+    error::CsiError,
+    inference::{ChatRequest, ChatResponse, Completion, CompletionRequest},
+    language::{LanguageCode, SelectLanguageRequest},
+    tokenization::{DetokenizeRequest, TokenizeRequest},
+    Csi,
+};
+
+/// The batched functions of the Cognitive System Interface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CsiFunction {
+    Chunk,
+    ChunkWithOffsets,
+    Search,
+    Documents,
+    DocumentsMetadata,
+    Chat,
+    Complete,
+    Embed,
+    Tokenize,
+    Detokenize,
+    CountTokens,
+    SelectLanguage,
+}
+
+/// A request to any of the batched functions, so requests to different functions can be sent
+/// together.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsiRequest {
+    Chunk(ChunkRequest),
+    ChunkWithOffsets(ChunkRequest),
+    Search(SearchRequest),
+    Documents(DocumentPath),
+    DocumentsMetadata(DocumentPath),
+    Chat(ChatRequest),
+    Complete(CompletionRequest),
+    Embed(EmbeddingRequest),
+    Tokenize(TokenizeRequest),
+    Detokenize(DetokenizeRequest),
+    CountTokens(TokenizeRequest),
+    SelectLanguage(SelectLanguageRequest),
+}
+
+/// The response to a [`CsiRequest`], of the same variant.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsiResponse {
+    Chunk(Vec<String>),
+    ChunkWithOffsets(Vec<Chunk>),
+    Search(Vec<SearchResult>),
+    Documents(Document),
+    DocumentsMetadata(Option<Value>),
+    Chat(ChatResponse),
+    Complete(Completion),
+    Embed(Vec<Embedding>),
+    Tokenize(Vec<u32>),
+    Detokenize(String),
+    CountTokens(u32),
+    SelectLanguage(Option<LanguageCode>),
+}
+
+impl CsiRequest {
+    /// The function this request is sent to.
+    #[must_use]
+    pub fn function(&self) -> CsiFunction {
+        match self {
+            Self::Chunk(_) => CsiFunction::Chunk,
+            Self::ChunkWithOffsets(_) => CsiFunction::ChunkWithOffsets,
+            Self::Search(_) => CsiFunction::Search,
+            Self::Documents(_) => CsiFunction::Documents,
+            Self::DocumentsMetadata(_) => CsiFunction::DocumentsMetadata,
+            Self::Chat(_) => CsiFunction::Chat,
+            Self::Complete(_) => CsiFunction::Complete,
+            Self::Embed(_) => CsiFunction::Embed,
+            Self::Tokenize(_) => CsiFunction::Tokenize,
+            Self::Detokenize(_) => CsiFunction::Detokenize,
+            Self::CountTokens(_) => CsiFunction::CountTokens,
+            Self::SelectLanguage(_) => CsiFunction::SelectLanguage,
+        }
+    }
+}
+
+/// Split requests into groups of the same function, remembering the position of each request.
+/// Groups are ordered by the first appearance of their function.
+pub(crate) fn group_by_function(
+    requests: Vec<CsiRequest>,
+) -> Vec<(CsiFunction, Vec<(usize, CsiRequest)>)> {
+    let mut groups = Vec::<(CsiFunction, Vec<_>)>::new();
+    for (index, request) in requests.into_iter().enumerate() {
+        let function = request.function();
+        match groups.iter_mut().find(|(f, _)| *f == function) {
+            Some((_, group)) => group.push((index, request)),
+            None => groups.push((function, vec![(index, request)])),
+        }
+    }
+    groups
+}
+
+/// Send each group of requests with the matching `*_concurrently` method and put the responses
+/// back into the order of the requests.
+pub(crate) fn send_batch<C: Csi + ?Sized>(
+    csi: &C,
+    requests: Vec<CsiRequest>,
+) -> Result<Vec<CsiResponse>, CsiError> {
+    let mut responses = requests.iter().map(|_| None).collect::<Vec<_>>();
+    for (function, group) in group_by_function(requests) {
+        let (indices, group): (Vec<_>, Vec<_>) = group.into_iter().unzip();
+        for (index, response) in indices.into_iter().zip(send_group(csi, function, group)?) {
+            responses[index] = Some(response);
+        }
+    }
+    responses
+        .into_iter()
+        .map(|response| {
+            response.ok_or_else(|| CsiError::Decode("Fewer responses than requests".to_owned()))
+        })
+        .collect()
+}
+
+/// Send requests which all belong to `function` with the matching `*_concurrently` method.
+pub(crate) fn send_group<C: Csi + ?Sized>(
+    csi: &C,
+    function: CsiFunction,
+    requests: Vec<CsiRequest>,
+) -> Result<Vec<CsiResponse>, CsiError> {
+    macro_rules! send {
+        ($variant:ident, $method:ident) => {{
+            let requests = requests
+                .into_iter()
+                .map(|request| match request {
+                    CsiRequest::$variant(request) => request,
+                    _ => unreachable!("requests are grouped by function"),
+                })
+                .collect();
+            csi.$method(requests)?
+                .into_iter()
+                .map(CsiResponse::$variant)
+                .collect()
+        }};
+    }
+
+    Ok(match function {
+        CsiFunction::Chunk => send!(Chunk, try_chunk_concurrently),
+        CsiFunction::ChunkWithOffsets => {
+            send!(ChunkWithOffsets, try_chunk_with_offsets_concurrently)
+        }
+        CsiFunction::Search => send!(Search, try_search_concurrently),
+        CsiFunction::Documents => send!(Documents, try_documents),
+        CsiFunction::DocumentsMetadata => send!(DocumentsMetadata, try_documents_metadata),
+        CsiFunction::Chat => send!(Chat, try_chat_concurrently),
+        CsiFunction::Complete => send!(Complete, try_complete_concurrently),
+        CsiFunction::Embed => send!(Embed, try_embed_concurrently),
+        CsiFunction::Tokenize => send!(Tokenize, try_tokenize_concurrently),
+        CsiFunction::Detokenize => send!(Detokenize, try_detokenize_concurrently),
+        CsiFunction::CountTokens => send!(CountTokens, try_count_tokens_concurrently),
+        CsiFunction::SelectLanguage => send!(SelectLanguage, try_select_language_concurrently),
+    })
+}
+
+/// Collects requests to different functions, so they can be sent together with
+/// [`Csi::try_batch`] instead of one round trip per function.
+///
+/// Adding a request returns a typed handle, which retrieves its response once the batch has been
+/// sent.
+///
+/// ```no_run
+/// use pharia_skill::{Batch, ChatRequest, Csi, IndexPath, Message, SearchRequest};
+///
+/// fn answer(csi: &impl Csi, question: &str) -> String {
+///     let index = IndexPath::new("f13", "wikipedia-de", "luminous-base-asymmetric-64");
+///     let mut batch = Batch::new();
+///     let results = batch.search(SearchRequest::new(question, index));
+///     let draft = batch.chat(ChatRequest::new("llama-3.1-8b-instruct", Message::user(question)));
+///
+///     let mut responses = batch.send(csi);
+///     let results = responses.take(results);
+///     let draft = responses.take(draft);
+///     format!("{} ({} sources)", draft.message.content, results.len())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Batch {
+    requests: Vec<CsiRequest>,
+}
+
+/// Retrieves the response to one request of a [`Batch`] from its [`BatchResponses`].
+pub struct BatchHandle<T> {
+    index: usize,
+    extract: fn(CsiResponse) -> Result<T, CsiError>,
+}
+
+/// The responses to all requests of a sent [`Batch`].
+#[derive(Debug)]
+pub struct BatchResponses {
+    responses: Vec<Option<CsiResponse>>,
+}
+
+fn mismatch() -> CsiError {
+    CsiError::Decode("Response does not match the request".to_owned())
+}
+
+macro_rules! add {
+    ($(#[$doc:meta])* $method:ident, $variant:ident, $request:ty => $response:ty) => {
+        $(#[$doc])*
+        pub fn $method(&mut self, request: $request) -> BatchHandle<$response> {
+            self.push(CsiRequest::$variant(request), |response| match response {
+                CsiResponse::$variant(response) => Ok(response),
+                _ => Err(mismatch()),
+            })
+        }
+    };
+}
+
+impl Batch {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push<T>(
+        &mut self,
+        request: CsiRequest,
+        extract: fn(CsiResponse) -> Result<T, CsiError>,
+    ) -> BatchHandle<T> {
+        self.requests.push(request);
+        BatchHandle {
+            index: self.requests.len() - 1,
+            extract,
+        }
+    }
+
+    add!(
+        /// Add a request like [`Csi::chunk`].
+        chunk, Chunk, ChunkRequest => Vec<String>
+    );
+    add!(
+        /// Add a request like [`Csi::chunk_with_offsets`].
+        chunk_with_offsets, ChunkWithOffsets, ChunkRequest => Vec<Chunk>
+    );
+    add!(
+        /// Add a request like [`Csi::search`].
+        search, Search, SearchRequest => Vec<SearchResult>
+    );
+    add!(
+        /// Add a request like [`Csi::chat`].
+        chat, Chat, ChatRequest => ChatResponse
+    );
+    add!(
+        /// Add a request like [`Csi::complete`].
+        complete, Complete, CompletionRequest => Completion
+    );
+    add!(
+        /// Add a request like [`Csi::embed`].
+        embed, Embed, EmbeddingRequest => Vec<Embedding>
+    );
+    add!(
+        /// Add a request like [`Csi::tokenize`].
+        tokenize, Tokenize, TokenizeRequest => Vec<u32>
+    );
+    add!(
+        /// Add a request like [`Csi::detokenize`].
+        detokenize, Detokenize, DetokenizeRequest => String
+    );
+    add!(
+        /// Add a request like [`Csi::count_tokens`].
+        count_tokens, CountTokens, TokenizeRequest => u32
+    );
+    add!(
+        /// Add a request like [`Csi::select_language`].
+        select_language, SelectLanguage, SelectLanguageRequest => Option<LanguageCode>
+    );
+
+    /// Add a request like [`Csi::document`].
+    pub fn document<Metadata>(&mut self, path: DocumentPath) -> BatchHandle<Document<Metadata>>
+    where
+        Metadata: for<'a> Deserialize<'a>,
+    {
+        self.push(CsiRequest::Documents(path), |response| match response {
+            CsiResponse::Documents(document) => Ok(document.deserialize_metadata()?),
+            _ => Err(mismatch()),
+        })
+    }
+
+    /// Add a request like [`Csi::document_metadata`].
+    pub fn document_metadata<Metadata>(
+        &mut self,
+        path: DocumentPath,
+    ) -> BatchHandle<Option<Metadata>>
+    where
+        Metadata: for<'a> Deserialize<'a>,
+    {
+        self.push(
+            CsiRequest::DocumentsMetadata(path),
+            |response| match response {
+                CsiResponse::DocumentsMetadata(metadata) => {
+                    Ok(metadata.map(serde_json::from_value).transpose()?)
+                }
+                _ => Err(mismatch()),
+            },
+        )
+    }
+
+    /// The number of requests in the batch.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Send all requests at once.
+    ///
+    /// # Panics
+    /// Will panic if any of the requests fails.
+    pub fn send<C: Csi + ?Sized>(self, csi: &C) -> BatchResponses {
+        self.try_send(csi).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Send all requests at once.
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    pub fn try_send<C: Csi + ?Sized>(self, csi: &C) -> Result<BatchResponses, CsiError> {
+        let expected = self.requests.len();
+        let responses = csi.try_batch(self.requests)?;
+        if responses.len() != expected {
+            return Err(CsiError::Decode(format!(
+                "Expected {expected} responses, got {}",
+                responses.len()
+            )));
+        }
+        Ok(BatchResponses {
+            responses: responses.into_iter().map(Some).collect(),
+        })
+    }
+}
+
+impl BatchResponses {
+    /// The response to the request the handle was returned for.
+    ///
+    /// # Panics
+    /// Will panic if the handle belongs to a different batch, or the document metadata cannot be
+    /// deserialized.
+    pub fn take<T>(&mut self, handle: BatchHandle<T>) -> T {
+        self.try_take(handle)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// The response to the request the handle was returned for.
+    ///
+    /// # Errors
+    /// Will return an error if the handle belongs to a different batch, or the document metadata
+    /// cannot be deserialized.
+    pub fn try_take<T>(&mut self, handle: BatchHandle<T>) -> Result<T, CsiError> {
+        let response = self
+            .responses
+            .get_mut(handle.index)
+            .and_then(Option::take)
+            .ok_or_else(mismatch)?;
+        (handle.extract)(response)
+    }
+}
@@ -1,7 +1,9 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use super::error::CsiError;
 
 /// Chunking parameters
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ChunkParams {
     /// The name of the model the chunk is intended to be used for.
     /// This must be a known model.
@@ -29,7 +31,7 @@ impl ChunkParams {
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ChunkRequest {
     pub text: String,
     pub params: ChunkParams,
@@ -43,3 +45,82 @@ impl ChunkRequest {
         }
     }
 }
+
+/// A chunk together with its position in the chunked text.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Chunk {
+    pub text: String,
+    /// The offset of the chunk in the chunked text, in bytes of its UTF-8 encoding.
+    /// Use it to slice the original string.
+    pub byte_offset: u64,
+    /// The offset of the chunk in the chunked text, in Unicode scalar values.
+    pub char_offset: u64,
+    /// The number of tokens of the chunk, for the model of the request.
+    pub token_count: u32,
+}
+
+/// Find the position of each chunk in the chunked text. Chunks are expected in order, but may
+/// overlap.
+pub(crate) fn locate_chunks(
+    text: &str,
+    chunks: Vec<String>,
+    token_counts: Vec<u32>,
+) -> Result<Vec<Chunk>, CsiError> {
+    let mut search_start = 0;
+    let mut char_offset = 0;
+    let mut counted_until = 0;
+    chunks
+        .into_iter()
+        .zip(token_counts)
+        .map(|(chunk, token_count)| {
+            let byte_offset = text[search_start..]
+                .find(&chunk)
+                .map(|position| search_start + position)
+                .ok_or_else(|| CsiError::Decode(format!("Chunk not found in text: {chunk}")))?;
+            char_offset += text[counted_until..byte_offset].chars().count();
+            counted_until = byte_offset;
+            // The next chunk may overlap, but starts after this one
+            search_start = text[byte_offset..]
+                .char_indices()
+                .nth(1)
+                .map_or(text.len(), |(next, _)| byte_offset + next);
+            Ok(Chunk {
+                text: chunk,
+                byte_offset: byte_offset as u64,
+                char_offset: char_offset as u64,
+                token_count,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_chunks_are_located() {
+        let text = "Grüß Gott, Grüß Gott";
+        let chunks = vec![
+            "Grüß Gott,".to_owned(),
+            "Gott, Grüß".to_owned(),
+            "Gott".to_owned(),
+        ];
+
+        let chunks = locate_chunks(text, chunks, vec![3, 3, 1]).unwrap();
+
+        let offsets = chunks
+            .iter()
+            .map(|chunk| (chunk.byte_offset, chunk.char_offset))
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![(0, 0), (7, 5), (20, 16)]);
+        assert_eq!(&text[20..], chunks[2].text);
+    }
+
+    #[test]
+    fn missing_chunk_is_an_error() {
+        let result = locate_chunks("Hello", vec!["World".to_owned()], vec![1]);
+
+        assert!(matches!(result, Err(CsiError::Decode(_))));
+    }
+}
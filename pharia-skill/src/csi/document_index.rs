@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 /// Which documents you want to search in, and which type of index should be used
-#[derive(Clone, Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct IndexPath {
     /// The namespace the collection belongs to
     pub namespace: String,
@@ -52,7 +52,7 @@ impl DocumentPath {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct SearchRequest {
     pub query: String,
     pub index_path: IndexPath,
@@ -92,7 +92,7 @@ impl SearchRequest {
 }
 
 /// Result to a search query
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct SearchResult {
     /// The path to the document that was found
     pub document_path: DocumentPath,
@@ -104,7 +104,7 @@ pub struct SearchResult {
     pub end: TextCursor,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct TextCursor {
     /// The index of the item in the document
     pub item: u32,
@@ -112,7 +112,7 @@ pub struct TextCursor {
     pub position: u32,
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum SearchFilter {
     Without(Vec<FilterCondition>),
@@ -120,26 +120,26 @@ pub enum SearchFilter {
     With(Vec<FilterCondition>),
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum FilterCondition {
     Metadata(MetadataFilter),
 }
 
-#[derive(Copy, Clone, Serialize, Debug)]
+#[derive(Copy, Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ModalityType {
     Text,
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct MetadataFilter {
     pub field: String,
     #[serde(flatten)]
     pub condition: MetadataFilterCondition,
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum MetadataFilterCondition {
     GreaterThan(f64),
@@ -154,7 +154,7 @@ pub enum MetadataFilterCondition {
     IsNull(serde_bool::True),
 }
 
-#[derive(Clone, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(untagged)]
 pub enum MetadataFieldValue {
     String(String),
@@ -175,3 +175,22 @@ pub struct Document<Metadata = Value> {
     pub contents: Vec<Modality>,
     pub metadata: Option<Metadata>,
 }
+
+impl Document {
+    /// Deserialize the metadata into a concrete type.
+    pub(crate) fn deserialize_metadata<Metadata>(self) -> serde_json::Result<Document<Metadata>>
+    where
+        Metadata: for<'a> Deserialize<'a>,
+    {
+        let Self {
+            path,
+            contents,
+            metadata,
+        } = self;
+        Ok(Document {
+            path,
+            contents,
+            metadata: metadata.map(serde_json::from_value).transpose()?,
+        })
+    }
+}
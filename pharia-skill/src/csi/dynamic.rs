@@ -0,0 +1,503 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    batch::{CsiRequest, CsiResponse},
+    chunking::{Chunk, ChunkRequest},
+    document_index::{Document, DocumentPath, SearchRequest, SearchResult},
+    embedding::{Embedding, EmbeddingRequest},
+    error::CsiError,
+    inference::{
+        ChatEvent, ChatRequest, ChatResponse, Completion, CompletionEvent, CompletionRequest,
+    },
+    language::{LanguageCode, SelectLanguageRequest},
+    logging::LogRecord,
+    tokenization::{DetokenizeRequest, TokenizeRequest},
+    Csi,
+};
+
+/// Iterator over the events of a streamed response, as returned by [`DynCsi`].
+pub type BoxedStream<'a, Event> = Box<dyn Iterator<Item = Result<Event, CsiError>> + 'a>;
+
+/// Dyn-compatible counterpart of [`Csi`], for choosing an implementation at runtime or storing
+/// one without a generic parameter.
+///
+/// Every [`Csi`] implements this trait, and `dyn DynCsi` implements [`Csi`] again, so a
+/// `&dyn DynCsi` or `Box<dyn DynCsi>` can be used wherever an `impl Csi` is expected.
+/// Document metadata is passed as [`serde_json::Value`] and only deserialized into the
+/// requested type on the way back to [`Csi`].
+///
+/// The methods mirror the `try_*` methods of [`Csi`]. They carry a `dyn_` prefix, so they do
+/// not clash with the methods of [`Csi`] if both traits are in scope.
+pub trait DynCsi {
+    /// See [`Csi::try_chunk_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError>;
+
+    /// See [`Csi::try_chunk_with_offsets_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError>;
+
+    /// See [`Csi::try_search_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError>;
+
+    /// See [`Csi::try_documents`].
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn dyn_try_documents(&self, paths: Vec<DocumentPath>) -> Result<Vec<Document>, CsiError>;
+
+    /// See [`Csi::try_documents_metadata`].
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn dyn_try_documents_metadata(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Value>>, CsiError>;
+
+    /// See [`Csi::try_chat_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError>;
+
+    /// See [`Csi::try_chat_stream`].
+    fn dyn_try_chat_stream(&self, request: ChatRequest) -> BoxedStream<'_, ChatEvent>;
+
+    /// See [`Csi::try_complete_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError>;
+
+    /// See [`Csi::try_completion_stream`].
+    fn dyn_try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> BoxedStream<'_, CompletionEvent>;
+
+    /// See [`Csi::try_embed_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_embed_concurrently(
+        &self,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError>;
+
+    /// See [`Csi::try_tokenize_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_tokenize_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError>;
+
+    /// See [`Csi::try_detokenize_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_detokenize_concurrently(
+        &self,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError>;
+
+    /// See [`Csi::try_count_tokens_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_count_tokens_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError>;
+
+    /// See [`Csi::try_select_language_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_select_language_concurrently(
+        &self,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError>;
+
+    /// See [`Csi::try_batch`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn dyn_try_batch(&self, requests: Vec<CsiRequest>) -> Result<Vec<CsiResponse>, CsiError>;
+
+    /// See [`Csi::log`].
+    fn dyn_log(&self, record: LogRecord);
+}
+
+impl<C: Csi> DynCsi for C {
+    fn dyn_try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        self.try_chunk_concurrently(requests)
+    }
+
+    fn dyn_try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        self.try_chunk_with_offsets_concurrently(requests)
+    }
+
+    fn dyn_try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        self.try_search_concurrently(requests)
+    }
+
+    fn dyn_try_documents(&self, paths: Vec<DocumentPath>) -> Result<Vec<Document>, CsiError> {
+        self.try_documents(paths)
+    }
+
+    fn dyn_try_documents_metadata(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Value>>, CsiError> {
+        self.try_documents_metadata(paths)
+    }
+
+    fn dyn_try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.try_chat_concurrently(requests)
+    }
+
+    fn dyn_try_chat_stream(&self, request: ChatRequest) -> BoxedStream<'_, ChatEvent> {
+        Box::new(self.try_chat_stream(request))
+    }
+
+    fn dyn_try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.try_complete_concurrently(requests)
+    }
+
+    fn dyn_try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> BoxedStream<'_, CompletionEvent> {
+        Box::new(self.try_completion_stream(request))
+    }
+
+    fn dyn_try_embed_concurrently(
+        &self,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        self.try_embed_concurrently(requests)
+    }
+
+    fn dyn_try_tokenize_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        self.try_tokenize_concurrently(requests)
+    }
+
+    fn dyn_try_detokenize_concurrently(
+        &self,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        self.try_detokenize_concurrently(requests)
+    }
+
+    fn dyn_try_count_tokens_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        self.try_count_tokens_concurrently(requests)
+    }
+
+    fn dyn_try_select_language_concurrently(
+        &self,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        self.try_select_language_concurrently(requests)
+    }
+
+    fn dyn_try_batch(&self, requests: Vec<CsiRequest>) -> Result<Vec<CsiResponse>, CsiError> {
+        self.try_batch(requests)
+    }
+
+    fn dyn_log(&self, record: LogRecord) {
+        self.log(record);
+    }
+}
+
+impl Csi for dyn DynCsi + '_ {
+    fn try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        self.dyn_try_chunk_concurrently(requests)
+    }
+
+    fn try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        self.dyn_try_chunk_with_offsets_concurrently(requests)
+    }
+
+    fn try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        self.dyn_try_search_concurrently(requests)
+    }
+
+    fn try_documents<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        self.dyn_try_documents(paths)?
+            .into_iter()
+            .map(|document| Ok(document.deserialize_metadata()?))
+            .collect()
+    }
+
+    fn try_documents_metadata<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        self.dyn_try_documents_metadata(paths)?
+            .into_iter()
+            .map(|metadata| Ok(metadata.map(serde_json::from_value).transpose()?))
+            .collect()
+    }
+
+    fn try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.dyn_try_chat_concurrently(requests)
+    }
+
+    fn try_chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+        self.dyn_try_chat_stream(request)
+    }
+
+    fn try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.dyn_try_complete_concurrently(requests)
+    }
+
+    fn try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+        self.dyn_try_completion_stream(request)
+    }
+
+    fn try_embed_concurrently(
+        &self,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        self.dyn_try_embed_concurrently(requests)
+    }
+
+    fn try_tokenize_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        self.dyn_try_tokenize_concurrently(requests)
+    }
+
+    fn try_detokenize_concurrently(
+        &self,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        self.dyn_try_detokenize_concurrently(requests)
+    }
+
+    fn try_count_tokens_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        self.dyn_try_count_tokens_concurrently(requests)
+    }
+
+    fn try_select_language_concurrently(
+        &self,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        self.dyn_try_select_language_concurrently(requests)
+    }
+
+    fn try_batch(&self, requests: Vec<CsiRequest>) -> Result<Vec<CsiResponse>, CsiError> {
+        self.dyn_try_batch(requests)
+    }
+
+    fn log(&self, record: LogRecord) {
+        self.dyn_log(record);
+    }
+}
+
+/// Implement [`Csi`] for a pointer type by forwarding every method with a native
+/// implementation, so wrapping an implementation in it does not lose e.g. native streaming.
+macro_rules! forward_csi {
+    ($pointer:ty) => {
+        impl<C: Csi + ?Sized> Csi for $pointer {
+            fn try_chunk_concurrently(
+                &self,
+                requests: Vec<ChunkRequest>,
+            ) -> Result<Vec<Vec<String>>, CsiError> {
+                (**self).try_chunk_concurrently(requests)
+            }
+
+            fn try_chunk_with_offsets_concurrently(
+                &self,
+                requests: Vec<ChunkRequest>,
+            ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+                (**self).try_chunk_with_offsets_concurrently(requests)
+            }
+
+            fn try_search_concurrently(
+                &self,
+                requests: Vec<SearchRequest>,
+            ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+                (**self).try_search_concurrently(requests)
+            }
+
+            fn try_documents<Metadata>(
+                &self,
+                paths: Vec<DocumentPath>,
+            ) -> Result<Vec<Document<Metadata>>, CsiError>
+            where
+                Metadata: for<'a> Deserialize<'a> + Serialize,
+            {
+                (**self).try_documents(paths)
+            }
+
+            fn try_documents_metadata<Metadata>(
+                &self,
+                paths: Vec<DocumentPath>,
+            ) -> Result<Vec<Option<Metadata>>, CsiError>
+            where
+                Metadata: for<'a> Deserialize<'a> + Serialize,
+            {
+                (**self).try_documents_metadata(paths)
+            }
+
+            fn try_chat_concurrently(
+                &self,
+                requests: Vec<ChatRequest>,
+            ) -> Result<Vec<ChatResponse>, CsiError> {
+                (**self).try_chat_concurrently(requests)
+            }
+
+            fn try_chat_stream(
+                &self,
+                request: ChatRequest,
+            ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+                (**self).try_chat_stream(request)
+            }
+
+            fn try_complete_concurrently(
+                &self,
+                requests: Vec<CompletionRequest>,
+            ) -> Result<Vec<Completion>, CsiError> {
+                (**self).try_complete_concurrently(requests)
+            }
+
+            fn try_completion_stream(
+                &self,
+                request: CompletionRequest,
+            ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+                (**self).try_completion_stream(request)
+            }
+
+            fn try_embed_concurrently(
+                &self,
+                requests: Vec<EmbeddingRequest>,
+            ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+                (**self).try_embed_concurrently(requests)
+            }
+
+            fn try_tokenize_concurrently(
+                &self,
+                requests: Vec<TokenizeRequest>,
+            ) -> Result<Vec<Vec<u32>>, CsiError> {
+                (**self).try_tokenize_concurrently(requests)
+            }
+
+            fn try_detokenize_concurrently(
+                &self,
+                requests: Vec<DetokenizeRequest>,
+            ) -> Result<Vec<String>, CsiError> {
+                (**self).try_detokenize_concurrently(requests)
+            }
+
+            fn try_count_tokens_concurrently(
+                &self,
+                requests: Vec<TokenizeRequest>,
+            ) -> Result<Vec<u32>, CsiError> {
+                (**self).try_count_tokens_concurrently(requests)
+            }
+
+            fn try_select_language_concurrently(
+                &self,
+                requests: Vec<SelectLanguageRequest>,
+            ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+                (**self).try_select_language_concurrently(requests)
+            }
+
+            fn try_batch(&self, requests: Vec<CsiRequest>) -> Result<Vec<CsiResponse>, CsiError> {
+                (**self).try_batch(requests)
+            }
+
+            fn log(&self, record: LogRecord) {
+                (**self).log(record);
+            }
+        }
+    };
+}
+
+forward_csi!(&C);
+forward_csi!(Box<C>);
+forward_csi!(Arc<C>);
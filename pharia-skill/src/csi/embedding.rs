@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+/// A vector representation of a text, whose similarity to other embeddings reflects the
+/// similarity in meaning of their texts.
+pub type Embedding = Vec<f32>;
+
+/// How the embedded texts are going to be used.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Representation {
+    /// Texts which are compared with other texts of the same kind, e.g. for deduplication or
+    /// clustering.
+    #[default]
+    Symmetric,
+    /// Texts which are searched through, e.g. the documents of a search index.
+    Document,
+    /// Texts which are searched with, e.g. the question of a user.
+    Query,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EmbeddingRequest {
+    /// The name of the model to embed the texts with.
+    pub model: String,
+    /// The texts to embed. Each text results in one embedding.
+    pub texts: Vec<String>,
+    pub representation: Representation,
+    /// Reduce the embeddings to this number of dimensions. Not all models support this.
+    pub dimensions: Option<u32>,
+}
+
+impl EmbeddingRequest {
+    pub fn new(model: impl Into<String>, texts: impl Into<Vec<String>>) -> Self {
+        Self {
+            model: model.into(),
+            texts: texts.into(),
+            representation: Representation::default(),
+            dimensions: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_representation(mut self, representation: Representation) -> Self {
+        self.representation = representation;
+        self
+    }
+
+    #[must_use]
+    pub fn with_dimensions(mut self, dimensions: u32) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+}
+
+/// The cosine of the angle between two embeddings, from -1 for opposite to 1 for the same
+/// direction. Zero vectors are not similar to anything.
+///
+/// # Panics
+/// Will panic if the embeddings have different dimensions.
+#[must_use]
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    assert_eq!(a.len(), b.len(), "Embeddings must have the same dimensions");
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// The `k` embeddings most similar to `query`, as pairs of their index and their cosine
+/// similarity, most similar first.
+///
+/// # Panics
+/// Will panic if the embeddings have different dimensions.
+#[must_use]
+pub fn top_k(query: &[f32], embeddings: &[impl AsRef<[f32]>], k: usize) -> Vec<(usize, f32)> {
+    let mut scores = embeddings
+        .iter()
+        .map(|embedding| cosine_similarity(query, embedding.as_ref()))
+        .enumerate()
+        .collect::<Vec<_>>();
+    scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    scores.truncate(k);
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_similarity_ignores_length() {
+        assert!((cosine_similarity(&[1.0, 1.0], &[2.0, 2.0]) - 1.0).abs() < 1e-6);
+        assert!((cosine_similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn top_k_returns_most_similar_first() {
+        let embeddings = vec![vec![0.0, 1.0], vec![1.0, 0.1], vec![1.0, 0.0]];
+
+        let top = top_k(&[1.0, 0.0], &embeddings, 2);
+
+        assert_eq!(top.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![2, 1]);
+    }
+}
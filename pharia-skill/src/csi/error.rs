@@ -0,0 +1,43 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Errors that can occur when calling a function of the Cognitive System Interface.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "message")]
+#[non_exhaustive]
+pub enum CsiError {
+    /// The request could not be sent, or the response could not be received.
+    Transport(String),
+    /// The response could not be decoded into the expected type.
+    Decode(String),
+    /// The requested model is not known.
+    UnknownModel(String),
+    /// The request contained invalid parameters.
+    InvalidParameters(String),
+    /// The function is not supported by this implementation.
+    UnsupportedFunction(String),
+    /// The request was refused because a usage budget has been used up.
+    BudgetExceeded(String),
+}
+
+impl fmt::Display for CsiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(message) => write!(f, "Transport error: {message}"),
+            Self::Decode(message) => write!(f, "Failed to decode response: {message}"),
+            Self::UnknownModel(model) => write!(f, "Unknown model: {model}"),
+            Self::InvalidParameters(message) => write!(f, "Invalid parameters: {message}"),
+            Self::UnsupportedFunction(function) => write!(f, "Unsupported function: {function}"),
+            Self::BudgetExceeded(message) => write!(f, "Budget exceeded: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for CsiError {}
+
+impl From<serde_json::Error> for CsiError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Decode(error.to_string())
+    }
+}
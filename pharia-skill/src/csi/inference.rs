@@ -1,8 +1,11 @@
-use serde::{Deserialize, Serialize};
+use schemars::{schema_for, JsonSchema};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
 
 /// The reason that the model stopped completing text
-#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
 #[serde(rename_all = "snake_case")]
+#[non_exhaustive]
 pub enum FinishReason {
     /// The model hit a natural stopping point or a provided stop sequence
     Stop,
@@ -10,9 +13,11 @@ pub enum FinishReason {
     Length,
     /// Content was omitted due to a flag from content filters
     ContentFilter,
+    /// The model requested one or more tool calls
+    ToolCalls,
 }
 
-#[derive(Clone, Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Logprobs {
     #[default]
@@ -21,26 +26,26 @@ pub enum Logprobs {
     Top(u8),
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct Logprob {
     pub token: Vec<u8>,
     pub logprob: f64,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct Distribution {
     pub sampled: Logprob,
     pub top: Vec<Logprob>,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub struct TokenUsage {
     pub prompt: u32,
     pub completion: u32,
 }
 
 /// Completion request parameters
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CompletionParams {
     /// The maximum tokens that should be inferred.
     ///
@@ -88,7 +93,7 @@ impl Default for CompletionParams {
 }
 
 /// Parameters required to make a completion request.
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CompletionRequest {
     /// The model to generate a completion from.
     pub model: String,
@@ -116,7 +121,7 @@ impl CompletionRequest {
 
 /// The result of a completion, including the text generated as well as
 /// why the model finished completing.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct Completion {
     /// The text generated by the model
     pub text: String,
@@ -129,10 +134,114 @@ pub struct Completion {
     pub usage: TokenUsage,
 }
 
+/// An event emitted while a completion is streamed.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionEvent {
+    /// A chunk of the completion text
+    Append {
+        /// A chunk of the completion text
+        text: String,
+        /// Corresponding log probabilities for each token in the completion text chunk
+        logprobs: Vec<Distribution>,
+    },
+    /// The model finished generating, no more text will follow
+    End {
+        /// The reason the model finished generating
+        finish_reason: FinishReason,
+    },
+    /// Usage statistics for the completion request, emitted after the end of the completion.
+    Usage {
+        /// Usage statistics for the completion request.
+        usage: TokenUsage,
+    },
+}
+
+impl Completion {
+    /// The events a stream of this completion would have emitted.
+    pub(crate) fn into_events(self) -> Vec<CompletionEvent> {
+        let Self {
+            text,
+            finish_reason,
+            logprobs,
+            usage,
+        } = self;
+        vec![
+            CompletionEvent::Append { text, logprobs },
+            CompletionEvent::End { finish_reason },
+            CompletionEvent::Usage { usage },
+        ]
+    }
+}
+
+/// A call of a tool, requested by the model.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ToolCall {
+    /// Identifies the call, so the result of the tool can be related to it.
+    pub id: String,
+    /// The name of the tool to call
+    pub name: String,
+    /// The arguments to call the tool with, as a JSON object
+    pub arguments: String,
+}
+
+impl ToolCall {
+    /// Deserialize the arguments of the call.
+    ///
+    /// # Errors
+    /// Will return an error if the arguments do not match the expected type.
+    pub fn parse_arguments<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_str(&self.arguments)
+    }
+}
+
+/// A tool the model may call while answering a chat request.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ToolDefinition {
+    /// The name of the tool
+    pub name: String,
+    /// Explains the model what the tool does and when to use it
+    pub description: Option<String>,
+    /// The JSON schema the arguments of a call need to match
+    pub parameters: Value,
+}
+
+impl ToolDefinition {
+    pub fn new(name: impl Into<String>, parameters: Value) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            parameters,
+        }
+    }
+
+    /// A tool whose arguments are described by the JSON schema of `Arguments`.
+    pub fn from_type<Arguments: JsonSchema>(name: impl Into<String>) -> Self {
+        Self::new(name, schema_value::<Arguments>())
+    }
+
+    #[must_use]
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+}
+
+/// The JSON schema of a type as a JSON value.
+pub(crate) fn schema_value<T: JsonSchema>() -> Value {
+    serde_json::to_value(schema_for!(T)).expect("JSON schemas can always be serialized")
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
     pub content: String,
+    /// The tool calls requested by the model. Only set for messages with the `assistant` role.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// The tool call this message contains the result of. Only set for messages with the `tool` role.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
 impl Message {
@@ -140,6 +249,16 @@ impl Message {
         Self {
             role: role.into(),
             content: content.into(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    /// The result of a tool call, to be sent back to the model.
+    pub fn tool(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id.into()),
+            ..Self::new("tool", content)
         }
     }
 
@@ -156,7 +275,7 @@ impl Message {
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ChatParams {
     /// The maximum tokens that should be inferred.
     ///
@@ -179,13 +298,38 @@ pub struct ChatParams {
     /// Use this to control the logarithmic probabilities you want to have returned. This is useful
     /// to figure out how likely it had been that this specific token had been sampled.
     pub logprobs: Logprobs,
+    /// Constrain the format of the generated message. Not all models support this, so it is
+    /// best combined with instructions in the prompt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// Constrains the format of the message generated by the model.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "schema")]
+pub enum ResponseFormat {
+    /// Plain text, the default
+    Text,
+    /// JSON that matches the given JSON schema
+    JsonSchema(Value),
+}
+
+impl ResponseFormat {
+    /// JSON that matches the JSON schema of `T`.
+    #[must_use]
+    pub fn json_schema<T: JsonSchema>() -> Self {
+        Self::JsonSchema(schema_value::<T>())
+    }
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ChatRequest {
     pub model: String,
     pub messages: Vec<Message>,
     pub params: ChatParams,
+    /// The tools the model may call. If empty, the model answers directly.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDefinition>,
 }
 
 impl ChatRequest {
@@ -194,9 +338,22 @@ impl ChatRequest {
             model: model.into(),
             messages: vec![message],
             params: ChatParams::default(),
+            tools: Vec::new(),
         }
     }
 
+    #[must_use]
+    pub fn with_tools(mut self, tools: impl Into<Vec<ToolDefinition>>) -> Self {
+        self.tools = tools.into();
+        self
+    }
+
+    #[must_use]
+    pub fn and_tool(mut self, tool: ToolDefinition) -> Self {
+        self.tools.push(tool);
+        self
+    }
+
     #[must_use]
     pub fn and_message(mut self, message: Message) -> Self {
         self.messages.push(message);
@@ -210,7 +367,7 @@ impl ChatRequest {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ChatResponse {
     /// The message generated by the model
     pub message: Message,
@@ -222,3 +379,103 @@ pub struct ChatResponse {
     /// Usage statistics for the completion request.
     pub usage: TokenUsage,
 }
+
+/// An event emitted while a chat response is streamed.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatEvent {
+    /// The model started a new message
+    MessageBegin {
+        /// The role of the message, e.g. `assistant`
+        role: String,
+    },
+    /// A chunk of the message content
+    MessageAppend {
+        /// A chunk of the message content
+        content: String,
+        /// Corresponding log probabilities for each token in the message content chunk
+        logprobs: Vec<Distribution>,
+    },
+    /// The model requested a call of a tool. Emitted after the content of the message, once for
+    /// each call.
+    ToolCall {
+        /// The requested call
+        tool_call: ToolCall,
+    },
+    /// The model finished the message, no more content will follow
+    MessageEnd {
+        /// The reason the model finished generating
+        finish_reason: FinishReason,
+    },
+    /// Usage statistics for the chat request, emitted after the end of the message.
+    Usage {
+        /// Usage statistics for the chat request.
+        usage: TokenUsage,
+    },
+}
+
+impl ChatResponse {
+    /// The events a stream of this response would have emitted, with the whole content in a
+    /// single chunk.
+    pub(crate) fn into_events(self) -> Vec<ChatEvent> {
+        let Self {
+            message,
+            finish_reason,
+            logprobs,
+            usage,
+        } = self;
+        let begin = [
+            ChatEvent::MessageBegin { role: message.role },
+            ChatEvent::MessageAppend {
+                content: message.content,
+                logprobs,
+            },
+        ];
+        let tool_calls = message
+            .tool_calls
+            .into_iter()
+            .map(|tool_call| ChatEvent::ToolCall { tool_call });
+        let end = [
+            ChatEvent::MessageEnd { finish_reason },
+            ChatEvent::Usage { usage },
+        ];
+        begin.into_iter().chain(tool_calls).chain(end).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_calls_are_emitted_as_events() {
+        let tool_call = ToolCall {
+            id: "call-1".to_owned(),
+            name: "search".to_owned(),
+            arguments: "{}".to_owned(),
+        };
+        let response = ChatResponse {
+            message: Message {
+                tool_calls: vec![tool_call.clone()],
+                ..Message::assistant("")
+            },
+            finish_reason: FinishReason::ToolCalls,
+            logprobs: vec![],
+            usage: TokenUsage {
+                prompt: 1,
+                completion: 2,
+            },
+        };
+
+        let events = response.into_events();
+
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[2], ChatEvent::ToolCall { tool_call });
+        assert_eq!(
+            events[3],
+            ChatEvent::MessageEnd {
+                finish_reason: FinishReason::ToolCalls
+            }
+        );
+    }
+}
@@ -158,7 +158,7 @@ pub enum LanguageCode {
     Zul,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct SelectLanguageRequest {
     pub text: String,
     pub languages: Vec<LanguageCode>,
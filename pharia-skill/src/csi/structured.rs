@@ -0,0 +1,272 @@
+use std::marker::PhantomData;
+
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::{
+    error::CsiError,
+    inference::{schema_value, ChatRequest, ChatResponse, Message, ResponseFormat},
+    Csi,
+};
+
+/// How often the model is asked for a valid answer before giving up.
+const MAX_ATTEMPTS: usize = 3;
+
+/// A chat response whose message has been parsed into a value of type `T`.
+#[derive(Clone, Debug)]
+pub struct StructuredResponse<T> {
+    /// The value parsed from the message of the model
+    pub value: T,
+    /// The response the value has been parsed from
+    pub response: ChatResponse,
+}
+
+/// Ask the model for JSON matching the schema of `T`, and retry with the validation error if
+/// the answer does not match.
+pub(crate) fn chat<C, T>(
+    csi: &C,
+    mut request: ChatRequest,
+) -> Result<StructuredResponse<T>, CsiError>
+where
+    C: Csi + ?Sized,
+    T: JsonSchema + DeserializeOwned,
+{
+    let schema = schema_value::<T>();
+    let validator = Validator::new(&schema)?;
+    instruct(&mut request, schema);
+
+    let mut attempt = 1;
+    loop {
+        let response = csi.try_chat(request.clone())?;
+        let error = match parse(&validator, &response.message.content) {
+            Ok(value) => return Ok(StructuredResponse { value, response }),
+            Err(error) => error,
+        };
+        if attempt == MAX_ATTEMPTS {
+            return Err(CsiError::Decode(format!(
+                "No valid answer after {MAX_ATTEMPTS} attempts: {error}"
+            )));
+        }
+        attempt += 1;
+        request.messages.push(response.message);
+        request.messages.push(Message::user(format!(
+            "Your answer is invalid: {error}\nRespond only with JSON that matches the schema."
+        )));
+    }
+}
+
+/// Tell the model about the schema, both in the system prompt and via the response format.
+fn instruct(request: &mut ChatRequest, schema: Value) {
+    let instruction = format!("Respond only with JSON that matches this JSON schema:\n{schema}");
+    match request.messages.first_mut() {
+        Some(message) if message.role == "system" => {
+            message.content.push_str("\n\n");
+            message.content.push_str(&instruction);
+        }
+        _ => request.messages.insert(0, Message::system(instruction)),
+    }
+    request
+        .params
+        .response_format
+        .get_or_insert(ResponseFormat::JsonSchema(schema));
+}
+
+/// Checks whether a value matches the schema of `T`, and parses it. With the `jsonschema`
+/// feature, all violations of the schema are reported, otherwise only the first one serde
+/// stumbles upon.
+struct Validator<T> {
+    #[cfg(feature = "jsonschema")]
+    schema: jsonschema::Validator,
+    parsed: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Validator<T> {
+    #[cfg_attr(
+        not(feature = "jsonschema"),
+        allow(unused_variables, clippy::unnecessary_wraps)
+    )]
+    fn new(schema: &Value) -> Result<Self, CsiError> {
+        Ok(Self {
+            #[cfg(feature = "jsonschema")]
+            schema: jsonschema::validator_for(schema)
+                .map_err(|error| CsiError::InvalidParameters(error.to_string()))?,
+            parsed: PhantomData,
+        })
+    }
+
+    #[cfg_attr(not(feature = "jsonschema"), allow(clippy::unused_self))]
+    fn validate(&self, value: Value) -> Result<T, String> {
+        #[cfg(feature = "jsonschema")]
+        {
+            let errors = self
+                .schema
+                .iter_errors(&value)
+                .map(|error| format!("{error} at `{}`", error.instance_path))
+                .collect::<Vec<_>>();
+            if !errors.is_empty() {
+                return Err(errors.join("\n"));
+            }
+        }
+        serde_json::from_value(value).map_err(|error| error.to_string())
+    }
+}
+
+/// Parse the first JSON in the answer of a model which is valid. The JSON may be surrounded by
+/// prose or wrapped in a Markdown code block. If no JSON is valid, the error of the first one is
+/// returned.
+fn parse<T: DeserializeOwned>(validator: &Validator<T>, content: &str) -> Result<T, String> {
+    let mut first_error = None;
+    for candidate in json_candidates(content) {
+        match validator.validate(candidate) {
+            Ok(value) => return Ok(value),
+            Err(error) => {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    Err(first_error.unwrap_or_else(|| "The answer does not contain JSON".to_owned()))
+}
+
+/// All JSON values in the answer of a model: the whole answer, or else every outermost object or
+/// array in the prose around it, in order.
+fn json_candidates(content: &str) -> impl Iterator<Item = Value> + '_ {
+    let whole = serde_json::from_str(content).ok();
+    let embedded = whole
+        .is_none()
+        .then(|| balanced_values(content).filter_map(|value| serde_json::from_str(value).ok()));
+    whole.into_iter().chain(embedded.into_iter().flatten())
+}
+
+/// The text of every top-level pair of balanced braces or brackets, found in a single pass.
+/// Brackets within JSON strings are skipped, so are unbalanced ones in the prose around them.
+fn balanced_values(content: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0_usize;
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    content.char_indices().filter_map(move |(index, c)| {
+        if in_string {
+            match (escaped, c) {
+                (true, _) => escaped = false,
+                (false, '\\') => escaped = true,
+                (false, '"') => in_string = false,
+                _ => {}
+            }
+            return None;
+        }
+        match c {
+            '{' | '[' => {
+                if depth == 0 {
+                    start = index;
+                }
+                depth += 1;
+            }
+            '}' | ']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&content[start..=index]);
+                }
+            }
+            '"' if depth > 0 => in_string = true,
+            _ => {}
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+    struct City {
+        city: String,
+    }
+
+    fn city_validator() -> Validator<City> {
+        Validator::new(&schema_value::<City>()).unwrap()
+    }
+
+    #[test]
+    fn json_is_parsed_from_code_block() {
+        let content = "Here you go:\n```json\n{\"city\": \"Heidelberg\"}\n```\nAnything else?";
+
+        assert_eq!(
+            parse(&city_validator(), content),
+            Ok(City {
+                city: "Heidelberg".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn json_is_parsed_after_braces_and_other_json_in_prose() {
+        let content = "The {city} is [1, 2] {\"city\": \"Heidelberg\"}";
+
+        assert_eq!(
+            parse(&city_validator(), content),
+            Ok(City {
+                city: "Heidelberg".to_owned()
+            })
+        );
+        assert_eq!(
+            parse(&city_validator(), "Only {prose}"),
+            Err("The answer does not contain JSON".to_owned())
+        );
+    }
+
+    #[test]
+    fn outermost_json_is_parsed() {
+        let content = r#"{"nested": {"city": "Mannheim"}, "city": "Heidelberg"} [{"a": "}"}]"#;
+
+        assert_eq!(
+            balanced_values(content).collect::<Vec<_>>(),
+            [
+                r#"{"nested": {"city": "Mannheim"}, "city": "Heidelberg"}"#,
+                r#"[{"a": "}"}]"#
+            ]
+        );
+        assert_eq!(
+            parse(&city_validator(), content),
+            Ok(City {
+                city: "Heidelberg".to_owned()
+            })
+        );
+    }
+
+    #[cfg(feature = "jsonschema")]
+    #[test]
+    fn validation_errors_point_to_the_invalid_value() {
+        let schema = json!({
+            "type": "object",
+            "properties": { "age": { "type": "integer" } },
+            "required": ["age"]
+        });
+        let validator = Validator::<Value>::new(&schema).unwrap();
+
+        let error = parse(&validator, "{\"age\": \"old\"}").unwrap_err();
+
+        assert!(error.contains("at `/age`"), "{error}");
+    }
+
+    #[test]
+    fn schema_is_added_to_system_prompt() {
+        let mut request = ChatRequest::new("model", Message::system("Be concise."))
+            .and_message(Message::user("Hi"));
+
+        instruct(&mut request, json!({ "type": "string" }));
+
+        assert_eq!(request.messages.len(), 2);
+        assert!(request.messages[0]
+            .content
+            .starts_with("Be concise.\n\nRespond only with JSON"));
+        assert_eq!(
+            request.params.response_format,
+            Some(ResponseFormat::JsonSchema(json!({ "type": "string" })))
+        );
+    }
+}
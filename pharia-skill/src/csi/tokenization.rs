@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TokenizeRequest {
+    /// The name of the model whose tokenizer should be used.
+    pub model: String,
+    pub text: String,
+}
+
+impl TokenizeRequest {
+    pub fn new(model: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            text: text.into(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DetokenizeRequest {
+    /// The name of the model whose tokenizer should be used.
+    pub model: String,
+    pub tokens: Vec<u32>,
+}
+
+impl DetokenizeRequest {
+    pub fn new(model: impl Into<String>, tokens: impl Into<Vec<u32>>) -> Self {
+        Self {
+            model: model.into(),
+            tokens: tokens.into(),
+        }
+    }
+}
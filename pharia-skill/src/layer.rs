@@ -0,0 +1,524 @@
+pub mod cache;
+pub mod fallback;
+pub mod run_trace;
+#[cfg(feature = "tracing")]
+pub mod trace;
+pub mod usage;
+pub mod window;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    csi::batch::send_batch, ChatEvent, ChatRequest, ChatResponse, Chunk, ChunkRequest, Completion,
+    CompletionEvent, CompletionRequest, Csi, CsiError, CsiRequest, CsiResponse, DetokenizeRequest,
+    Document, DocumentPath, Embedding, EmbeddingRequest, LanguageCode, LogRecord, SearchRequest,
+    SearchResult, SelectLanguageRequest, TokenizeRequest,
+};
+
+/// Middleware around a [`Csi`], which can intercept each kind of request and its response.
+///
+/// Every method receives the wrapped implementation as `inner` and passes the requests on to it
+/// by default, so a layer only overrides the functions it cares about. Use [`CsiExt::layer`] to
+/// wrap an implementation. Document metadata is passed as [`serde_json::Value`] and only
+/// deserialized into the requested type once it leaves the outermost layer.
+///
+/// ```
+/// use pharia_skill::{ChatRequest, ChatResponse, Csi, CsiError, CsiExt, CsiLayer};
+///
+/// /// Sends every chat request to the same model.
+/// struct PinModel(String);
+///
+/// impl CsiLayer for PinModel {
+///     fn chat<C: Csi + ?Sized>(
+///         &self,
+///         inner: &C,
+///         requests: Vec<ChatRequest>,
+///     ) -> Result<Vec<ChatResponse>, CsiError> {
+///         let requests = requests
+///             .into_iter()
+///             .map(|request| ChatRequest { model: self.0.clone(), ..request })
+///             .collect();
+///         inner.try_chat_concurrently(requests)
+///     }
+/// }
+///
+/// fn pinned(csi: impl Csi) -> impl Csi {
+///     csi.layer(PinModel("llama-3.1-8b-instruct".to_owned()))
+/// }
+/// ```
+pub trait CsiLayer {
+    /// Intercept [`Csi::try_chunk_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn chunk<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        inner.try_chunk_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_chunk_with_offsets_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn chunk_with_offsets<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        inner.try_chunk_with_offsets_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_search_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn search<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        inner.try_search_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_documents`].
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn documents<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document>, CsiError> {
+        inner.try_documents(paths)
+    }
+
+    /// Intercept [`Csi::try_documents_metadata`].
+    ///
+    /// # Errors
+    /// Will return an error if the request fails.
+    fn documents_metadata<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Value>>, CsiError> {
+        inner.try_documents_metadata(paths)
+    }
+
+    /// Intercept [`Csi::try_chat_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        inner.try_chat_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_chat_stream`].
+    fn chat_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> + 'a {
+        inner.try_chat_stream(request)
+    }
+
+    /// Intercept [`Csi::try_complete_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        inner.try_complete_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_completion_stream`].
+    fn completion_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> + 'a {
+        inner.try_completion_stream(request)
+    }
+
+    /// Intercept [`Csi::try_embed_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn embed<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        inner.try_embed_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_tokenize_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn tokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        inner.try_tokenize_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_detokenize_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn detokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        inner.try_detokenize_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_count_tokens_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn count_tokens<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        inner.try_count_tokens_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_select_language_concurrently`].
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn select_language<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        inner.try_select_language_concurrently(requests)
+    }
+
+    /// Intercept [`Csi::try_batch`].
+    ///
+    /// By default, the requests of the batch go through the other methods of the layer with
+    /// [`batch_by_function`]. Layers which pass every request on unchanged can forward the batch
+    /// as a whole with `inner.try_batch(requests)` instead, so it stays a single round trip.
+    ///
+    /// # Errors
+    /// Will return an error if any of the requests fails.
+    fn batch<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CsiRequest>,
+    ) -> Result<Vec<CsiResponse>, CsiError> {
+        batch_by_function(self, inner, requests)
+    }
+
+    /// Intercept [`Csi::log`].
+    fn log<C: Csi + ?Sized>(&self, inner: &C, record: LogRecord) {
+        inner.log(record);
+    }
+}
+
+/// Send the requests of a batch through the methods of the layer for their function, e.g.
+/// [`CsiLayer::chat`] for chat requests. Requests to the same function are still sent
+/// concurrently, but requests to different functions are no longer sent together.
+///
+/// # Errors
+/// Will return an error if any of the requests fails.
+pub fn batch_by_function<L, C>(
+    layer: &L,
+    inner: &C,
+    requests: Vec<CsiRequest>,
+) -> Result<Vec<CsiResponse>, CsiError>
+where
+    L: CsiLayer + ?Sized,
+    C: Csi + ?Sized,
+{
+    send_batch(&Layered::new(layer, inner), requests)
+}
+
+impl<L: CsiLayer + ?Sized> CsiLayer for &L {
+    fn chunk<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        (**self).chunk(inner, requests)
+    }
+
+    fn chunk_with_offsets<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        (**self).chunk_with_offsets(inner, requests)
+    }
+
+    fn search<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        (**self).search(inner, requests)
+    }
+
+    fn documents<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document>, CsiError> {
+        (**self).documents(inner, paths)
+    }
+
+    fn documents_metadata<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Value>>, CsiError> {
+        (**self).documents_metadata(inner, paths)
+    }
+
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        (**self).chat(inner, requests)
+    }
+
+    fn chat_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> + 'a {
+        (**self).chat_stream(inner, request)
+    }
+
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        (**self).complete(inner, requests)
+    }
+
+    fn completion_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> + 'a {
+        (**self).completion_stream(inner, request)
+    }
+
+    fn embed<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        (**self).embed(inner, requests)
+    }
+
+    fn tokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        (**self).tokenize(inner, requests)
+    }
+
+    fn detokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        (**self).detokenize(inner, requests)
+    }
+
+    fn count_tokens<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        (**self).count_tokens(inner, requests)
+    }
+
+    fn select_language<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        (**self).select_language(inner, requests)
+    }
+
+    fn batch<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CsiRequest>,
+    ) -> Result<Vec<CsiResponse>, CsiError> {
+        (**self).batch(inner, requests)
+    }
+
+    fn log<C: Csi + ?Sized>(&self, inner: &C, record: LogRecord) {
+        (**self).log(inner, record);
+    }
+}
+
+/// A [`Csi`] wrapped in a [`CsiLayer`].
+pub struct Layered<L, C> {
+    /// The layer intercepting the requests
+    pub layer: L,
+    /// The wrapped implementation
+    pub inner: C,
+}
+
+impl<L, C> Layered<L, C> {
+    pub fn new(layer: L, inner: C) -> Self {
+        Self { layer, inner }
+    }
+}
+
+impl<L: CsiLayer, C: Csi> Csi for Layered<L, C> {
+    fn try_chunk_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        self.layer.chunk(&self.inner, requests)
+    }
+
+    fn try_chunk_with_offsets_concurrently(
+        &self,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        self.layer.chunk_with_offsets(&self.inner, requests)
+    }
+
+    fn try_search_concurrently(
+        &self,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        self.layer.search(&self.inner, requests)
+    }
+
+    fn try_documents<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document<Metadata>>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        self.layer
+            .documents(&self.inner, paths)?
+            .into_iter()
+            .map(|document| Ok(document.deserialize_metadata()?))
+            .collect()
+    }
+
+    fn try_documents_metadata<Metadata>(
+        &self,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Metadata>>, CsiError>
+    where
+        Metadata: for<'a> Deserialize<'a> + Serialize,
+    {
+        self.layer
+            .documents_metadata(&self.inner, paths)?
+            .into_iter()
+            .map(|metadata| Ok(metadata.map(serde_json::from_value).transpose()?))
+            .collect()
+    }
+
+    fn try_chat_concurrently(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.layer.chat(&self.inner, requests)
+    }
+
+    fn try_chat_stream(
+        &self,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> {
+        self.layer.chat_stream(&self.inner, request)
+    }
+
+    fn try_complete_concurrently(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.layer.complete(&self.inner, requests)
+    }
+
+    fn try_completion_stream(
+        &self,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> {
+        self.layer.completion_stream(&self.inner, request)
+    }
+
+    fn try_embed_concurrently(
+        &self,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        self.layer.embed(&self.inner, requests)
+    }
+
+    fn try_tokenize_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        self.layer.tokenize(&self.inner, requests)
+    }
+
+    fn try_detokenize_concurrently(
+        &self,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        self.layer.detokenize(&self.inner, requests)
+    }
+
+    fn try_count_tokens_concurrently(
+        &self,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        self.layer.count_tokens(&self.inner, requests)
+    }
+
+    fn try_select_language_concurrently(
+        &self,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        self.layer.select_language(&self.inner, requests)
+    }
+
+    fn try_batch(&self, requests: Vec<CsiRequest>) -> Result<Vec<CsiResponse>, CsiError> {
+        self.layer.batch(&self.inner, requests)
+    }
+
+    fn log(&self, record: LogRecord) {
+        self.layer.log(&self.inner, record);
+    }
+}
+
+/// Extension methods available on every [`Csi`].
+pub trait CsiExt: Csi + Sized {
+    /// Wrap this implementation in a [`CsiLayer`]. Layers can be stacked, the last one added
+    /// sees the requests first.
+    fn layer<L: CsiLayer>(self, layer: L) -> Layered<L, Self> {
+        Layered::new(layer, self)
+    }
+}
+
+impl<C: Csi> CsiExt for C {}
@@ -0,0 +1,279 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{CsiLayer, Layered};
+use crate::{
+    ChatRequest, ChatResponse, ChunkRequest, Completion, CompletionRequest, Csi, CsiError,
+    LanguageCode, SearchRequest, SearchResult, SelectLanguageRequest,
+};
+
+/// A [`Csi`] which remembers the responses of its inner implementation.
+pub type CachingCsi<C, S = LruStore> = Layered<Cache<S>, C>;
+
+/// Storage for the responses remembered by a [`Cache`].
+///
+/// Keys are derived from the serialized requests, values are the serialized responses.
+pub trait CacheStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+
+    fn insert(&self, key: String, value: Vec<u8>);
+}
+
+/// Keeps a limited number of responses in memory, evicting the least recently used first.
+pub struct LruStore {
+    capacity: usize,
+    state: Mutex<LruState>,
+}
+
+#[derive(Default)]
+struct LruState {
+    /// The value of each key, together with the time it was last used
+    entries: HashMap<String, (Vec<u8>, u64)>,
+    /// The key used at each time, oldest first
+    usage: BTreeMap<u64, String>,
+    now: u64,
+}
+
+impl LruState {
+    /// Mark the key as most recently used.
+    fn touch(&mut self, key: &str) {
+        self.now += 1;
+        if let Some((_, used)) = self.entries.get_mut(key) {
+            self.usage.remove(used);
+            *used = self.now;
+            self.usage.insert(self.now, key.to_owned());
+        }
+    }
+}
+
+impl LruStore {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(LruState::default()),
+        }
+    }
+
+    /// The number of responses currently remembered.
+    ///
+    /// # Panics
+    /// Will panic if another thread panicked while using the store.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CacheStore for LruStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        state.touch(key);
+        state.entries.get(key).map(|(value, _)| value.clone())
+    }
+
+    fn insert(&self, key: String, value: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.now += 1;
+        let now = state.now;
+        if let Some((_, used)) = state.entries.insert(key.clone(), (value, now)) {
+            state.usage.remove(&used);
+        }
+        state.usage.insert(now, key);
+        while state.entries.len() > self.capacity {
+            let Some((_, oldest)) = state.usage.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+    }
+}
+
+/// Layer which remembers the responses to deterministic requests, so they are only sent once.
+///
+/// Chunking, search and language selection are always cached. Completion and chat requests
+/// are only cached if they are sampled without randomness, i.e. their temperature is unset or
+/// zero. For batched calls, only the requests missing from the cache are passed on.
+pub struct Cache<S = LruStore> {
+    store: S,
+}
+
+impl Cache {
+    /// Remember up to `capacity` responses in memory.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self::with_store(LruStore::new(capacity))
+    }
+}
+
+impl<S: CacheStore> Cache<S> {
+    pub fn with_store(store: S) -> Self {
+        Self { store }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Answer the cacheable requests from the store, and only send the others to `fetch`.
+    fn cached<R, T>(
+        &self,
+        function: &str,
+        requests: Vec<R>,
+        cacheable: impl Fn(&R) -> bool,
+        fetch: impl FnOnce(Vec<R>) -> Result<Vec<T>, CsiError>,
+    ) -> Result<Vec<T>, CsiError>
+    where
+        R: Serialize,
+        T: Serialize + DeserializeOwned,
+    {
+        let keys = requests
+            .iter()
+            .map(|request| {
+                let key = serde_json::to_string(request).ok()?;
+                cacheable(request).then(|| format!("{function}:{key}"))
+            })
+            .collect::<Vec<_>>();
+        let mut responses = keys
+            .iter()
+            .map(|key| {
+                let value = self.store.get(key.as_ref()?)?;
+                serde_json::from_slice::<T>(&value).ok()
+            })
+            .collect::<Vec<_>>();
+
+        let misses = requests
+            .into_iter()
+            .zip(&responses)
+            .filter(|(_, response)| response.is_none())
+            .map(|(request, _)| request)
+            .collect::<Vec<_>>();
+        if !misses.is_empty() {
+            let mut fetched = fetch(misses)?.into_iter();
+            for (response, key) in responses.iter_mut().zip(&keys) {
+                if response.is_some() {
+                    continue;
+                }
+                let value = fetched.next().ok_or_else(|| {
+                    CsiError::Decode(format!("Fewer {function} responses than requests"))
+                })?;
+                if let (Some(key), Ok(bytes)) = (key, serde_json::to_vec(&value)) {
+                    self.store.insert(key.clone(), bytes);
+                }
+                *response = Some(value);
+            }
+        }
+        Ok(responses.into_iter().flatten().collect())
+    }
+}
+
+/// Whether sampling with this temperature always yields the same result.
+fn is_deterministic(temperature: Option<f64>) -> bool {
+    temperature.is_none_or(|temperature| temperature == 0.0)
+}
+
+impl<S: CacheStore> CsiLayer for Cache<S> {
+    fn chunk<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        self.cached(
+            "chunk",
+            requests,
+            |_| true,
+            |requests| inner.try_chunk_concurrently(requests),
+        )
+    }
+
+    fn search<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        self.cached(
+            "search",
+            requests,
+            |_| true,
+            |requests| inner.try_search_concurrently(requests),
+        )
+    }
+
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.cached(
+            "chat",
+            requests,
+            |request| is_deterministic(request.params.temperature),
+            |requests| inner.try_chat_concurrently(requests),
+        )
+    }
+
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.cached(
+            "complete",
+            requests,
+            |request| is_deterministic(request.params.temperature),
+            |requests| inner.try_complete_concurrently(requests),
+        )
+    }
+
+    fn select_language<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        self.cached(
+            "select_language",
+            requests,
+            |_| true,
+            |requests| inner.try_select_language_concurrently(requests),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let store = LruStore::new(2);
+        store.insert("a".to_owned(), b"1".to_vec());
+        store.insert("b".to_owned(), b"2".to_vec());
+
+        store.get("a");
+        store.insert("c".to_owned(), b"3".to_vec());
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+        assert_eq!(store.get("b"), None);
+        assert_eq!(store.get("c"), Some(b"3".to_vec()));
+    }
+
+    #[test]
+    fn only_deterministic_sampling_is_cached() {
+        assert!(is_deterministic(None));
+        assert!(is_deterministic(Some(0.0)));
+        assert!(!is_deterministic(Some(0.7)));
+    }
+}
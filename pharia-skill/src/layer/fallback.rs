@@ -0,0 +1,233 @@
+use super::{CsiLayer, Layered};
+use crate::{
+    ChatRequest, ChatResponse, Completion, CompletionRequest, Csi, CsiError, FinishReason,
+};
+
+/// A [`Csi`] which retries failed chat and completion requests with other models.
+pub type FallbackCsi<C> = Layered<Fallback, C>;
+
+/// Decides whether an answer counts as failed, so the request is retried with the next model.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retry if the model stopped for one of these reasons
+    pub finish_reasons: Vec<FinishReason>,
+    /// Retry if the answer is empty or only consists of whitespace
+    pub empty_answer: bool,
+    /// Retry if the request fails with an error
+    pub error: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Retry content filtered, truncated and empty answers, as well as errors.
+    fn default() -> Self {
+        Self {
+            finish_reasons: vec![FinishReason::ContentFilter, FinishReason::Length],
+            empty_answer: true,
+            error: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry, e.g. to disable fallback for one function.
+    #[must_use]
+    pub fn never() -> Self {
+        Self {
+            finish_reasons: Vec::new(),
+            empty_answer: false,
+            error: false,
+        }
+    }
+
+    fn is_failure(&self, finish_reason: FinishReason, empty: bool) -> bool {
+        self.finish_reasons.contains(&finish_reason) || (self.empty_answer && empty)
+    }
+}
+
+/// A response together with the model which produced it.
+#[derive(Clone, Debug)]
+pub struct Answered<T> {
+    pub response: T,
+    /// The model which answered the request, either the requested one or a fallback
+    pub model: String,
+}
+
+/// Layer which retries failed chat and completion requests with fallback models.
+///
+/// Each request is sent to its own model first, then to the fallback models in order, until
+/// an answer does not count as failed according to the [`RetryPolicy`] of the function.
+/// Only the failed requests of a batch are sent again. If all models fail, the answer of the
+/// last one is returned. Use [`FallbackCsi::try_chat_answered`] or
+/// [`FallbackCsi::try_complete_answered`] to learn which model answered each request.
+/// Streams are passed on without fallback.
+pub struct Fallback {
+    models: Vec<String>,
+    chat: RetryPolicy,
+    complete: RetryPolicy,
+}
+
+impl Fallback {
+    pub fn new<I>(models: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self {
+            models: models.into_iter().map(Into::into).collect(),
+            chat: RetryPolicy::default(),
+            complete: RetryPolicy::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_chat_policy(mut self, policy: RetryPolicy) -> Self {
+        self.chat = policy;
+        self
+    }
+
+    #[must_use]
+    pub fn with_completion_policy(mut self, policy: RetryPolicy) -> Self {
+        self.complete = policy;
+        self
+    }
+
+    fn chat_answered<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<Answered<ChatResponse>>, CsiError> {
+        self.with_fallback(
+            requests,
+            &self.chat,
+            |request| &mut request.model,
+            |response: &ChatResponse| {
+                let empty = response.message.content.trim().is_empty()
+                    && response.message.tool_calls.is_empty();
+                self.chat.is_failure(response.finish_reason, empty)
+            },
+            |requests| inner.try_chat_concurrently(requests),
+        )
+    }
+
+    fn complete_answered<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Answered<Completion>>, CsiError> {
+        self.with_fallback(
+            requests,
+            &self.complete,
+            |request| &mut request.model,
+            |completion: &Completion| {
+                let empty = completion.text.trim().is_empty();
+                self.complete.is_failure(completion.finish_reason, empty)
+            },
+            |requests| inner.try_complete_concurrently(requests),
+        )
+    }
+
+    /// Send the requests to each model in turn, until all of them have been answered.
+    fn with_fallback<R: Clone, T>(
+        &self,
+        requests: Vec<R>,
+        policy: &RetryPolicy,
+        model: impl Fn(&mut R) -> &mut String,
+        failed: impl Fn(&T) -> bool,
+        send: impl Fn(Vec<R>) -> Result<Vec<T>, CsiError>,
+    ) -> Result<Vec<Answered<T>>, CsiError> {
+        let mut answers = requests.iter().map(|_| None).collect::<Vec<_>>();
+        let mut pending = (0..requests.len()).collect::<Vec<_>>();
+
+        for attempt in 0..=self.models.len() {
+            if pending.is_empty() {
+                break;
+            }
+            let is_last = attempt == self.models.len();
+            let mut batch = pending
+                .iter()
+                .map(|&index| requests[index].clone())
+                .collect::<Vec<_>>();
+            if let Some(fallback) = attempt.checked_sub(1).map(|i| &self.models[i]) {
+                for request in &mut batch {
+                    fallback.clone_into(model(request));
+                }
+            }
+            let models = batch
+                .iter_mut()
+                .map(|request| model(request).clone())
+                .collect::<Vec<_>>();
+
+            let responses = match send(batch) {
+                Ok(responses) => responses,
+                Err(_) if policy.error && !is_last => continue,
+                Err(error) => return Err(error),
+            };
+            let mut retry = Vec::new();
+            for ((index, response), model) in pending.into_iter().zip(responses).zip(models) {
+                if failed(&response) && !is_last {
+                    retry.push(index);
+                }
+                answers[index] = Some(Answered { response, model });
+            }
+            pending = retry;
+        }
+
+        answers
+            .into_iter()
+            .map(|answer| {
+                answer.ok_or_else(|| CsiError::Decode("Fewer responses than requests".to_owned()))
+            })
+            .collect()
+    }
+}
+
+impl CsiLayer for Fallback {
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        Ok(self
+            .chat_answered(inner, requests)?
+            .into_iter()
+            .map(|answer| answer.response)
+            .collect())
+    }
+
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        Ok(self
+            .complete_answered(inner, requests)?
+            .into_iter()
+            .map(|answer| answer.response)
+            .collect())
+    }
+}
+
+impl<C: Csi> FallbackCsi<C> {
+    /// Like [`Csi::try_chat_concurrently`], but also reports which model answered each request.
+    ///
+    /// # Errors
+    /// Will return an error if a request fails with the last model.
+    pub fn try_chat_answered(
+        &self,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<Answered<ChatResponse>>, CsiError> {
+        self.layer.chat_answered(&self.inner, requests)
+    }
+
+    /// Like [`Csi::try_complete_concurrently`], but also reports which model answered each
+    /// request.
+    ///
+    /// # Errors
+    /// Will return an error if a request fails with the last model.
+    pub fn try_complete_answered(
+        &self,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Answered<Completion>>, CsiError> {
+        self.layer.complete_answered(&self.inner, requests)
+    }
+}
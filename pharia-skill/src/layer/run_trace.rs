@@ -0,0 +1,235 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{CsiLayer, Layered};
+use crate::{
+    ActiveStep, ChatEvent, ChatRequest, ChatResponse, Chunk, ChunkRequest, Completion,
+    CompletionEvent, CompletionRequest, Csi, CsiError, CsiRequest, CsiResponse, DetokenizeRequest,
+    Document, DocumentPath, Embedding, EmbeddingRequest, LanguageCode, RunTrace, SearchRequest,
+    SearchResult, SelectLanguageRequest, TokenizeRequest,
+};
+
+/// A [`Csi`] which records every call as a step of a [`RunTrace`].
+pub type RunTraceCsi<C> = Layered<RunTrace, C>;
+
+impl RunTrace {
+    /// Record the call in a step named after the function, with the requests as input and the
+    /// responses or the error as output.
+    fn traced<R: Serialize, T: Serialize>(
+        &self,
+        function: &str,
+        requests: Vec<R>,
+        send: impl FnOnce(Vec<R>) -> Result<Vec<T>, CsiError>,
+    ) -> Result<Vec<T>, CsiError> {
+        self.in_step(function, |step| {
+            step.set_input(&requests);
+            let result = send(requests);
+            match &result {
+                Ok(responses) => step.set_output(responses),
+                Err(error) => step.set_error(error),
+            }
+            result
+        })
+    }
+}
+
+/// The step of a stream, with the events received so far.
+struct StreamStep<E: Serialize> {
+    step: Option<ActiveStep>,
+    events: Vec<E>,
+}
+
+impl<E: Serialize> StreamStep<E> {
+    /// Record the events as output and close the step.
+    fn close(&mut self) {
+        if let Some(step) = self.step.take() {
+            step.set_output(&self.events);
+        }
+    }
+}
+
+impl<E: Serialize> Drop for StreamStep<E> {
+    /// A stream which is dropped before its end is recorded with the events received so far.
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Record the stream in `step`, with all events as output. The step is closed once the stream
+/// ends or fails, so later calls do not become its children while the stream is still held.
+fn traced_stream<'a, R: Serialize, E: Serialize + Clone + 'a>(
+    step: ActiveStep,
+    request: R,
+    stream: impl FnOnce(R) -> Box<dyn Iterator<Item = Result<E, CsiError>> + 'a>,
+) -> impl Iterator<Item = Result<E, CsiError>> + 'a {
+    step.set_input(&request);
+    let mut inner = stream(request);
+    let mut recorded = StreamStep {
+        step: Some(step),
+        events: Vec::new(),
+    };
+    std::iter::from_fn(move || {
+        let event = inner.next();
+        match &event {
+            Some(Ok(event)) => recorded.events.push(event.clone()),
+            Some(Err(error)) => {
+                if let Some(step) = &recorded.step {
+                    step.set_error(error);
+                }
+                recorded.close();
+            }
+            None => recorded.close(),
+        }
+        event
+    })
+}
+
+impl CsiLayer for RunTrace {
+    fn chunk<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        self.traced("chunk", requests, |requests| {
+            inner.try_chunk_concurrently(requests)
+        })
+    }
+
+    fn chunk_with_offsets<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        self.traced("chunk_with_offsets", requests, |requests| {
+            inner.try_chunk_with_offsets_concurrently(requests)
+        })
+    }
+
+    fn search<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        self.traced("search", requests, |requests| {
+            inner.try_search_concurrently(requests)
+        })
+    }
+
+    fn documents<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document>, CsiError> {
+        self.traced("documents", paths, |paths| inner.try_documents(paths))
+    }
+
+    fn documents_metadata<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Value>>, CsiError> {
+        self.traced("documents_metadata", paths, |paths| {
+            inner.try_documents_metadata(paths)
+        })
+    }
+
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.traced("chat", requests, |requests| {
+            inner.try_chat_concurrently(requests)
+        })
+    }
+
+    fn chat_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> + 'a {
+        traced_stream(self.step("chat_stream"), request, |request| {
+            Box::new(inner.try_chat_stream(request))
+        })
+    }
+
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.traced("complete", requests, |requests| {
+            inner.try_complete_concurrently(requests)
+        })
+    }
+
+    fn completion_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> + 'a {
+        traced_stream(self.step("completion_stream"), request, |request| {
+            Box::new(inner.try_completion_stream(request))
+        })
+    }
+
+    fn embed<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        self.traced("embed", requests, |requests| {
+            inner.try_embed_concurrently(requests)
+        })
+    }
+
+    fn tokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        self.traced("tokenize", requests, |requests| {
+            inner.try_tokenize_concurrently(requests)
+        })
+    }
+
+    fn detokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        self.traced("detokenize", requests, |requests| {
+            inner.try_detokenize_concurrently(requests)
+        })
+    }
+
+    fn count_tokens<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        self.traced("count_tokens", requests, |requests| {
+            inner.try_count_tokens_concurrently(requests)
+        })
+    }
+
+    fn select_language<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        self.traced("select_language", requests, |requests| {
+            inner.try_select_language_concurrently(requests)
+        })
+    }
+
+    /// Batches are sent on as a whole, so they stay a single round trip, and recorded in one
+    /// step named `batch`.
+    fn batch<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CsiRequest>,
+    ) -> Result<Vec<CsiResponse>, CsiError> {
+        self.traced("batch", requests, |requests| inner.try_batch(requests))
+    }
+}
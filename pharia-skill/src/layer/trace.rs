@@ -0,0 +1,354 @@
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+use tracing::{field::Empty, Span};
+
+use super::{CsiLayer, Layered};
+use crate::{
+    ChatEvent, ChatRequest, ChatResponse, Chunk, ChunkRequest, Completion, CompletionEvent,
+    CompletionRequest, Csi, CsiError, CsiRequest, CsiResponse, DetokenizeRequest, Document,
+    DocumentPath, Embedding, EmbeddingRequest, FinishReason, LanguageCode, SearchRequest,
+    SearchResult, SelectLanguageRequest, TokenUsage, TokenizeRequest,
+};
+
+/// A [`Csi`] which opens a [`tracing`] span for every call.
+pub type TracedCsi<C> = Layered<Tracing, C>;
+
+/// Layer which opens a span named `csi` for every call, with the target `pharia_skill::csi`.
+///
+/// Each span records the `function`, the `batch_size` and, where the requests name one, the
+/// `model`. Completion and chat spans also record the summed `prompt_tokens` and
+/// `completion_tokens` and the `finish_reason`, streams once the events arrive. Failed calls
+/// record the `error`. Batches are traced as a whole in one span of the function `batch`. The
+/// duration of a call is the lifetime of its span, which is measured by
+/// the subscriber, e.g. [`crate::SpanCollector`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tracing;
+
+/// Open the span of one call. Models are only recorded if the requests name one.
+fn open<'a>(function: &str, batch_size: usize, models: impl IntoIterator<Item = &'a str>) -> Span {
+    let span = tracing::info_span!(
+        target: "pharia_skill::csi",
+        "csi",
+        function,
+        batch_size,
+        model = Empty,
+        prompt_tokens = Empty,
+        completion_tokens = Empty,
+        finish_reason = Empty,
+        error = Empty,
+    );
+    let models = models.into_iter().collect::<BTreeSet<_>>();
+    if !models.is_empty() {
+        span.record("model", join(models));
+    }
+    span
+}
+
+fn join<'a>(values: impl IntoIterator<Item = &'a str>) -> String {
+    values.into_iter().collect::<Vec<_>>().join(", ")
+}
+
+fn finish_reason_name(finish_reason: FinishReason) -> &'static str {
+    match finish_reason {
+        FinishReason::Stop => "stop",
+        FinishReason::Length => "length",
+        FinishReason::ContentFilter => "content_filter",
+        FinishReason::ToolCalls => "tool_calls",
+    }
+}
+
+/// The model named by a request of a batch, if any.
+fn request_model(request: &CsiRequest) -> Option<&str> {
+    match request {
+        CsiRequest::Chunk(r) | CsiRequest::ChunkWithOffsets(r) => Some(&r.params.model),
+        CsiRequest::Chat(r) => Some(&r.model),
+        CsiRequest::Complete(r) => Some(&r.model),
+        CsiRequest::Embed(r) => Some(&r.model),
+        CsiRequest::Tokenize(r) | CsiRequest::CountTokens(r) => Some(&r.model),
+        CsiRequest::Detokenize(r) => Some(&r.model),
+        CsiRequest::Search(_)
+        | CsiRequest::Documents(_)
+        | CsiRequest::DocumentsMetadata(_)
+        | CsiRequest::SelectLanguage(_) => None,
+    }
+}
+
+/// Record the summed usage and the distinct finish reasons of a batch.
+fn record_answers<'a>(
+    span: &Span,
+    answers: impl IntoIterator<Item = (&'a TokenUsage, FinishReason)>,
+) {
+    let mut prompt = 0u64;
+    let mut completion = 0u64;
+    let mut finish_reasons = BTreeSet::new();
+    for (usage, finish_reason) in answers {
+        prompt += u64::from(usage.prompt);
+        completion += u64::from(usage.completion);
+        finish_reasons.insert(finish_reason_name(finish_reason));
+    }
+    span.record("prompt_tokens", prompt);
+    span.record("completion_tokens", completion);
+    span.record("finish_reason", join(finish_reasons));
+}
+
+/// Send the requests within `span`, recording the error if the call fails.
+fn traced<R, T>(
+    span: &Span,
+    requests: Vec<R>,
+    send: impl FnOnce(Vec<R>) -> Result<Vec<T>, CsiError>,
+) -> Result<Vec<T>, CsiError> {
+    let result = span.in_scope(|| send(requests));
+    if let Err(error) = &result {
+        span.record("error", tracing::field::display(error));
+    }
+    result
+}
+
+impl CsiLayer for Tracing {
+    fn chunk<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        let span = open(
+            "chunk",
+            requests.len(),
+            requests.iter().map(|r| r.params.model.as_str()),
+        );
+        traced(&span, requests, |requests| {
+            inner.try_chunk_concurrently(requests)
+        })
+    }
+
+    fn chunk_with_offsets<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        let span = open(
+            "chunk_with_offsets",
+            requests.len(),
+            requests.iter().map(|r| r.params.model.as_str()),
+        );
+        traced(&span, requests, |requests| {
+            inner.try_chunk_with_offsets_concurrently(requests)
+        })
+    }
+
+    fn search<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        let span = open("search", requests.len(), []);
+        traced(&span, requests, |requests| {
+            inner.try_search_concurrently(requests)
+        })
+    }
+
+    fn documents<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document>, CsiError> {
+        let span = open("documents", paths.len(), []);
+        traced(&span, paths, |paths| inner.try_documents(paths))
+    }
+
+    fn documents_metadata<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Value>>, CsiError> {
+        let span = open("documents_metadata", paths.len(), []);
+        traced(&span, paths, |paths| inner.try_documents_metadata(paths))
+    }
+
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        let span = open(
+            "chat",
+            requests.len(),
+            requests.iter().map(|r| r.model.as_str()),
+        );
+        let responses = traced(&span, requests, |requests| {
+            inner.try_chat_concurrently(requests)
+        })?;
+        record_answers(&span, responses.iter().map(|r| (&r.usage, r.finish_reason)));
+        Ok(responses)
+    }
+
+    fn chat_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> + 'a {
+        let span = open("chat_stream", 1, [request.model.as_str()]);
+        let mut events = span.in_scope(|| inner.try_chat_stream(request));
+        std::iter::from_fn(move || {
+            let event = span.in_scope(|| events.next());
+            match &event {
+                Some(Ok(ChatEvent::MessageEnd { finish_reason })) => {
+                    span.record("finish_reason", finish_reason_name(*finish_reason));
+                }
+                Some(Ok(ChatEvent::Usage { usage })) => {
+                    span.record("prompt_tokens", usage.prompt);
+                    span.record("completion_tokens", usage.completion);
+                }
+                Some(Err(error)) => {
+                    span.record("error", tracing::field::display(error));
+                }
+                _ => {}
+            }
+            event
+        })
+    }
+
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        let span = open(
+            "complete",
+            requests.len(),
+            requests.iter().map(|r| r.model.as_str()),
+        );
+        let completions = traced(&span, requests, |requests| {
+            inner.try_complete_concurrently(requests)
+        })?;
+        record_answers(
+            &span,
+            completions.iter().map(|c| (&c.usage, c.finish_reason)),
+        );
+        Ok(completions)
+    }
+
+    fn completion_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> + 'a {
+        let span = open("completion_stream", 1, [request.model.as_str()]);
+        let mut events = span.in_scope(|| inner.try_completion_stream(request));
+        std::iter::from_fn(move || {
+            let event = span.in_scope(|| events.next());
+            match &event {
+                Some(Ok(CompletionEvent::End { finish_reason })) => {
+                    span.record("finish_reason", finish_reason_name(*finish_reason));
+                }
+                Some(Ok(CompletionEvent::Usage { usage })) => {
+                    span.record("prompt_tokens", usage.prompt);
+                    span.record("completion_tokens", usage.completion);
+                }
+                Some(Err(error)) => {
+                    span.record("error", tracing::field::display(error));
+                }
+                _ => {}
+            }
+            event
+        })
+    }
+
+    fn embed<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        let span = open(
+            "embed",
+            requests.len(),
+            requests.iter().map(|r| r.model.as_str()),
+        );
+        traced(&span, requests, |requests| {
+            inner.try_embed_concurrently(requests)
+        })
+    }
+
+    fn tokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        let span = open(
+            "tokenize",
+            requests.len(),
+            requests.iter().map(|r| r.model.as_str()),
+        );
+        traced(&span, requests, |requests| {
+            inner.try_tokenize_concurrently(requests)
+        })
+    }
+
+    fn detokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        let span = open(
+            "detokenize",
+            requests.len(),
+            requests.iter().map(|r| r.model.as_str()),
+        );
+        traced(&span, requests, |requests| {
+            inner.try_detokenize_concurrently(requests)
+        })
+    }
+
+    fn count_tokens<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        let span = open(
+            "count_tokens",
+            requests.len(),
+            requests.iter().map(|r| r.model.as_str()),
+        );
+        traced(&span, requests, |requests| {
+            inner.try_count_tokens_concurrently(requests)
+        })
+    }
+
+    fn select_language<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        let span = open("select_language", requests.len(), []);
+        traced(&span, requests, |requests| {
+            inner.try_select_language_concurrently(requests)
+        })
+    }
+
+    /// Batches are sent on as a whole, so they stay a single round trip, within one span of the
+    /// function `batch`.
+    fn batch<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CsiRequest>,
+    ) -> Result<Vec<CsiResponse>, CsiError> {
+        let span = open(
+            "batch",
+            requests.len(),
+            requests.iter().filter_map(request_model),
+        );
+        let responses = traced(&span, requests, |requests| inner.try_batch(requests))?;
+        let answers = responses
+            .iter()
+            .filter_map(|response| match response {
+                CsiResponse::Chat(r) => Some((&r.usage, r.finish_reason)),
+                CsiResponse::Complete(c) => Some((&c.usage, c.finish_reason)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if !answers.is_empty() {
+            record_answers(&span, answers);
+        }
+        Ok(responses)
+    }
+}
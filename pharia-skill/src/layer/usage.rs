@@ -0,0 +1,275 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::{CsiLayer, Layered};
+use crate::{
+    ChatEvent, ChatRequest, ChatResponse, Completion, CompletionEvent, CompletionRequest, Csi,
+    CsiError, TokenUsage,
+};
+
+/// A [`Csi`] which adds up the tokens used by its inner implementation.
+pub type UsageTrackingCsi<C> = Layered<UsageTracker, C>;
+
+/// The price of a model, in an arbitrary currency per million tokens.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModelPrice {
+    /// Price per million prompt tokens
+    pub prompt: f64,
+    /// Price per million completion tokens
+    pub completion: f64,
+}
+
+impl ModelPrice {
+    #[must_use]
+    pub fn new(prompt: f64, completion: f64) -> Self {
+        Self { prompt, completion }
+    }
+
+    fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        #[allow(clippy::cast_precision_loss)]
+        let (prompt_tokens, completion_tokens) = (prompt_tokens as f64, completion_tokens as f64);
+        (prompt_tokens * self.prompt + completion_tokens * self.completion) / 1_000_000.0
+    }
+}
+
+/// A limit on the usage, after which further requests fail with
+/// [`CsiError::BudgetExceeded`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget {
+    /// Maximum number of prompt and completion tokens across all models
+    Tokens(u64),
+    /// Maximum cost across all models with a known price
+    Cost(f64),
+}
+
+/// The usage of a single model.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, JsonSchema)]
+pub struct ModelUsage {
+    /// The number of chat and completion requests
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// The cost of the tokens, if the price of the model is known
+    pub cost: Option<f64>,
+}
+
+/// The usage of all models, e.g. to be returned as part of the output of a skill.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, JsonSchema)]
+pub struct UsageReport {
+    /// The usage of each model, by model name
+    pub models: BTreeMap<String, ModelUsage>,
+}
+
+impl UsageReport {
+    /// The number of prompt and completion tokens across all models.
+    #[must_use]
+    pub fn total_tokens(&self) -> u64 {
+        self.models
+            .values()
+            .map(|usage| usage.prompt_tokens + usage.completion_tokens)
+            .sum()
+    }
+
+    /// The cost across all models with a known price.
+    #[must_use]
+    pub fn total_cost(&self) -> f64 {
+        self.models.values().filter_map(|usage| usage.cost).sum()
+    }
+}
+
+/// Layer which adds up the token usage of chat and completion requests per model.
+///
+/// With a price table, the cost of each model is reported as well. With a [`Budget`], requests
+/// are refused once it has been used up. As the usage is only known after a request, the
+/// last request before refusal may overshoot the budget.
+#[derive(Default)]
+pub struct UsageTracker {
+    prices: HashMap<String, ModelPrice>,
+    budget: Option<Budget>,
+    report: Mutex<UsageReport>,
+}
+
+impl UsageTracker {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_price(mut self, model: impl Into<String>, price: ModelPrice) -> Self {
+        self.prices.insert(model.into(), price);
+        self
+    }
+
+    #[must_use]
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// The usage so far.
+    ///
+    /// # Panics
+    /// Will panic if another thread panicked while recording usage.
+    #[must_use]
+    pub fn report(&self) -> UsageReport {
+        self.report.lock().unwrap().clone()
+    }
+
+    fn check_budget(&self) -> Result<(), CsiError> {
+        let report = self.report.lock().unwrap();
+        match self.budget {
+            Some(Budget::Tokens(max)) if report.total_tokens() >= max => Err(
+                CsiError::BudgetExceeded(format!("{max} tokens have been used up")),
+            ),
+            Some(Budget::Cost(max)) if report.total_cost() >= max => Err(CsiError::BudgetExceeded(
+                format!("A cost of {max} has been reached"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Add the usage of a single request.
+    fn record(&self, model: &str, usage: &TokenUsage) {
+        let mut report = self.report.lock().unwrap();
+        let entry = report.models.entry(model.to_owned()).or_default();
+        entry.requests += 1;
+        entry.prompt_tokens += u64::from(usage.prompt);
+        entry.completion_tokens += u64::from(usage.completion);
+        entry.cost = self
+            .prices
+            .get(model)
+            .map(|price| price.cost(entry.prompt_tokens, entry.completion_tokens));
+    }
+}
+
+impl CsiLayer for UsageTracker {
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.check_budget()?;
+        let models = requests
+            .iter()
+            .map(|request| request.model.clone())
+            .collect::<Vec<_>>();
+        let responses = inner.try_chat_concurrently(requests)?;
+        for (model, response) in models.iter().zip(&responses) {
+            self.record(model, &response.usage);
+        }
+        Ok(responses)
+    }
+
+    fn chat_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: ChatRequest,
+    ) -> impl Iterator<Item = Result<ChatEvent, CsiError>> + 'a {
+        if let Err(error) = self.check_budget() {
+            return Box::new(std::iter::once(Err(error))) as Box<dyn Iterator<Item = _>>;
+        }
+        let model = request.model.clone();
+        Box::new(inner.try_chat_stream(request).inspect(move |event| {
+            if let Ok(ChatEvent::Usage { usage }) = event {
+                self.record(&model, usage);
+            }
+        }))
+    }
+
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.check_budget()?;
+        let models = requests
+            .iter()
+            .map(|request| request.model.clone())
+            .collect::<Vec<_>>();
+        let completions = inner.try_complete_concurrently(requests)?;
+        for (model, completion) in models.iter().zip(&completions) {
+            self.record(model, &completion.usage);
+        }
+        Ok(completions)
+    }
+
+    fn completion_stream<'a, C: Csi + ?Sized>(
+        &'a self,
+        inner: &'a C,
+        request: CompletionRequest,
+    ) -> impl Iterator<Item = Result<CompletionEvent, CsiError>> + 'a {
+        if let Err(error) = self.check_budget() {
+            return Box::new(std::iter::once(Err(error))) as Box<dyn Iterator<Item = _>>;
+        }
+        let model = request.model.clone();
+        Box::new(inner.try_completion_stream(request).inspect(move |event| {
+            if let Ok(CompletionEvent::Usage { usage }) = event {
+                self.record(&model, usage);
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_is_added_up_per_model() {
+        let tracker = UsageTracker::new().with_price("expensive", ModelPrice::new(2.0, 4.0));
+
+        tracker.record(
+            "expensive",
+            &TokenUsage {
+                prompt: 1_000_000,
+                completion: 500_000,
+            },
+        );
+        tracker.record(
+            "expensive",
+            &TokenUsage {
+                prompt: 1_000_000,
+                completion: 0,
+            },
+        );
+        tracker.record(
+            "free",
+            &TokenUsage {
+                prompt: 10,
+                completion: 5,
+            },
+        );
+
+        let report = tracker.report();
+        assert_eq!(report.models["expensive"].requests, 2);
+        assert_eq!(report.models["expensive"].cost, Some(6.0));
+        assert_eq!(report.models["free"].cost, None);
+        assert_eq!(report.total_tokens(), 2_500_015);
+        assert!((report.total_cost() - 6.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn budget_refuses_requests_once_used_up() {
+        let tracker = UsageTracker::new().with_budget(Budget::Tokens(10));
+        assert!(tracker.check_budget().is_ok());
+
+        tracker.record(
+            "model",
+            &TokenUsage {
+                prompt: 8,
+                completion: 2,
+            },
+        );
+
+        assert!(matches!(
+            tracker.check_budget(),
+            Err(CsiError::BudgetExceeded(_))
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde_json::Value;
+
+use super::{CsiLayer, Layered};
+use crate::{
+    csi::batch::{group_by_function, send_group},
+    ChatRequest, ChatResponse, Chunk, ChunkRequest, Completion, CompletionRequest, Csi, CsiError,
+    CsiFunction, CsiRequest, CsiResponse, DetokenizeRequest, Document, DocumentPath, Embedding,
+    EmbeddingRequest, LanguageCode, SearchRequest, SearchResult, SelectLanguageRequest,
+    TokenizeRequest,
+};
+
+/// A [`Csi`] which sends large batches in bounded windows.
+pub type WindowedCsi<C> = Layered<Windowing, C>;
+
+/// Layer which splits large batches into windows, so no single call to the inner
+/// implementation carries more than a configured number of requests.
+///
+/// Windows are sent one after the other and their results are concatenated, so the order of
+/// the responses matches the order of the requests. If a window fails, the remaining windows
+/// are not sent. Use [`WindowedCsi::try_interleaved`] to send requests to different functions
+/// in alternating windows.
+pub struct Windowing {
+    default: usize,
+    windows: HashMap<CsiFunction, usize>,
+}
+
+impl Windowing {
+    /// Send at most `size` requests per call to every function.
+    #[must_use]
+    pub fn new(size: usize) -> Self {
+        Self {
+            default: size.max(1),
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Send at most `size` requests per call to `function`, overriding the default size.
+    #[must_use]
+    pub fn with_window(mut self, function: CsiFunction, size: usize) -> Self {
+        self.windows.insert(function, size.max(1));
+        self
+    }
+
+    /// The maximum number of requests sent to `function` in one call.
+    #[must_use]
+    pub fn window(&self, function: CsiFunction) -> usize {
+        self.windows.get(&function).copied().unwrap_or(self.default)
+    }
+
+    /// Send the requests in windows of the size configured for `function`.
+    fn windowed<R, T>(
+        &self,
+        function: CsiFunction,
+        requests: Vec<R>,
+        send: impl Fn(Vec<R>) -> Result<Vec<T>, CsiError>,
+    ) -> Result<Vec<T>, CsiError> {
+        let mut requests = requests.into_iter().peekable();
+        let mut responses = Vec::new();
+        while requests.peek().is_some() {
+            let window = requests.by_ref().take(self.window(function)).collect();
+            responses.extend(send(window)?);
+        }
+        Ok(responses)
+    }
+
+    fn interleaved<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CsiRequest>,
+    ) -> Result<Vec<CsiResponse>, CsiError> {
+        let mut responses = requests.iter().map(|_| None).collect::<Vec<_>>();
+        let mut queues = group_by_function(requests)
+            .into_iter()
+            .map(|(function, group)| {
+                let mut group = group.into_iter().peekable();
+                let mut windows = VecDeque::new();
+                while group.peek().is_some() {
+                    windows.push_back(
+                        group
+                            .by_ref()
+                            .take(self.window(function))
+                            .collect::<Vec<_>>(),
+                    );
+                }
+                (function, windows)
+            })
+            .collect::<Vec<_>>();
+
+        while !queues.is_empty() {
+            for (function, windows) in &mut queues {
+                let Some(window) = windows.pop_front() else {
+                    continue;
+                };
+                let (indices, window): (Vec<_>, Vec<_>) = window.into_iter().unzip();
+                let sent = send_group(inner, *function, window)?;
+                for (index, response) in indices.into_iter().zip(sent) {
+                    responses[index] = Some(response);
+                }
+            }
+            queues.retain(|(_, windows)| !windows.is_empty());
+        }
+
+        responses
+            .into_iter()
+            .map(|response| {
+                response.ok_or_else(|| CsiError::Decode("Fewer responses than requests".to_owned()))
+            })
+            .collect()
+    }
+}
+
+impl CsiLayer for Windowing {
+    fn chunk<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<String>>, CsiError> {
+        self.windowed(CsiFunction::Chunk, requests, |requests| {
+            inner.try_chunk_concurrently(requests)
+        })
+    }
+
+    fn chunk_with_offsets<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChunkRequest>,
+    ) -> Result<Vec<Vec<Chunk>>, CsiError> {
+        self.windowed(CsiFunction::ChunkWithOffsets, requests, |requests| {
+            inner.try_chunk_with_offsets_concurrently(requests)
+        })
+    }
+
+    fn search<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SearchRequest>,
+    ) -> Result<Vec<Vec<SearchResult>>, CsiError> {
+        self.windowed(CsiFunction::Search, requests, |requests| {
+            inner.try_search_concurrently(requests)
+        })
+    }
+
+    fn documents<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Document>, CsiError> {
+        self.windowed(CsiFunction::Documents, paths, |paths| {
+            inner.try_documents(paths)
+        })
+    }
+
+    fn documents_metadata<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        paths: Vec<DocumentPath>,
+    ) -> Result<Vec<Option<Value>>, CsiError> {
+        self.windowed(CsiFunction::DocumentsMetadata, paths, |paths| {
+            inner.try_documents_metadata(paths)
+        })
+    }
+
+    fn chat<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<ChatRequest>,
+    ) -> Result<Vec<ChatResponse>, CsiError> {
+        self.windowed(CsiFunction::Chat, requests, |requests| {
+            inner.try_chat_concurrently(requests)
+        })
+    }
+
+    fn complete<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<CompletionRequest>,
+    ) -> Result<Vec<Completion>, CsiError> {
+        self.windowed(CsiFunction::Complete, requests, |requests| {
+            inner.try_complete_concurrently(requests)
+        })
+    }
+
+    fn embed<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<EmbeddingRequest>,
+    ) -> Result<Vec<Vec<Embedding>>, CsiError> {
+        self.windowed(CsiFunction::Embed, requests, |requests| {
+            inner.try_embed_concurrently(requests)
+        })
+    }
+
+    fn tokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<Vec<u32>>, CsiError> {
+        self.windowed(CsiFunction::Tokenize, requests, |requests| {
+            inner.try_tokenize_concurrently(requests)
+        })
+    }
+
+    fn detokenize<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<DetokenizeRequest>,
+    ) -> Result<Vec<String>, CsiError> {
+        self.windowed(CsiFunction::Detokenize, requests, |requests| {
+            inner.try_detokenize_concurrently(requests)
+        })
+    }
+
+    fn count_tokens<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<TokenizeRequest>,
+    ) -> Result<Vec<u32>, CsiError> {
+        self.windowed(CsiFunction::CountTokens, requests, |requests| {
+            inner.try_count_tokens_concurrently(requests)
+        })
+    }
+
+    fn select_language<C: Csi + ?Sized>(
+        &self,
+        inner: &C,
+        requests: Vec<SelectLanguageRequest>,
+    ) -> Result<Vec<Option<LanguageCode>>, CsiError> {
+        self.windowed(CsiFunction::SelectLanguage, requests, |requests| {
+            inner.try_select_language_concurrently(requests)
+        })
+    }
+}
+
+impl<C: Csi> WindowedCsi<C> {
+    /// Send requests to different functions, alternating between one window of each function
+    /// until all requests are answered. This way a long list of requests to one function does
+    /// not hold back the others. The responses are in the same order as the requests.
+    ///
+    /// # Errors
+    /// Will return an error if any of the windows fails.
+    pub fn try_interleaved(&self, requests: Vec<CsiRequest>) -> Result<Vec<CsiResponse>, CsiError> {
+        self.layer.interleaved(&self.inner, requests)
+    }
+}
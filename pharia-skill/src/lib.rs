@@ -1,21 +1,52 @@
+mod agent;
 /// Pub for macro to work. Internal use only.
 #[doc(hidden)]
 pub mod bindings;
 mod csi;
+mod layer;
+mod run_trace;
+#[cfg(feature = "tracing")]
+mod trace;
+
+pub use agent::{Agent, AgentOutput, Tool, ToolMode};
+#[cfg(feature = "tracing")]
+pub use layer::trace::{TracedCsi, Tracing};
+pub use layer::{
+    batch_by_function,
+    cache::{Cache, CacheStore, CachingCsi, LruStore},
+    fallback::{Answered, Fallback, FallbackCsi, RetryPolicy},
+    run_trace::RunTraceCsi,
+    usage::{Budget, ModelPrice, ModelUsage, UsageReport, UsageTracker, UsageTrackingCsi},
+    window::{WindowedCsi, Windowing},
+    CsiExt, CsiLayer, Layered,
+};
 
 pub use csi::{
-    chunking::{ChunkParams, ChunkRequest},
+    batch::{Batch, BatchHandle, BatchResponses, CsiFunction, CsiRequest, CsiResponse},
+    chunking::{Chunk, ChunkParams, ChunkRequest},
     document_index::{
         Document, DocumentPath, FilterCondition, IndexPath, MetadataFieldValue, MetadataFilter,
         MetadataFilterCondition, Modality, ModalityType, SearchFilter, SearchRequest, SearchResult,
         TextCursor,
     },
+    dynamic::{BoxedStream, DynCsi},
+    embedding::{cosine_similarity, top_k, Embedding, EmbeddingRequest, Representation},
+    error::CsiError,
     inference::{
-        ChatParams, ChatRequest, ChatResponse, Completion, CompletionParams, CompletionRequest,
-        Distribution, FinishReason, Logprob, Logprobs, Message, TokenUsage,
+        ChatEvent, ChatParams, ChatRequest, ChatResponse, Completion, CompletionEvent,
+        CompletionParams, CompletionRequest, Distribution, FinishReason, Logprob, Logprobs,
+        Message, ResponseFormat, TokenUsage, ToolCall, ToolDefinition,
     },
     language::{LanguageCode, SelectLanguageRequest},
+    logging::{Level, LogRecord},
+    structured::StructuredResponse,
+    tokenization::{DetokenizeRequest, TokenizeRequest},
     Csi,
 };
 /// Macro to define a Skill. It wraps a function that takes a single argument and returns a single value.
 pub use pharia_skill_macros::skill;
+/// Macro to define a [`Tool`] from a plain function, to be called by an [`Agent`].
+pub use pharia_skill_macros::tool;
+pub use run_trace::{ActiveStep, CurrentRunTrace, RunTrace, TraceNode, TraceStep};
+#[cfg(feature = "tracing")]
+pub use trace::{CsiLogger, SpanCollector, SpanExporter, SpanRecord};
@@ -0,0 +1,306 @@
+use std::{
+    cell::RefCell,
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One step of a traced skill run, e.g. the run itself, a sub-step or a CSI call.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TraceStep {
+    pub id: u64,
+    /// The step which was active when this one was opened
+    pub parent_id: Option<u64>,
+    pub name: String,
+    pub start: Timestamp,
+    /// Unset while the step is still open
+    pub end: Option<Timestamp>,
+    pub input: Option<Value>,
+    pub output: Option<Value>,
+    pub error: Option<String>,
+}
+
+/// A step together with the steps opened within it.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TraceNode {
+    #[serde(flatten)]
+    pub step: TraceStep,
+    pub children: Vec<TraceNode>,
+}
+
+#[derive(Debug)]
+struct TraceState {
+    trace_id: u128,
+    steps: Vec<TraceStep>,
+    /// Ids of the open steps, the innermost last
+    open: Vec<u64>,
+}
+
+/// Number of traces created so far, to tell apart traces created at the same time.
+static TRACES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static CURRENT: RefCell<Option<RunTrace>> = const { RefCell::new(None) };
+}
+
+/// Records a tree of steps, e.g. to find out which prompts a skill issued in a failing run.
+///
+/// Steps are nested by the order in which they are opened: a step opened while another one is
+/// open becomes its child. Wrap a [`crate::Csi`] in the trace with [`crate::CsiExt::layer`] to
+/// record every CSI call with its requests and responses. Skills defined with
+/// `#[skill(trace)]` are traced automatically, and can add their own sub-steps via
+/// [`RunTrace::current`].
+///
+/// Cloning a trace is cheap, all clones record into the same trace. A disabled trace, as
+/// returned by [`RunTrace::current`] outside of a traced run, records nothing.
+///
+/// Run traces do not depend on the `tracing` feature and are unrelated to the spans of the
+/// `Tracing` layer; they are meant for inspecting single runs, not for ongoing observability.
+#[derive(Clone, Debug)]
+pub struct RunTrace {
+    state: Option<Arc<Mutex<TraceState>>>,
+}
+
+/// Handle to an open step, which is closed when dropped.
+#[must_use = "the step is closed when the handle is dropped"]
+pub struct ActiveStep {
+    trace: RunTrace,
+    id: u64,
+}
+
+/// Restores the previously current trace when dropped.
+#[must_use = "the trace stops being current when the guard is dropped"]
+pub struct CurrentRunTrace {
+    previous: Option<RunTrace>,
+}
+
+impl RunTrace {
+    #[must_use]
+    pub fn new() -> Self {
+        // Only the clock and a counter, as e.g. the process id is not available in components.
+        let start = Timestamp::now().as_nanosecond();
+        let sequence = TRACES.fetch_add(1, Ordering::Relaxed);
+        let mut hasher = DefaultHasher::new();
+        start.hash(&mut hasher);
+        let high = hasher.finish();
+        sequence.hash(&mut hasher);
+        let low = hasher.finish();
+        let trace_id = (u128::from(high) << 64) | u128::from(low);
+        Self {
+            state: Some(Arc::new(Mutex::new(TraceState {
+                trace_id,
+                steps: Vec::new(),
+                open: Vec::new(),
+            }))),
+        }
+    }
+
+    /// A trace which records nothing.
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self { state: None }
+    }
+
+    /// The trace of the current run on this thread, or a disabled one if there is none.
+    #[must_use]
+    pub fn current() -> Self {
+        CURRENT.with(|current| current.borrow().clone().unwrap_or_else(Self::disabled))
+    }
+
+    /// Make this the trace returned by [`RunTrace::current`] on this thread, until the guard is
+    /// dropped.
+    pub fn set_current(&self) -> CurrentRunTrace {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        CurrentRunTrace { previous }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// Identifies all steps of this trace, unique with high probability.
+    #[must_use]
+    pub fn trace_id(&self) -> u128 {
+        self.with_state(|state| state.trace_id).unwrap_or_default()
+    }
+
+    /// Open a step as child of the innermost open step.
+    pub fn step(&self, name: impl Into<String>) -> ActiveStep {
+        let id = self
+            .with_state(|state| {
+                let id = state.steps.len() as u64 + 1;
+                state.steps.push(TraceStep {
+                    id,
+                    parent_id: state.open.last().copied(),
+                    name: name.into(),
+                    start: Timestamp::now(),
+                    end: None,
+                    input: None,
+                    output: None,
+                    error: None,
+                });
+                state.open.push(id);
+                id
+            })
+            .unwrap_or_default();
+        ActiveStep {
+            trace: self.clone(),
+            id,
+        }
+    }
+
+    /// Run `f` within a new step.
+    pub fn in_step<T>(&self, name: impl Into<String>, f: impl FnOnce(&ActiveStep) -> T) -> T {
+        let step = self.step(name);
+        f(&step)
+    }
+
+    /// All steps recorded so far, in the order they were opened.
+    #[must_use]
+    pub fn steps(&self) -> Vec<TraceStep> {
+        self.with_state(|state| state.steps.clone())
+            .unwrap_or_default()
+    }
+
+    /// The recorded steps as a tree, starting with the steps without parent.
+    #[must_use]
+    pub fn tree(&self) -> Vec<TraceNode> {
+        fn children(steps: &[TraceStep], parent_id: Option<u64>) -> Vec<TraceNode> {
+            steps
+                .iter()
+                .filter(|step| step.parent_id == parent_id)
+                .map(|step| TraceNode {
+                    step: step.clone(),
+                    children: children(steps, Some(step.id)),
+                })
+                .collect()
+        }
+        children(&self.steps(), None)
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut TraceState) -> T) -> Option<T> {
+        let state = self.state.as_ref()?;
+        let mut state = state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        Some(f(&mut state))
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut TraceStep)) {
+        self.with_state(|state| {
+            if let Some(step) = id
+                .checked_sub(1)
+                .and_then(|index| state.steps.get_mut(usize::try_from(index).ok()?))
+            {
+                f(step);
+            }
+        });
+    }
+}
+
+impl Default for RunTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ActiveStep {
+    /// Record the input of this step. Values which cannot be serialized are skipped.
+    pub fn set_input(&self, input: &impl Serialize) {
+        let input = serde_json::to_value(input).ok();
+        self.trace.update(self.id, |step| step.input = input);
+    }
+
+    /// Record the output of this step. Values which cannot be serialized are skipped.
+    pub fn set_output(&self, output: &impl Serialize) {
+        let output = serde_json::to_value(output).ok();
+        self.trace.update(self.id, |step| step.output = output);
+    }
+
+    /// Mark this step as failed.
+    pub fn set_error(&self, error: &impl fmt::Display) {
+        let error = error.to_string();
+        self.trace.update(self.id, |step| step.error = Some(error));
+    }
+}
+
+impl Drop for ActiveStep {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.trace.with_state(|state| {
+            state.open.retain(|open| *open != id);
+        });
+        self.trace
+            .update(id, |step| step.end = Some(Timestamp::now()));
+    }
+}
+
+impl Drop for CurrentRunTrace {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_are_nested_by_opening_order() {
+        let trace = RunTrace::new();
+
+        trace.in_step("run", |run| {
+            run.set_input(&"question");
+            trace.in_step("retrieve", |_| {});
+            trace.in_step("answer", |answer| answer.set_output(&"answer"));
+        });
+
+        let tree = trace.tree();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].step.input, Some("question".into()));
+        let children = tree[0]
+            .children
+            .iter()
+            .map(|child| child.step.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(children, ["retrieve", "answer"]);
+        assert!(trace.steps().iter().all(|step| step.end.is_some()));
+    }
+
+    #[test]
+    fn disabled_trace_records_nothing() {
+        let trace = RunTrace::current();
+
+        trace.in_step("run", |run| run.set_output(&1));
+
+        assert!(!trace.is_enabled());
+        assert!(trace.steps().is_empty());
+    }
+
+    #[test]
+    fn traces_have_distinct_ids() {
+        let first = RunTrace::new();
+        let second = RunTrace::new();
+
+        assert_ne!(first.trace_id(), second.trace_id());
+    }
+
+    #[test]
+    fn current_trace_is_restored() {
+        let trace = RunTrace::new();
+        {
+            let _current = trace.set_current();
+            assert_eq!(RunTrace::current().trace_id(), trace.trace_id());
+        }
+        assert!(!RunTrace::current().is_enabled());
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    num::NonZeroU64,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Instant,
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Metadata, Subscriber,
+};
+
+use crate::{Csi, Level, LogRecord};
+
+/// A finished span, as handed to a [`SpanExporter`].
+#[derive(Clone, Debug, Serialize)]
+pub struct SpanRecord {
+    pub name: &'static str,
+    pub target: &'static str,
+    /// All fields recorded on the span, including those recorded after it was opened
+    pub fields: Map<String, Value>,
+    /// Time between opening and closing the span, in milliseconds
+    pub duration_ms: f64,
+}
+
+/// Receives each span collected by a [`SpanCollector`] once it is closed.
+pub trait SpanExporter {
+    fn export(&self, span: SpanRecord);
+}
+
+impl<F: Fn(SpanRecord)> SpanExporter for F {
+    fn export(&self, span: SpanRecord) {
+        self(span);
+    }
+}
+
+/// A minimal [`Subscriber`] which collects the fields and duration of every span and passes
+/// them on to a [`SpanExporter`] once the span is closed. Events are ignored.
+///
+/// Install it with [`tracing::subscriber::set_global_default`], or
+/// [`tracing::subscriber::with_default`] for a single scope, to export the spans of a
+/// [`crate::TracedCsi`].
+pub struct SpanCollector<E> {
+    exporter: E,
+    spans: OpenSpans,
+}
+
+impl<E: SpanExporter> SpanCollector<E> {
+    pub fn new(exporter: E) -> Self {
+        Self {
+            exporter,
+            spans: OpenSpans::default(),
+        }
+    }
+}
+
+/// The spans of a subscriber which have not been closed yet.
+struct OpenSpans {
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, OpenSpan>>,
+}
+
+struct OpenSpan {
+    metadata: &'static Metadata<'static>,
+    fields: Map<String, Value>,
+    start: Instant,
+    /// Number of handles to the span, it is closed once the last one is dropped
+    references: usize,
+}
+
+impl Default for OpenSpans {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl OpenSpans {
+    fn open(&self, attributes: &span::Attributes<'_>) -> span::Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut fields = Map::new();
+        attributes.record(&mut JsonVisitor(&mut fields));
+        self.spans.lock().unwrap().insert(
+            id,
+            OpenSpan {
+                metadata: attributes.metadata(),
+                fields,
+                start: Instant::now(),
+                references: 1,
+            },
+        );
+        span::Id::from_non_zero_u64(NonZeroU64::new(id).expect("ids start at one"))
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        if let Some(open) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut JsonVisitor(&mut open.fields));
+        }
+    }
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        if let Some(open) = self.spans.lock().unwrap().get_mut(&id.into_u64()) {
+            open.references += 1;
+        }
+        id.clone()
+    }
+
+    /// Drop a handle to the span, and return the span once the last handle is gone.
+    fn try_close(&self, id: &span::Id) -> Option<(&'static Metadata<'static>, SpanRecord)> {
+        let mut spans = self.spans.lock().unwrap();
+        let open = spans.get_mut(&id.into_u64())?;
+        open.references -= 1;
+        if open.references > 0 {
+            return None;
+        }
+        let open = spans.remove(&id.into_u64())?;
+        let record = SpanRecord {
+            name: open.metadata.name(),
+            target: open.metadata.target(),
+            fields: open.fields,
+            duration_ms: open.start.elapsed().as_secs_f64() * 1000.0,
+        };
+        Some((open.metadata, record))
+    }
+}
+
+/// Collects recorded values as JSON.
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), format!("{value:?}").into());
+    }
+}
+
+impl<E: SpanExporter + 'static> Subscriber for SpanCollector<E> {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
+        self.spans.open(attributes)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        self.spans.record(span, values);
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        self.spans.clone_span(id)
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        // Export outside of the lock, so the exporter may open spans itself.
+        let Some((_, span)) = self.spans.try_close(&id) else {
+            return false;
+        };
+        self.exporter.export(span);
+        true
+    }
+}
+
+/// A [`Subscriber`] which forwards events as [`LogRecord`]s to [`Csi::log`], so logs emitted with
+/// e.g. [`tracing::info!`] reach the Kernel. Spans, like those of a [`crate::TracedCsi`], are
+/// forwarded once they are closed, with their name as message, their fields and a `duration_ms`
+/// field.
+///
+/// Skills built with the `tracing` feature install it for the Kernel automatically. In tests, it
+/// can be installed for a test double to assert on the logs of a skill.
+pub struct CsiLogger<C> {
+    csi: C,
+    level: Level,
+    spans: OpenSpans,
+}
+
+impl<C: Csi> CsiLogger<C> {
+    /// Forward events of level [`Level::Info`] and above.
+    pub fn new(csi: C) -> Self {
+        Self {
+            csi,
+            level: Level::Info,
+            spans: OpenSpans::default(),
+        }
+    }
+
+    /// Forward events of this level and above.
+    #[must_use]
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl From<&tracing::Level> for Level {
+    fn from(value: &tracing::Level) -> Self {
+        match *value {
+            tracing::Level::TRACE => Self::Trace,
+            tracing::Level::DEBUG => Self::Debug,
+            tracing::Level::INFO => Self::Info,
+            tracing::Level::WARN => Self::Warn,
+            tracing::Level::ERROR => Self::Error,
+        }
+    }
+}
+
+/// Collects the message and the other fields of an event as strings.
+struct RecordVisitor<'a>(&'a mut LogRecord);
+
+impl Visit for RecordVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            value.clone_into(&mut self.0.message);
+        } else {
+            self.0
+                .fields
+                .push((field.name().to_owned(), value.to_owned()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0.message = format!("{value:?}");
+        } else {
+            self.0
+                .fields
+                .push((field.name().to_owned(), format!("{value:?}")));
+        }
+    }
+}
+
+impl<C: Csi + 'static> Subscriber for CsiLogger<C> {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        Level::from(metadata.level()) >= self.level
+    }
+
+    fn new_span(&self, attributes: &span::Attributes<'_>) -> span::Id {
+        self.spans.open(attributes)
+    }
+
+    fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+        self.spans.record(span, values);
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut record = LogRecord::new(event.metadata().level().into(), "");
+        event.record(&mut RecordVisitor(&mut record));
+        self.csi.log(record);
+    }
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+
+    fn clone_span(&self, id: &span::Id) -> span::Id {
+        self.spans.clone_span(id)
+    }
+
+    fn try_close(&self, id: span::Id) -> bool {
+        let Some((metadata, span)) = self.spans.try_close(&id) else {
+            return false;
+        };
+        let mut record = LogRecord::new(metadata.level().into(), span.name);
+        for (key, value) in span.fields {
+            let value = match value {
+                Value::String(value) => value,
+                value => value.to_string(),
+            };
+            record.fields.push((key, value));
+        }
+        record
+            .fields
+            .push(("duration_ms".to_owned(), format!("{:.3}", span.duration_ms)));
+        self.csi.log(record);
+        true
+    }
+}